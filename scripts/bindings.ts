@@ -0,0 +1,98 @@
+// Generates src/bindings from the Rust types of the settings and the IPC messages.
+//
+//   pnpm bindings           write the files
+//   pnpm check:bindings     fail if the files are stale
+//
+// The schemas are written by the `bindings` test of src-tauri, which fails
+// on its own when the committed ones are stale, then compiled into TypeScript.
+import * as path from "path";
+import * as fs from "fs";
+import * as child_process from "child_process";
+import { compile, type SchemaObject } from "./schema_to_ts";
+
+const BINDINGS_DIR = "src/bindings";
+
+const SETTINGS = { schema: "settings.schema.json", output: "settings.ts" };
+const MESSAGES = { schema: "messages.schema.json", output: "messages.ts" };
+
+const BANNER = `/* eslint-disable */
+// Generated by scripts/bindings.ts from src-tauri, don't edit.
+// Run \`pnpm bindings\` after changing the Rust types.`;
+
+function generateSchemas(update: boolean) {
+  const env = update ? { ...process.env, UPDATE_SCHEMAS: "1" } : process.env;
+  child_process.execFileSync(
+    "cargo",
+    [
+      "test",
+      "--quiet",
+      "--manifest-path",
+      "src-tauri/Cargo.toml",
+      "bindings::",
+    ],
+    { stdio: "inherit", env }
+  );
+}
+
+function readSchema(file: string): SchemaObject {
+  return JSON.parse(fs.readFileSync(path.join(BINDINGS_DIR, file), "utf-8"));
+}
+
+function generateBindings(): Map<string, string> {
+  const settings = readSchema(SETTINGS.schema);
+  const messages = readSchema(MESSAGES.schema);
+
+  const result = new Map<string, string>();
+  result.set(SETTINGS.output, compile(settings, { bannerComment: BANNER }));
+  // the settings types are declared once, in settings.ts
+  const settingsTypes = [
+    settings.title ?? "",
+    ...Object.keys(settings.definitions ?? {}),
+  ];
+  result.set(
+    MESSAGES.output,
+    compile(messages, {
+      bannerComment: BANNER,
+      imports: { from: "./settings", names: settingsTypes },
+    })
+  );
+  return result;
+}
+
+function main() {
+  const check = process.argv.includes("--check");
+  generateSchemas(!check);
+  const bindings = generateBindings();
+
+  if (check) {
+    const stale: string[] = [];
+    for (const [file, content] of bindings) {
+      const filePath = path.join(BINDINGS_DIR, file);
+      const existing = fs.existsSync(filePath)
+        ? fs.readFileSync(filePath, "utf-8")
+        : null;
+      if (existing !== content) {
+        stale.push(filePath);
+      }
+    }
+    if (stale.length > 0) {
+      console.error(`stale bindings: ${stale.join(", ")}`);
+      console.error("run `pnpm bindings` and commit the result");
+      process.exit(1);
+    }
+    console.log("bindings are up to date");
+    return;
+  }
+
+  for (const [file, content] of bindings) {
+    fs.writeFileSync(path.join(BINDINGS_DIR, file), content);
+    console.log(`written ${path.join(BINDINGS_DIR, file)}`);
+  }
+}
+
+try {
+  main();
+} catch (err) {
+  console.error(err);
+  process.exit(1);
+}
@@ -0,0 +1,219 @@
+// Tests of the schema compiler, run by `pnpm test`.
+import { test } from "node:test";
+import * as assert from "node:assert/strict";
+import { compile, type SchemaObject } from "./schema_to_ts";
+
+const BANNER = "// banner";
+
+function compileDefinitions(definitions: SchemaObject["definitions"]): string {
+  return compile({ definitions }, { bannerComment: BANNER });
+}
+
+test("objects are interfaces", () => {
+  const output = compileDefinitions({
+    Font: {
+      description: "The font of the terminal.",
+      type: "object",
+      required: ["family"],
+      properties: {
+        family: { description: "The font family.", type: "string" },
+        size: { default: 14, type: "integer" },
+        weight: { type: ["string", "null"] },
+        "line-height": { type: "number" },
+      },
+    },
+  });
+  assert.equal(
+    output,
+    `// banner
+
+/**
+ * The font of the terminal.
+ */
+export interface Font {
+  /**
+   * The font family.
+   */
+  family: string;
+  size: number;
+  weight?: string | null;
+  "line-height"?: number;
+}
+`
+  );
+});
+
+test("the root is declared with its title", () => {
+  const output = compile(
+    {
+      title: "Settings",
+      type: "object",
+      properties: { font: { $ref: "#/definitions/Font" } },
+      definitions: {
+        Font: { type: "string" },
+      },
+    },
+    { bannerComment: BANNER }
+  );
+  assert.equal(
+    output,
+    `// banner
+
+export interface Settings {
+  font?: Font;
+}
+
+export type Font = string;
+`
+  );
+});
+
+test("definitions are sorted", () => {
+  const output = compileDefinitions({
+    B: { type: "string" },
+    A: { type: "boolean" },
+  });
+  assert.equal(
+    output,
+    `// banner
+
+export type A = boolean;
+
+export type B = string;
+`
+  );
+});
+
+test("arrays, maps and options", () => {
+  const output = compileDefinitions({
+    Lists: {
+      type: "object",
+      required: ["names", "optional", "env", "any", "option"],
+      properties: {
+        names: { type: "array", items: { type: "string" } },
+        optional: { type: "array", items: { type: ["string", "null"] } },
+        env: { type: "object", additionalProperties: { type: "string" } },
+        any: { type: "object" },
+        option: { anyOf: [{ $ref: "#/definitions/A" }, { type: "null" }] },
+      },
+    },
+  });
+  assert.equal(
+    output,
+    `// banner
+
+export interface Lists {
+  names: string[];
+  optional: (string | null)[];
+  env: { [key: string]: string };
+  any: { [key: string]: unknown };
+  option: A | null;
+}
+`
+  );
+});
+
+test("unit enums are unions of strings", () => {
+  const output = compileDefinitions({
+    Encoding: { type: "string", enum: ["utf8", "base64"] },
+    Long: {
+      type: "string",
+      enum: ["first-variant", "second-variant", "third-variant", "fourth"],
+    },
+  });
+  assert.equal(
+    output,
+    `// banner
+
+export type Encoding = "utf8" | "base64";
+
+export type Long =
+  | "first-variant"
+  | "second-variant"
+  | "third-variant"
+  | "fourth";
+`
+  );
+});
+
+test("tagged enums have a variant per line", () => {
+  const output = compileDefinitions({
+    Message: {
+      oneOf: [
+        {
+          type: "object",
+          required: ["type"],
+          properties: { type: { type: "string", enum: ["ping"] } },
+        },
+        {
+          type: "object",
+          required: ["type", "id"],
+          properties: {
+            type: { type: "string", enum: ["close"] },
+            id: { type: "string" },
+          },
+        },
+      ],
+    },
+  });
+  assert.equal(
+    output,
+    `// banner
+
+export type Message =
+  | {
+      type: "ping";
+    }
+  | {
+      type: "close";
+      id: string;
+    };
+`
+  );
+});
+
+test("only the used types are imported", () => {
+  const output = compile(
+    {
+      definitions: {
+        Settings: { type: "string" },
+        Update: {
+          type: "object",
+          required: ["settings"],
+          properties: {
+            // Theme is only named in the doc comment
+            settings: {
+              description: "See Theme.",
+              $ref: "#/definitions/Settings",
+            },
+          },
+        },
+      },
+    },
+    {
+      bannerComment: BANNER,
+      imports: { from: "./settings", names: ["Settings", "Theme"] },
+    }
+  );
+  assert.equal(
+    output,
+    `// banner
+
+import type { Settings } from "./settings";
+
+export interface Update {
+  /**
+   * See Theme.
+   */
+  settings: Settings;
+}
+`
+  );
+});
+
+test("unsupported types are an error", () => {
+  assert.throws(
+    () => compileDefinitions({ Bad: { type: "tuple" } }),
+    /unsupported type: tuple/
+  );
+});
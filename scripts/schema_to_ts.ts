@@ -0,0 +1,172 @@
+// Compiles the JSON Schemas written by schemars into TypeScript types.
+// Only what schemars generates for the Rust types is supported:
+// objects, arrays, maps, `Option`, unit enums and tagged enums.
+
+export type Schema = boolean | SchemaObject;
+
+export interface SchemaObject {
+  title?: string;
+  description?: string;
+  default?: unknown;
+  type?: string | string[];
+  enum?: unknown[];
+  $ref?: string;
+  allOf?: Schema[];
+  anyOf?: Schema[];
+  oneOf?: Schema[];
+  properties?: Record<string, Schema>;
+  required?: string[];
+  additionalProperties?: Schema;
+  items?: Schema;
+  definitions?: Record<string, Schema>;
+}
+
+export interface CompileOptions {
+  bannerComment: string;
+  // The types declared in another module, they're imported from it.
+  imports?: { from: string; names: string[] };
+}
+
+const INDENT = "  ";
+const MAX_LINE = 80;
+
+function refName(ref: string): string {
+  return ref.slice(ref.lastIndexOf("/") + 1);
+}
+
+function propertyName(name: string): string {
+  return /^[A-Za-z_$][A-Za-z0-9_$]*$/.test(name) ? name : JSON.stringify(name);
+}
+
+function docComment(description: string | undefined, indent: string): string {
+  if (!description) {
+    return "";
+  }
+  const lines = description.split("\n").map((line) => `${indent} * ${line}`.trimEnd());
+  return `${indent}/**\n${lines.join("\n")}\n${indent} */\n`;
+}
+
+function union(types: string[]): string {
+  const unique = types.filter((type, index) => types.indexOf(type) === index);
+  return unique.join(" | ");
+}
+
+function objectType(schema: SchemaObject, indent: string): string {
+  const properties = Object.entries(schema.properties ?? {});
+  if (properties.length === 0) {
+    const value =
+      schema.additionalProperties === undefined
+        ? "unknown"
+        : toType(schema.additionalProperties, indent);
+    return `{ [key: string]: ${value} }`;
+  }
+  return `{\n${members(schema, indent + INDENT)}${indent}}`;
+}
+
+function members(schema: SchemaObject, indent: string): string {
+  const required = schema.required ?? [];
+  return Object.entries(schema.properties ?? {})
+    .map(([name, property]) => {
+      const doc = typeof property === "object" ? property.description : undefined;
+      // a field with a default is always serialized by the Rust side
+      const hasDefault = typeof property === "object" && "default" in property;
+      const optional = required.includes(name) || hasDefault ? "" : "?";
+      const type = toType(property, indent);
+      return `${docComment(doc, indent)}${indent}${propertyName(name)}${optional}: ${type};\n`;
+    })
+    .join("");
+}
+
+function toType(schema: Schema, indent: string): string {
+  if (typeof schema === "boolean") {
+    return schema ? "unknown" : "never";
+  }
+  if (schema.$ref) {
+    return refName(schema.$ref);
+  }
+  if (schema.enum) {
+    return union(schema.enum.map((value) => JSON.stringify(value)));
+  }
+  const variants = schema.allOf ?? schema.anyOf ?? schema.oneOf;
+  if (variants) {
+    const types = variants.map((variant) => toType(variant, indent));
+    // the inline objects of a tagged enum are on their own line
+    if (types.some((type) => type.startsWith("{"))) {
+      const objectIndent = indent + INDENT + INDENT;
+      return variants
+        .map((variant) => `\n${indent}${INDENT}| ${toType(variant, objectIndent)}`)
+        .join("");
+    }
+    return union(types);
+  }
+  if (schema.type === undefined) {
+    return "unknown";
+  }
+
+  const types = Array.isArray(schema.type) ? schema.type : [schema.type];
+  return union(
+    types.map((type) => {
+      switch (type) {
+        case "string":
+          return "string";
+        case "integer":
+        case "number":
+          return "number";
+        case "boolean":
+          return "boolean";
+        case "null":
+          return "null";
+        case "array": {
+          const item = schema.items === undefined ? "unknown" : toType(schema.items, indent);
+          return item.includes(" ") ? `(${item})[]` : `${item}[]`;
+        }
+        case "object":
+          return objectType(schema, indent);
+        default:
+          throw new Error(`unsupported type: ${type}`);
+      }
+    })
+  );
+}
+
+function declaration(name: string, schema: Schema): string {
+  const doc = typeof schema === "object" ? docComment(schema.description, "") : "";
+  if (typeof schema === "object" && schema.type === "object" && schema.properties) {
+    return `${doc}export interface ${name} {\n${members(schema, INDENT)}}\n`;
+  }
+  const type = toType(schema, "");
+  if (!type.startsWith("\n") && `export type ${name} = ${type};`.length > MAX_LINE) {
+    const lines = type.split(" | ").map((member) => `\n${INDENT}| ${member}`);
+    return `${doc}export type ${name} =${lines.join("")};\n`;
+  }
+  return `${doc}export type ${name} =${type.startsWith("\n") ? "" : " "}${type};\n`;
+}
+
+// The declarations of the root type, if it has a title, and of all the definitions.
+export function compile(root: SchemaObject, options: CompileOptions): string {
+  const imported = options.imports?.names ?? [];
+  const declarations: string[] = [];
+
+  if (root.title) {
+    declarations.push(declaration(root.title, { ...root, definitions: undefined }));
+  }
+  const definitions = Object.entries(root.definitions ?? {}).sort(([a], [b]) =>
+    a < b ? -1 : a > b ? 1 : 0
+  );
+  for (const [name, schema] of definitions) {
+    if (name === root.title || imported.includes(name)) {
+      continue;
+    }
+    declarations.push(declaration(name, schema));
+  }
+
+  let header = `${options.bannerComment}\n\n`;
+  const code = declarations.join("\n").replace(/\/\*\*[\s\S]*?\*\//g, "");
+  const used = imported.filter((name) => new RegExp(`\\b${name}\\b`).test(code));
+  if (options.imports && used.length > 0) {
+    const names = used.join(", ");
+    header += `import type { ${names} } from "${options.imports.from}";\n\n`;
+  }
+
+  return header + declarations.join("\n");
+}
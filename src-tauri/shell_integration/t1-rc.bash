@@ -0,0 +1,61 @@
+# Terminal One shell integration for bash,
+# add `source /path/to/t1-rc.bash` to your ~/.bashrc
+
+if [[ "$TERM_PROGRAM" != "Terminal_One.app" || -n "$T1_SHELL_INTEGRATION" ]]; then
+	return 0 2>/dev/null
+fi
+
+T1_SHELL_INTEGRATION=1
+
+__t1_precmd() {
+	# Must be the first statement to get the exit code of the command.
+	local ret=$?
+
+	# Mark the end of the command output, and the start of the prompt.
+	printf '\e]133;D;%s\a' "$ret"
+	printf '\e]133;A\a'
+
+	# Identify the directory using a "file:" scheme URL, including
+	# the host name to disambiguate local vs. remote paths.
+
+	# Percent-encode the pathname.
+	# Use LC_CTYPE=C to process text byte-by-byte and
+	# LC_COLLATE=C to compare byte-for-byte. Ensure that
+	# LC_ALL and LANG are not set so they don't interfere.
+	local url_path='' i ch hexch LC_CTYPE=C LC_COLLATE=C LC_ALL= LANG=
+	for ((i = 0; i < ${#PWD}; ++i)); do
+		ch="${PWD:i:1}"
+		if [[ "$ch" =~ [/._~A-Za-z0-9-] ]]; then
+			url_path+="$ch"
+		else
+			printf -v hexch "%02X" "'$ch"
+			url_path+="%$hexch"
+		fi
+	done
+
+	printf '\e]7;%s\a' "file://$HOSTNAME$url_path"
+
+	# Mark the end of the prompt, where the command starts. Checked
+	# on every prompt, PS1 may be set after this file is sourced.
+	if [[ "$PS1" != *"133;B"* ]]; then
+		PS1+=$'\[\e]133;B\a\]'
+	fi
+}
+
+# First, to get the exit code of the command. Bash 5.1 and later
+# run each element of PROMPT_COMMAND if it's an array.
+if [[ "$(declare -p PROMPT_COMMAND 2>/dev/null)" == "declare -a"* ]]; then
+	if [[ " ${PROMPT_COMMAND[*]} " != *" __t1_precmd "* ]]; then
+		PROMPT_COMMAND=(__t1_precmd "${PROMPT_COMMAND[@]}")
+	fi
+elif [[ -z "$PROMPT_COMMAND" ]]; then
+	PROMPT_COMMAND="__t1_precmd"
+elif [[ "$PROMPT_COMMAND" != *__t1_precmd* ]]; then
+	PROMPT_COMMAND="__t1_precmd;$PROMPT_COMMAND"
+fi
+
+# Mark the start of the command output, PS0 is printed
+# after a command is read and before it is executed.
+if [[ "$PS0" != *"133;C"* ]]; then
+	PS0=$'\e]133;C\a'"$PS0"
+fi
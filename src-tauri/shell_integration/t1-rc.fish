@@ -0,0 +1,33 @@
+# Terminal One shell integration for fish,
+# add `source /path/to/t1-rc.fish` to your ~/.config/fish/config.fish
+
+if test "$TERM_PROGRAM" = "Terminal_One.app"; and not set -q T1_SHELL_INTEGRATION
+	set -g T1_SHELL_INTEGRATION 1
+
+	function __t1_precmd --on-event fish_prompt
+		# Mark the end of the command output, and the start of the prompt.
+		printf '\e]133;D;%s\a' $status
+		printf '\e]133;A\a'
+
+		# Identify the directory using a "file:" scheme URL, including
+		# the host name to disambiguate local vs. remote paths.
+		set -l url_path (string escape --style=url -- $PWD)
+		printf '\e]7;%s\a' "file://$hostname$url_path"
+
+		# Mark the end of the prompt, where the command starts. fish_prompt
+		# is wrapped before it's printed, it may be defined after this file.
+		if functions -q fish_prompt; and not functions fish_prompt | string match -q '*__t1_fish_prompt*'
+			functions --erase __t1_fish_prompt
+			functions --copy fish_prompt __t1_fish_prompt
+			function fish_prompt
+				__t1_fish_prompt
+				printf '\e]133;B\a'
+			end
+		end
+	end
+
+	function __t1_preexec --on-event fish_preexec
+		# Mark the start of the command output.
+		printf '\e]133;C\a'
+	end
+end
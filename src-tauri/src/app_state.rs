@@ -1,8 +1,17 @@
-use crate::messages::ThemeResponse;
-use crate::terminal_delegate::{TerminalDelegate, TerminalDelegateEventHandler};
-use crate::theme_context::{ThemeContext, ThemeItem};
+use crate::asciicast::{read_cast, CastPlayer, CastPlayerEventHandler};
+use crate::messages::{
+    CanCloseResponse, PlaybackInfo, ProjectSettingsChangedMessage, ProjectTrustRequestMessage,
+    RunningProcess, SavedSession, ShellOptions, ThemeResponse,
+};
+use crate::project_settings::{apply_project_file, find_project_file, load_trust, save_trust, ProjectFile};
+use crate::shell::ShellCommand;
+use crate::terminal_delegate::{PtyBackend, TerminalDelegate, TerminalDelegateEventHandler};
+use crate::theme_context::{
+    watch_themes, ThemeContext, ThemeError, ThemeItem, ThemeWatcherEventHandler,
+};
 use crate::{Error, Result};
 use log::{info, debug, warn};
+use notify_debouncer_mini::{notify::RecommendedWatcher, Debouncer};
 use polodb_core::mac_proxy_settings;
 use serde_json::Value;
 use tauri::Wry;
@@ -10,7 +19,8 @@ use tauri::updater::UpdateResponse;
 use std::collections::{HashMap, BTreeMap};
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use crate::settings::Settings;
+use std::time::Duration;
+use crate::settings::{watch_settings, Settings, SettingsError, SettingsWatcherEventHandler};
 
 #[derive(Clone)]
 pub(crate) struct AppState {
@@ -30,8 +40,47 @@ impl AppState {
         &self,
         id: String,
         path: Option<String>,
+        profile: Option<String>,
+        shell_options: Option<ShellOptions>,
+        restore: Option<String>,
         event_handler: Box<dyn TerminalDelegateEventHandler + Send>,
     ) -> Result<TerminalDelegate> {
+        let settings = self.settings();
+
+        // Respawn a tab of the last session in its directory,
+        // with the scrollback above a marker.
+        // With the session daemon, the shell may be still running,
+        // it's reattached then.
+        let restored = restore.and_then(|session_id| self.take_restorable_session(&session_id));
+        let attach = restored.as_ref().map(|session| session.id.clone());
+        let (path, profile, preamble) = match restored {
+            Some(session) => (
+                path.or(session.cwd),
+                profile.or(session.profile),
+                Some(restored_preamble(session.scrollback.as_deref())),
+            ),
+            None => (path, profile, None),
+        };
+        let backend = match self.daemon_socket() {
+            Some(socket) => PtyBackend::Daemon { socket, attach },
+            None => PtyBackend::Local,
+        };
+
+        let profile = match profile.as_ref() {
+            Some(name) => Some(
+                settings
+                    .find_profile(name)
+                    .ok_or_else(|| Error::ProfileNotFound(name.clone()))?,
+            ),
+            None => None,
+        };
+        // An explicit path, e.g. "open in new tab", wins over the profile.
+        let path = path.or_else(|| profile.and_then(|profile| profile.expanded_cwd()));
+        // a trusted project file where the terminal starts
+        let terminal_settings = match path.as_ref() {
+            Some(path) => self.project_settings_at(Path::new(path), &settings).terminal,
+            None => settings.terminal.clone(),
+        };
         let envs = {
             let inner = self.inner.lock().unwrap();
             inner.preserved_envs.clone()
@@ -40,27 +89,101 @@ impl AppState {
             let inner = self.inner.lock().unwrap();
             inner.shell_path.clone()
         };
+        let shell = {
+            let mut shell = ShellCommand::from_settings(&terminal_settings);
+            if let Some(profile) = profile {
+                shell.apply_profile(profile);
+            }
+            if let Some(options) = shell_options.as_ref() {
+                shell.apply_options(options);
+            }
+            shell
+        };
+        debug!("new terminal {} with shell: {:?}", id, shell);
         // The new operation is slow.
         // So we don't want to obtain the lock when creating a new terminal.
         let delegate: TerminalDelegate = TerminalDelegate::new(
             id,
             path,
             shell_path,
+            shell,
             envs,
+            terminal_settings.scrollback.0 as usize,
+            preamble,
+            backend,
+            Duration::from_millis(terminal_settings.kill_grace_period.0),
             event_handler,
         )?;
+        delegate.set_exit_action(
+            profile
+                .and_then(|profile| profile.on_exit)
+                .unwrap_or(terminal_settings.on_exit),
+        );
+        delegate.set_profile(profile.map(|profile| profile.name.clone()));
         {
             let mut inner = self.inner.lock().unwrap();
             inner.insert_terminal(delegate.clone());
         }
+        self.save_daemon_sessions();
         Ok(delegate)
     }
 
+    /// Start the shell of an exited terminal again, in its last cwd.
+    pub(crate) fn restart_terminal(
+        &self,
+        id: &str,
+        event_handler: Box<dyn TerminalDelegateEventHandler + Send>,
+    ) -> Result<TerminalDelegate> {
+        let previous = self
+            .find_terminal_by_id(id)
+            .ok_or_else(|| Error::TerminalNotFound(id.to_string()))?;
+        previous.close();
+        self.new_terminal(
+            id.to_string(),
+            previous.cwd(),
+            previous.profile(),
+            None,
+            None,
+            event_handler,
+        )
+    }
+
+    /// Run the PTYs in the session daemon listening on `socket`,
+    /// it's started if it's not running.
+    pub(crate) fn enable_session_daemon(&self, socket: PathBuf) -> Result<()> {
+        let kill_grace_period = Duration::from_millis(self.settings().terminal.kill_grace_period.0);
+        crate::daemon_client::ensure_daemon(&socket, kill_grace_period)?;
+        let mut inner = self.inner.lock().unwrap();
+        inner.daemon_socket = Some(socket);
+        Ok(())
+    }
+
+    fn daemon_socket(&self) -> Option<PathBuf> {
+        let inner = self.inner.lock().unwrap();
+        inner.daemon_socket.clone()
+    }
+
+    /// The shells in the daemon survive a crash of the app,
+    /// keep the saved sessions up to date so they can be reattached.
+    fn save_daemon_sessions(&self) {
+        if self.daemon_socket().is_none() {
+            return;
+        }
+        if let Err(err) = self.save_sessions() {
+            warn!("save sessions error: {}", err);
+        }
+    }
+
     pub(crate) fn get_terminal_by_id(&self, id: &str) -> TerminalDelegate {
         let inner = self.inner.lock().unwrap();
         inner.terminals.get(id).unwrap().clone()
     }
 
+    pub(crate) fn find_terminal_by_id(&self, id: &str) -> Option<TerminalDelegate> {
+        let inner = self.inner.lock().unwrap();
+        inner.terminals.get(id).cloned()
+    }
+
     pub(crate) fn remove_terminal_by_id(&self, id: &str) {
         let mut inner = self.inner.lock().unwrap();
         {
@@ -68,16 +191,377 @@ impl AppState {
             terminal.close();
         }
         inner.terminals.remove(id);
+        inner.terminal_order.retain(|terminal_id| terminal_id != id);
+        inner.terminal_projects.remove(id);
+        // ask again for what's running in the other tabs
+        inner.close_confirmed = false;
+        drop(inner);
+        self.save_daemon_sessions();
     }
 
-    pub(crate) fn load_themes(&self, dir: &Path) -> Result<()> {
+    /// Apply the project file above the new cwd of a terminal,
+    /// if it's another one than the last time.
+    pub(crate) fn update_terminal_project(&self, id: &str, cwd: &str) -> Result<Option<ProjectChange>> {
+        let file = find_project_file(Path::new(cwd));
+        {
+            let mut inner = self.inner.lock().unwrap();
+            let previous = inner.terminal_projects.get(id).cloned().flatten();
+            if previous == file {
+                return Ok(None);
+            }
+            inner.terminal_projects.insert(id.to_string(), file.clone());
+        }
+        self.resolve_terminal_project(id, file).map(Some)
+    }
+
+    /// Record the decision of the user about `content`, the content they were shown,
+    /// and apply it to the terminals in the project. They're asked again
+    /// if the file has changed in the meantime.
+    pub(crate) fn trust_project(
+        &self,
+        path: &str,
+        content: &str,
+        trusted: bool,
+    ) -> Result<Vec<ProjectChange>> {
+        let path = PathBuf::from(path);
+        let file = ProjectFile {
+            content: std::fs::read_to_string(&path)?,
+            path,
+        };
+        if file.content != content {
+            warn!("project file changed before it's trusted: {:?}", file.path);
+        }
+        let affected: Vec<String> = {
+            let inner = self.inner.lock().unwrap();
+            if let Some(db) = inner.database.as_ref() {
+                let seen = ProjectFile {
+                    path: file.path.clone(),
+                    content: content.to_string(),
+                };
+                save_trust(db, &seen, trusted)?;
+            }
+            inner
+                .terminal_projects
+                .iter()
+                .filter(|(_, project)| {
+                    project
+                        .as_ref()
+                        .map_or(false, |project| project.path == file.path)
+                })
+                .map(|(id, _)| id.clone())
+                .collect()
+        };
+        info!("trust project {:?}: {}", file.path, trusted);
+
+        let mut changes = Vec::new();
+        for id in affected {
+            changes.push(self.resolve_terminal_project(&id, Some(file.clone()))?);
+        }
+        Ok(changes)
+    }
+
+    fn resolve_terminal_project(&self, id: &str, file: Option<ProjectFile>) -> Result<ProjectChange> {
+        let settings = self.settings();
+        let mut change = ProjectSettingsChangedMessage {
+            id: id.to_string(),
+            path: None,
+            settings: settings.as_ref().clone(),
+            ignored_keys: Vec::new(),
+            error: None,
+        };
+
+        if let Some(file) = file {
+            match self.project_trust(&file)? {
+                None => {
+                    return Ok(ProjectChange::TrustRequested(ProjectTrustRequestMessage {
+                        id: id.to_string(),
+                        path: file.path.to_string_lossy().to_string(),
+                        content: file.content,
+                    }));
+                }
+                Some(false) => (),
+                Some(true) => match apply_project_file(&settings, &file) {
+                    Ok((project_settings, ignored_keys)) => {
+                        change.path = Some(file.path.to_string_lossy().to_string());
+                        change.settings = project_settings;
+                        change.ignored_keys = ignored_keys;
+                    }
+                    Err(err) => {
+                        warn!("invalid project settings: {}, {:?}", err, file.path);
+                        change.error = Some(format!("{}: {}", file.path.to_string_lossy(), err));
+                    }
+                },
+            }
+        }
+
+        // what the shell does on exit, unless its profile says otherwise
+        if let Some(terminal) = self.find_terminal_by_id(id) {
+            let profile_on_exit = terminal
+                .profile()
+                .and_then(|name| settings.find_profile(&name).and_then(|profile| profile.on_exit));
+            terminal.set_exit_action(profile_on_exit.unwrap_or(change.settings.terminal.on_exit));
+        }
+
+        Ok(ProjectChange::Changed(change))
+    }
+
+    fn project_trust(&self, file: &ProjectFile) -> Result<Option<bool>> {
+        let inner = self.inner.lock().unwrap();
+        match inner.database.as_ref() {
+            Some(db) => load_trust(db, file),
+            None => Ok(None),
+        }
+    }
+
+    /// The settings with the project file above `path` if it's trusted.
+    fn project_settings_at(&self, path: &Path, settings: &Settings) -> Settings {
+        let file = match find_project_file(path) {
+            Some(file) => file,
+            None => return settings.clone(),
+        };
+        match self.project_trust(&file) {
+            Ok(Some(true)) => match apply_project_file(settings, &file) {
+                Ok((project_settings, _)) => project_settings,
+                Err(err) => {
+                    warn!("invalid project settings: {}, {:?}", err, file.path);
+                    settings.clone()
+                }
+            },
+            _ => settings.clone(),
+        }
+    }
+
+    /// The id and the pid of the shell of every running terminal.
+    pub(crate) fn terminal_pids(&self) -> Vec<(String, u32)> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .terminals
+            .iter()
+            .filter_map(|(id, terminal)| terminal.process_id().map(|pid| (id.clone(), pid)))
+            .collect()
+    }
+
+    /// Whether the terminal `id`, or all the terminals, can be closed
+    /// without killing what the user is running.
+    pub(crate) fn can_close(&self, id: Option<&str>) -> CanCloseResponse {
+        let terminals: Vec<TerminalDelegate> = {
+            let inner = self.inner.lock().unwrap();
+            match id {
+                Some(id) => inner.terminals.get(id).cloned().into_iter().collect(),
+                None => inner.terminals.values().cloned().collect(),
+            }
+        };
+        let settings = self.settings();
+        let allowlist = &settings.terminal.confirm_close_allowlist;
+
+        // Only the foreground job, the background jobs and the daemons
+        // started from the shell are not what the user is looking at.
+        let mut counts: BTreeMap<String, u32> = BTreeMap::new();
+        for terminal in terminals {
+            let process = match terminal.fetch_foreground() {
+                Some(process) if !process.is_shell => process,
+                _ => continue,
+            };
+            if allowlist.contains(&process.name) {
+                continue;
+            }
+            *counts.entry(process.name).or_default() += 1;
+        }
+
+        let processes: Vec<RunningProcess> = counts
+            .into_iter()
+            .map(|(name, count)| RunningProcess { name, count })
+            .collect();
+
+        CanCloseResponse {
+            can_close: processes.is_empty(),
+            message: running_processes_message(&processes),
+            processes,
+        }
+    }
+
+    /// The user has confirmed to close the window with running processes.
+    pub(crate) fn confirm_close(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.close_confirmed = true;
+    }
+
+    pub(crate) fn is_close_confirmed(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.close_confirmed
+    }
+
+    /// Replay the recording at `path` in the read-only tab `id`.
+    pub(crate) fn start_playback(
+        &self,
+        id: String,
+        path: &Path,
+        event_handler: Box<dyn CastPlayerEventHandler + Send>,
+    ) -> Result<PlaybackInfo> {
+        let (header, events) = read_cast(path)?;
+        let info = PlaybackInfo {
+            cols: header.width,
+            rows: header.height,
+            duration: events.last().map_or(0.0, |event| event.0),
+        };
+
+        let player = CastPlayer::start(id.clone(), events, event_handler);
+        let mut inner = self.inner.lock().unwrap();
+        if let Some(previous) = inner.playbacks.insert(id, player) {
+            previous.stop();
+        }
+
+        Ok(info)
+    }
+
+    pub(crate) fn get_playback_by_id(&self, id: &str) -> Result<CastPlayer> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .playbacks
+            .get(id)
+            .cloned()
+            .ok_or_else(|| Error::PlaybackNotFound(id.to_string()))
+    }
+
+    pub(crate) fn remove_playback_by_id(&self, id: &str) {
         let mut inner = self.inner.lock().unwrap();
-        inner.load_themes(dir)
+        if let Some(player) = inner.playbacks.remove(id) {
+            player.stop();
+        }
     }
 
-    pub(crate) fn get_a_theme(&self) -> Result<ThemeResponse> {
+    /// Close the connections to the daemon and leave the shells running,
+    /// they are reattached on next launch.
+    pub(crate) fn detach_terminals(&self) {
         let inner = self.inner.lock().unwrap();
-        inner.get_a_theme()
+        for terminal in inner.terminals.values() {
+            terminal.detach();
+        }
+    }
+
+    /// Save the open terminals, so they can be restored on next launch.
+    pub(crate) fn save_sessions(&self) -> Result<()> {
+        let terminals: Vec<TerminalDelegate> = {
+            let inner = self.inner.lock().unwrap();
+            inner
+                .terminal_order
+                .iter()
+                .filter_map(|id| inner.terminals.get(id).cloned())
+                .collect()
+        };
+
+        let mut sessions: Vec<SavedSession> = terminals
+            .iter()
+            .map(|terminal| SavedSession {
+                id: terminal.session_id(),
+                profile: terminal.profile(),
+                cwd: terminal.cwd(),
+                scrollback: Some(terminal.snapshot(MAX_SNAPSHOT_LINES)),
+            })
+            .collect();
+
+        let inner = self.inner.lock().unwrap();
+        // not reattached yet, they're kept until the user decides
+        sessions.extend(inner.restorable_sessions.iter().cloned());
+
+        let db_opt = inner.database.as_ref();
+        if db_opt.is_none() {
+            warn!("db is none");
+            return Ok(());
+        }
+        let db = db_opt.unwrap();
+
+        crate::session_store::save_sessions(db, &sessions)?;
+
+        info!("saved {} sessions", sessions.len());
+
+        Ok(())
+    }
+
+    pub(crate) fn restorable_sessions(&self) -> Vec<SavedSession> {
+        let inner = self.inner.lock().unwrap();
+        inner.restorable_sessions.clone()
+    }
+
+    fn take_restorable_session(&self, id: &str) -> Option<SavedSession> {
+        let mut inner = self.inner.lock().unwrap();
+        let index = inner
+            .restorable_sessions
+            .iter()
+            .position(|session| session.id == id)?;
+        Some(inner.restorable_sessions.remove(index))
+    }
+
+    pub(crate) fn discard_restorable_sessions(&self) {
+        let (sessions, daemon_socket) = {
+            let mut inner = self.inner.lock().unwrap();
+            let sessions = std::mem::take(&mut inner.restorable_sessions);
+            (sessions, inner.daemon_socket.clone())
+        };
+        let kill_grace_period = Duration::from_millis(self.settings().terminal.kill_grace_period.0);
+
+        // Don't leave the shells of the discarded sessions running.
+        if let Some(socket) = daemon_socket {
+            for session in sessions {
+                if let Err(err) = crate::daemon_client::kill_session(&socket, &session.id, kill_grace_period) {
+                    debug!("kill session {} error: {}", session.id, err);
+                }
+            }
+        }
+
+        // only the open terminals are left in the database
+        if let Err(err) = self.save_sessions() {
+            warn!("save sessions error: {}", err);
+        }
+    }
+
+    /// The invalid theme files are skipped, see `theme_errors`.
+    pub(crate) fn load_themes(&self, dirs: &[PathBuf]) {
+        let (theme_context, errors) = ThemeContext::load(dirs);
+        self.set_themes(theme_context, errors);
+    }
+
+    pub(crate) fn set_themes(&self, theme_context: ThemeContext, errors: Vec<ThemeError>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.theme_context = Some(theme_context);
+        inner.theme_errors = errors;
+    }
+
+    pub(crate) fn theme_errors(&self) -> Vec<ThemeError> {
+        let inner = self.inner.lock().unwrap();
+        inner.theme_errors.clone()
+    }
+
+    /// Watch the theme directories for as long as the app runs.
+    pub(crate) fn watch_themes(
+        &self,
+        dirs: Vec<PathBuf>,
+        event_handler: Box<dyn ThemeWatcherEventHandler + Send>,
+    ) -> Result<()> {
+        let watcher = watch_themes(dirs, event_handler)?;
+        let mut inner = self.inner.lock().unwrap();
+        inner.theme_watcher = Some(watcher);
+        Ok(())
+    }
+
+    /// The names of the themes which can be set.
+    pub(crate) fn list_themes(&self) -> Vec<String> {
+        let inner = self.inner.lock().unwrap();
+        inner
+            .theme_context
+            .as_ref()
+            .map_or_else(Vec::new, |theme_context| theme_context.list_themes())
+    }
+
+    pub(crate) fn get_theme(&self, name: &str) -> Result<ThemeResponse> {
+        let inner = self.inner.lock().unwrap();
+        inner.get_theme(name)
+    }
+
+    /// The theme of `app.theme`, the default one if it's not set.
+    pub(crate) fn current_theme(&self) -> Result<ThemeResponse> {
+        let inner = self.inner.lock().unwrap();
+        inner.current_theme()
     }
 
     pub(crate) fn init_db(&self, data_path: &Path) -> Result<()> {
@@ -214,19 +698,113 @@ impl AppState {
         inner.settings.clone()
     }
 
+    /// The terminals opened from now on use the new settings.
+    pub(crate) fn set_settings(&self, settings: Settings) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.settings = Arc::new(settings);
+        inner.settings_error = None;
+    }
+
+    /// Why the settings in use are not the ones in `settings.toml`.
+    pub(crate) fn settings_error(&self) -> Option<SettingsError> {
+        let inner = self.inner.lock().unwrap();
+        inner.settings_error.clone()
+    }
+
+    pub(crate) fn set_settings_error(&self, error: Option<SettingsError>) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.settings_error = error;
+    }
+
+    /// Watch `settings.toml` for as long as the app runs.
+    pub(crate) fn watch_settings(
+        &self,
+        app_dir: &Path,
+        event_handler: Box<dyn SettingsWatcherEventHandler + Send>,
+    ) -> Result<()> {
+        let watcher = watch_settings(app_dir, event_handler)?;
+        let mut inner = self.inner.lock().unwrap();
+        inner.settings_watcher = Some(watcher);
+        Ok(())
+    }
+
 }
 
 struct AppStateInner {
     shell_path: PathBuf,
     settings: Arc<Settings>,
+    settings_error: Option<SettingsError>,
+    settings_watcher: Option<Debouncer<RecommendedWatcher>>,
     terminals: HashMap<String, TerminalDelegate>,
     preserved_envs: BTreeMap<String, Option<String>>,
     theme_context: Option<ThemeContext>,
+    /// The theme files skipped by the last load.
+    theme_errors: Vec<ThemeError>,
+    theme_watcher: Option<Debouncer<RecommendedWatcher>>,
     database: Option<rusqlite::Connection>,
     update: Option<UpdateResponse<Wry>>,
+    /// The order the terminals are opened in.
+    terminal_order: Vec<String>,
+    /// The sessions saved when the app quit last time, not restored yet.
+    restorable_sessions: Vec<SavedSession>,
+    /// Set when the PTYs are owned by the session daemon.
+    daemon_socket: Option<PathBuf>,
+    /// The read-only tabs replaying a recording.
+    playbacks: HashMap<String, CastPlayer>,
+    close_confirmed: bool,
+    /// The project file above the cwd of each terminal.
+    terminal_projects: HashMap<String, Option<ProjectFile>>,
+}
+
+/// What the new cwd of a terminal means for its settings.
+pub(crate) enum ProjectChange {
+    Changed(ProjectSettingsChangedMessage),
+    TrustRequested(ProjectTrustRequestMessage),
+}
+
+const MAX_SNAPSHOT_LINES: usize = 1000;
+
+const SESSION_RESTORED_MARKER: &str = "\x1b[2m--- session restored ---\x1b[0m";
+
+fn restored_preamble(scrollback: Option<&str>) -> Vec<u8> {
+    let mut result = String::new();
+    if let Some(scrollback) = scrollback {
+        if !scrollback.is_empty() {
+            result += &scrollback.replace('\n', "\r\n");
+            result += "\r\n";
+        }
+    }
+    result += SESSION_RESTORED_MARKER;
+    result += "\r\n";
+    result.into_bytes()
 }
 
 
+/// "vim and cargo are still running", "node (2) is still running".
+fn running_processes_message(processes: &[RunningProcess]) -> Option<String> {
+    let names: Vec<String> = processes
+        .iter()
+        .map(|process| {
+            if process.count > 1 {
+                format!("{} ({})", process.name, process.count)
+            } else {
+                process.name.clone()
+            }
+        })
+        .collect();
+
+    let (last, rest) = names.split_last()?;
+    let is_plural = !rest.is_empty() || processes[0].count > 1;
+    let subject = if rest.is_empty() {
+        last.clone()
+    } else {
+        format!("{} and {}", rest.join(", "), last)
+    };
+    let verb = if is_plural { "are" } else { "is" };
+
+    Some(format!("{} {} still running", subject, verb))
+}
+
 fn get_preserved_envs() -> BTreeMap<String, Option<String>> {
     let preserved_envs_keys: Vec<&str> = vec![
         "HTTP_PROXY",
@@ -289,11 +867,21 @@ impl AppStateInner {
         let result = AppStateInner {
             shell_path,
             settings,
+            settings_error: None,
+            settings_watcher: None,
             terminals: HashMap::new(),
             preserved_envs,
             theme_context: None,
+            theme_errors: Vec::new(),
+            theme_watcher: None,
             database: None,
             update: None,
+            terminal_order: Vec::new(),
+            restorable_sessions: Vec::new(),
+            daemon_socket: None,
+            playbacks: HashMap::new(),
+            close_confirmed: false,
+            terminal_projects: HashMap::new(),
         };
 
         result.init_proxy();
@@ -325,6 +913,11 @@ impl AppStateInner {
 
         let database = crate::database::open_database(data_path_buf.as_path())?;
 
+        // Kept in the database until the user decides, in case the app
+        // quits before, they are saved again when it quits.
+        self.restorable_sessions = crate::session_store::load_sessions(&database)?;
+        debug!("restorable sessions: {}", self.restorable_sessions.len());
+
         self.database = Some(database);
 
         Ok(())
@@ -332,52 +925,31 @@ impl AppStateInner {
 
     fn insert_terminal(&mut self, terminal: TerminalDelegate) {
         let id = terminal.id();
+        // a restarted terminal keeps its place
+        if !self.terminal_order.contains(&id) {
+            self.terminal_order.push(id.clone());
+        }
         self.terminals.insert(id, terminal);
     }
 
-    fn load_themes(&mut self, path: &Path) -> Result<()> {
-        let mut theme_context = ThemeContext::new();
-
-        debug!("Load themes from {:?}", path);
-
-        // check if path is a dir
-        if !path.is_dir() {
-            return Err(Error::NoThemesFound);
-        }
-
-        // list all child folder under path
-        for entry in path.read_dir()? {
-            let entry = entry?;
-            debug!("Found {:?}", entry.path());
-
-            let theme_item_opt = ThemeItem::load_from_file(entry.path().as_path())?;
-            if let Some(theme_item) = theme_item_opt {
-                theme_context.add(theme_item);
-            }
-        }
-
-        if theme_context.is_empty() {
-            return Err(Error::NoThemesFound);
-        }
-
-        self.theme_context = Some(theme_context);
-        Ok(())
+    fn theme_context(&self) -> Result<&ThemeContext> {
+        self.theme_context.as_ref().ok_or(Error::NoThemesFound)
     }
 
-    pub(crate) fn get_a_theme(&self) -> Result<ThemeResponse> {
-        let theme_context = self.theme_context.as_ref().unwrap();
-        let first = &theme_context[0];
-
-        let content = if let Some(toml_path) = &first.toml_file_path {
-            std::fs::read_to_string(toml_path)?
-        } else {
-            unreachable!()
-        };
+    pub(crate) fn get_theme(&self, name: &str) -> Result<ThemeResponse> {
+        let item = self
+            .theme_context()?
+            .get_theme(name)
+            .ok_or_else(|| Error::ThemeNotFound(name.to_string()))?;
+        read_theme(item)
+    }
 
-        Ok(ThemeResponse {
-            name: first.name.clone(),
-            json_content: Some(toml_str_to_json_str(&content)?),
-        })
+    pub(crate) fn current_theme(&self) -> Result<ThemeResponse> {
+        let item = self
+            .theme_context()?
+            .get_theme_or_default(self.settings.app.theme.as_deref())
+            .ok_or(Error::NoThemesFound)?;
+        read_theme(item)
     }
 
     pub(crate) fn set_update(&mut self, update: UpdateResponse<Wry>) {
@@ -385,6 +957,18 @@ impl AppStateInner {
     }
 }
 
+fn read_theme(item: &ThemeItem) -> Result<ThemeResponse> {
+    let json_content = match &item.toml_file_path {
+        Some(toml_path) => Some(toml_str_to_json_str(&std::fs::read_to_string(toml_path)?)?),
+        None => None,
+    };
+
+    Ok(ThemeResponse {
+        name: item.name.clone(),
+        json_content,
+    })
+}
+
 fn toml_str_to_json_str(toml: &str) -> Result<String> {
     let value = toml::from_str::<toml::Value>(toml)?;
     let json = serde_json::to_string(&value)?;
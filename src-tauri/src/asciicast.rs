@@ -0,0 +1,369 @@
+use crate::Result;
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+const CAST_VERSION: u32 = 2;
+
+const MIN_SPEED: f64 = 0.1;
+const MAX_SPEED: f64 = 16.0;
+
+/// The first line of an asciicast v2 file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CastHeader {
+    pub version: u32,
+    pub width: u16,
+    pub height: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_time_limit: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub env: Option<BTreeMap<String, String>>,
+}
+
+/// The lines after the header, `[time, code, data]`.
+/// The code is "o" for output and "r" for resize, whose data is "COLSxROWS".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct CastEvent(pub f64, pub String, pub String);
+
+/// Tees the output of a terminal into a `.cast` file.
+pub(crate) struct CastRecorder {
+    path: PathBuf,
+    file: BufWriter<File>,
+    started_at: Instant,
+}
+
+impl CastRecorder {
+    /// `shell` is the program of the recorded terminal, not the shell of the app.
+    pub(crate) fn create(path: &Path, cols: u16, rows: u16, shell: &str) -> Result<CastRecorder> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut file = BufWriter::new(File::create(path)?);
+
+        let mut env = BTreeMap::new();
+        env.insert("TERM".to_string(), "xterm-256color".to_string());
+        env.insert("SHELL".to_string(), shell.to_string());
+        let header = CastHeader {
+            version: CAST_VERSION,
+            width: cols,
+            height: rows,
+            timestamp: Some(chrono::Utc::now().timestamp()),
+            idle_time_limit: None,
+            env: Some(env),
+        };
+        serde_json::to_writer(&mut file, &header)?;
+        file.write_all(b"\n")?;
+
+        info!("start recording: {:?}", path);
+
+        Ok(CastRecorder {
+            path: path.to_path_buf(),
+            file,
+            started_at: Instant::now(),
+        })
+    }
+
+    pub(crate) fn output(&mut self, data: &str) -> Result<()> {
+        self.write_event("o", data.to_string())
+    }
+
+    pub(crate) fn resize(&mut self, cols: u16, rows: u16) -> Result<()> {
+        self.write_event("r", format!("{}x{}", cols, rows))
+    }
+
+    fn write_event(&mut self, code: &str, data: String) -> Result<()> {
+        let event = CastEvent(
+            self.started_at.elapsed().as_secs_f64(),
+            code.to_string(),
+            data,
+        );
+        serde_json::to_writer(&mut self.file, &event)?;
+        self.file.write_all(b"\n")?;
+        Ok(())
+    }
+
+    /// Flush the file and return its path.
+    pub(crate) fn finish(mut self) -> Result<PathBuf> {
+        self.file.flush()?;
+        info!("stop recording: {:?}", self.path);
+        Ok(self.path)
+    }
+}
+
+/// Read a `.cast` file, the gaps longer than `idle_time_limit` are shortened.
+pub(crate) fn read_cast(path: &Path) -> Result<(CastHeader, Vec<CastEvent>)> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut lines = reader.lines();
+
+    let header_line = lines
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("empty cast file: {:?}", path))??;
+    let header: CastHeader = serde_json::from_str(&header_line)?;
+    if header.version != CAST_VERSION {
+        return Err(anyhow::anyhow!("unsupported cast version: {}", header.version).into());
+    }
+
+    let mut events = Vec::new();
+    let mut last_time = 0.0;
+    let mut shifted = 0.0;
+    for line in lines {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let mut event: CastEvent = match serde_json::from_str(&line) {
+            Ok(event) => event,
+            Err(err) => {
+                warn!("invalid cast event: {}", err);
+                continue;
+            }
+        };
+        if let Some(limit) = header.idle_time_limit {
+            let gap = event.0 - last_time;
+            if gap > limit {
+                shifted += gap - limit;
+            }
+        }
+        last_time = event.0;
+        event.0 -= shifted;
+        events.push(event);
+    }
+
+    Ok((header, events))
+}
+
+pub(crate) trait CastPlayerEventHandler {
+    fn handle_output(&self, id: String, data: String) -> Result<()>;
+    fn handle_resize(&self, id: String, cols: u16, rows: u16) -> Result<()>;
+    fn handle_finished(&self, id: String) -> Result<()>;
+}
+
+struct PlayerState {
+    paused: bool,
+    speed: f64,
+    stopped: bool,
+}
+
+/// Replays the events of a `.cast` file in real time, scaled by the speed.
+#[derive(Clone)]
+pub(crate) struct CastPlayer {
+    state: Arc<(Mutex<PlayerState>, Condvar)>,
+}
+
+impl CastPlayer {
+    pub(crate) fn start(
+        id: String,
+        events: Vec<CastEvent>,
+        event_handler: Box<dyn CastPlayerEventHandler + Send>,
+    ) -> CastPlayer {
+        let state = Arc::new((
+            Mutex::new(PlayerState {
+                paused: false,
+                speed: 1.0,
+                stopped: false,
+            }),
+            Condvar::new(),
+        ));
+
+        let thread_state = state.clone();
+        std::thread::spawn(move || {
+            info!("begin playback: {}", id);
+            let (lock, cvar) = &*thread_state;
+            // the time of the recording which is played
+            let mut position = 0.0;
+
+            for CastEvent(time, code, data) in events {
+                let mut state = lock.lock().unwrap();
+                while !state.stopped && position < time {
+                    if state.paused {
+                        state = cvar.wait(state).unwrap();
+                        continue;
+                    }
+                    let speed = state.speed;
+                    let wait = Duration::from_secs_f64((time - position) / speed);
+                    let waited_at = Instant::now();
+                    state = cvar.wait_timeout(state, wait).unwrap().0;
+                    position += waited_at.elapsed().as_secs_f64() * speed;
+                }
+                if state.stopped {
+                    info!("playback stopped: {}", id);
+                    return;
+                }
+                drop(state);
+
+                let result = match code.as_str() {
+                    "o" => event_handler.handle_output(id.clone(), data),
+                    "r" => match parse_size(&data) {
+                        Some((cols, rows)) => event_handler.handle_resize(id.clone(), cols, rows),
+                        None => Ok(()),
+                    },
+                    _ => Ok(()),
+                };
+                if let Err(err) = result {
+                    warn!("playback error: {}, id: {}", err, id);
+                }
+            }
+
+            if let Err(err) = event_handler.handle_finished(id.clone()) {
+                warn!("playback error: {}, id: {}", err, id);
+            }
+            info!("end playback: {}", id);
+        });
+
+        CastPlayer { state }
+    }
+
+    pub(crate) fn set_paused(&self, paused: bool) {
+        let (lock, cvar) = &*self.state;
+        lock.lock().unwrap().paused = paused;
+        cvar.notify_all();
+    }
+
+    pub(crate) fn set_speed(&self, speed: f64) {
+        let (lock, cvar) = &*self.state;
+        lock.lock().unwrap().speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        cvar.notify_all();
+    }
+
+    pub(crate) fn stop(&self) {
+        let (lock, cvar) = &*self.state;
+        lock.lock().unwrap().stopped = true;
+        cvar.notify_all();
+    }
+}
+
+fn parse_size(data: &str) -> Option<(u16, u16)> {
+    let (cols, rows) = data.split_once('x')?;
+    Some((cols.parse().ok()?, rows.parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cast(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("t1-asciicast-{}-{}.cast", name, std::process::id()))
+    }
+
+    fn write_cast(name: &str, lines: &[&str]) -> PathBuf {
+        let path = temp_cast(name);
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        path
+    }
+
+    fn times(events: &[CastEvent]) -> Vec<f64> {
+        events.iter().map(|event| event.0).collect()
+    }
+
+    #[test]
+    fn recorder_round_trip() {
+        let path = temp_cast("round-trip");
+        let mut recorder = CastRecorder::create(&path, 80, 24, "/bin/zsh").unwrap();
+        recorder.output("hello\r\n").unwrap();
+        recorder.resize(100, 30).unwrap();
+        recorder.output("\u{1b}[1mbold \"quoted\"").unwrap();
+        assert_eq!(recorder.finish().unwrap(), path);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), 4);
+        let header: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(header["version"], 2);
+        assert_eq!(header["width"], 80);
+        assert_eq!(header["height"], 24);
+        assert!(header["timestamp"].is_i64());
+        assert!(header.get("idle_time_limit").is_none());
+        assert_eq!(
+            header["env"],
+            serde_json::json!({ "SHELL": "/bin/zsh", "TERM": "xterm-256color" })
+        );
+        let event: serde_json::Value = serde_json::from_str(lines[2]).unwrap();
+        assert!(event[0].is_f64());
+        assert_eq!(event[1], "r");
+        assert_eq!(event[2], "100x30");
+
+        let (header, events) = read_cast(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((header.version, header.width, header.height), (2, 80, 24));
+        let events: Vec<(&str, &str)> = events
+            .iter()
+            .map(|CastEvent(_, code, data)| (code.as_str(), data.as_str()))
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                ("o", "hello\r\n"),
+                ("r", "100x30"),
+                ("o", "\u{1b}[1mbold \"quoted\""),
+            ]
+        );
+    }
+
+    #[test]
+    fn idle_time_limit_shortens_gaps() {
+        let path = write_cast(
+            "idle",
+            &[
+                r#"{"version": 2, "width": 80, "height": 24, "idle_time_limit": 2.0}"#,
+                r#"[1.0, "o", "a"]"#,
+                r#"[2.5, "o", "b"]"#,
+                r#"[10.5, "o", "c"]"#,
+                r#"[11.0, "o", "d"]"#,
+                r#"[20.0, "o", "e"]"#,
+            ],
+        );
+        let (header, events) = read_cast(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(header.idle_time_limit, Some(2.0));
+        assert_eq!(times(&events), vec![1.0, 2.5, 4.5, 5.0, 7.0]);
+    }
+
+    #[test]
+    fn gaps_are_kept_without_idle_time_limit() {
+        let path = write_cast(
+            "no-idle",
+            &[
+                r#"{"version": 2, "width": 80, "height": 24}"#,
+                r#"[1.0, "o", "a"]"#,
+                "",
+                "not an event",
+                r#"[30.0, "o", "b"]"#,
+            ],
+        );
+        let (_, events) = read_cast(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(times(&events), vec![1.0, 30.0]);
+    }
+
+    #[test]
+    fn unsupported_version_is_an_error() {
+        let path = write_cast("version", &[r#"{"version": 1, "width": 80, "height": 24}"#]);
+        let result = read_cast(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+
+        let path = write_cast("empty", &[]);
+        let result = read_cast(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parse_sizes() {
+        assert_eq!(parse_size("80x24"), Some((80, 24)));
+        assert_eq!(parse_size("0x0"), Some((0, 0)));
+        assert_eq!(parse_size("80"), None);
+        assert_eq!(parse_size("80x"), None);
+        assert_eq!(parse_size("x24"), None);
+        assert_eq!(parse_size("80x24x1"), None);
+        assert_eq!(parse_size("-1x24"), None);
+        assert_eq!(parse_size("70000x24"), None);
+    }
+}
@@ -0,0 +1,151 @@
+use crate::settings::Settings;
+use crate::Result;
+use schemars::gen::SchemaSettings;
+use schemars::schema::RootSchema;
+use std::path::Path;
+
+const SETTINGS_SCHEMA_FILE: &str = "settings.schema.json";
+
+/// The schema of `settings.toml`, for the editors and `src/bindings/settings.ts`.
+pub(crate) fn settings_schema() -> RootSchema {
+    SchemaSettings::draft07()
+        .into_generator()
+        .into_root_schema_for::<Settings>()
+}
+
+/// Keep `settings.schema.json` next to `settings.toml` up to date, so it can be
+/// referenced with `#:schema ./settings.schema.json` at the top of the file.
+pub(crate) fn write_settings_schema(user_dir: &Path) -> Result<()> {
+    write_schema(&user_dir.join(SETTINGS_SCHEMA_FILE), &settings_schema())
+}
+
+fn schema_content(schema: &RootSchema) -> Result<String> {
+    let mut content = serde_json::to_string_pretty(schema)?;
+    content.push('\n');
+    Ok(content)
+}
+
+fn write_schema(path: &Path, schema: &RootSchema) -> Result<()> {
+    let content = schema_content(schema)?;
+
+    // don't touch the file when nothing has changed, editors watch it
+    if std::fs::read_to_string(path).ok().as_deref() == Some(content.as_str()) {
+        return Ok(());
+    }
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messages::*;
+    use crate::process_statistics::{ResourceAlert, StatResult};
+    use crate::settings::SettingsError;
+    use schemars::schema::{SchemaObject, SubschemaValidation};
+    use std::path::PathBuf;
+
+    const MESSAGES_SCHEMA_FILE: &str = "messages.schema.json";
+
+    /// `pnpm bindings` sets it to write the schemas instead of checking them.
+    const UPDATE_SCHEMAS_ENV: &str = "UPDATE_SCHEMAS";
+
+    /// Every type sent to or received from the frontend, the root is
+    /// a union of them so that they are all generated.
+    pub(crate) fn messages_schema() -> RootSchema {
+        let mut gen = SchemaSettings::draft07().into_generator();
+
+        let types = vec![
+            gen.subschema_for::<InitMessage>(),
+            gen.subschema_for::<SavedSession>(),
+            gen.subschema_for::<PtyEncoding>(),
+            gen.subschema_for::<PtyResponse>(),
+            gen.subschema_for::<OutputMetrics>(),
+            gen.subschema_for::<ThemeResponse>(),
+            gen.subschema_for::<ThemeErrorsMessage>(),
+            gen.subschema_for::<PtyExitMessage>(),
+            gen.subschema_for::<FileItem>(),
+            gen.subschema_for::<FsLsResponse>(),
+            gen.subschema_for::<FsStatResponse>(),
+            gen.subschema_for::<SpawnResult>(),
+            gen.subschema_for::<BatchTestFilesReq>(),
+            gen.subschema_for::<TermOptions>(),
+            gen.subschema_for::<ShellOptions>(),
+            gen.subschema_for::<NewTerminalResponse>(),
+            gen.subschema_for::<ShellIntegrationStatus>(),
+            gen.subschema_for::<BatchTestFilesResp>(),
+            gen.subschema_for::<MenuItem>(),
+            gen.subschema_for::<OpenContextMenuReq>(),
+            gen.subschema_for::<OpenContextMenuClickedMessage>(),
+            gen.subschema_for::<FsChangedMessage>(),
+            gen.subschema_for::<ShellPhase>(),
+            gen.subschema_for::<TerminalInfo>(),
+            gen.subschema_for::<ProjectSettingsChangedMessage>(),
+            gen.subschema_for::<ProjectTrustRequestMessage>(),
+            gen.subschema_for::<ForegroundChangedMessage>(),
+            gen.subschema_for::<CwdChangedMessage>(),
+            gen.subschema_for::<TitleChangedMessage>(),
+            gen.subschema_for::<CommandFinishedMessage>(),
+            gen.subschema_for::<RunningProcess>(),
+            gen.subschema_for::<CanCloseResponse>(),
+            gen.subschema_for::<PlaybackInfo>(),
+            gen.subschema_for::<PlaybackResizedMessage>(),
+            gen.subschema_for::<PlaybackFinishedMessage>(),
+            gen.subschema_for::<StatResult>(),
+            gen.subschema_for::<StatsUpdatedMessage>(),
+            gen.subschema_for::<PortsChangedMessage>(),
+            gen.subschema_for::<CellColor>(),
+            gen.subschema_for::<ScreenCell>(),
+            gen.subschema_for::<ScreenLine>(),
+            gen.subschema_for::<ScreenResponse>(),
+            // emitted as they are
+            gen.subschema_for::<ResourceAlert>(),
+            gen.subschema_for::<SettingsError>(),
+        ];
+
+        let schema = SchemaObject {
+            subschemas: Some(Box::new(SubschemaValidation {
+                any_of: Some(types),
+                ..Default::default()
+            })),
+            ..Default::default()
+        };
+
+        RootSchema {
+            meta_schema: gen.settings().meta_schema.clone(),
+            schema,
+            definitions: gen.take_definitions(),
+        }
+    }
+
+    /// The schemas are committed next to the TypeScript types compiled from them.
+    fn bindings_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("..")
+            .join("src")
+            .join("bindings")
+    }
+
+    #[test]
+    fn schemas_are_up_to_date() {
+        let update = std::env::var_os(UPDATE_SCHEMAS_ENV).is_some();
+        let schemas = [
+            (SETTINGS_SCHEMA_FILE, settings_schema()),
+            (MESSAGES_SCHEMA_FILE, messages_schema()),
+        ];
+
+        for (file, schema) in schemas {
+            let path = bindings_dir().join(file);
+            if update {
+                write_schema(&path, &schema).unwrap();
+                continue;
+            }
+            let committed = std::fs::read_to_string(&path).unwrap_or_default();
+            assert!(
+                committed == schema_content(&schema).unwrap(),
+                "{:?} is stale, run `pnpm bindings` and commit the result",
+                path
+            );
+        }
+    }
+}
@@ -0,0 +1,400 @@
+use crate::session_daemon::{
+    send_message, DaemonRequest, DaemonResponse, DAEMON_ARG, DAEMON_PROTOCOL,
+};
+use crate::Result;
+use base64::{engine::general_purpose, Engine as _};
+use log::{info, warn};
+use portable_pty::{Child, ChildKiller, CommandBuilder, ExitStatus, MasterPty, PtySize};
+use std::io::{BufRead, BufReader};
+use std::os::unix::net::UnixStream;
+use std::os::unix::process::CommandExt;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+const DAEMON_START_TIMEOUT: Duration = Duration::from_secs(2);
+
+// A daemon older than the version request doesn't answer it.
+const VERSION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Connect to the daemon, start it if it's not running.
+///
+/// A daemon of another version, e.g. left running by the app before
+/// an update, is shut down with its sessions and replaced.
+pub(crate) fn ensure_daemon(socket_path: &Path, kill_grace_period: Duration) -> Result<()> {
+    if let Ok(mut stream) = UnixStream::connect(socket_path) {
+        match query_version(&mut stream) {
+            Ok(true) => return Ok(()),
+            Ok(false) => {
+                warn!("replace the session daemon of another version");
+                let request = DaemonRequest::Shutdown {
+                    grace_period_ms: kill_grace_period.as_millis() as u64,
+                };
+                send_message(&mut stream, &request)?;
+            }
+            Err(err) => warn!("replace the session daemon, version error: {}", err),
+        }
+        // the old daemon keeps its listener until it exits,
+        // the new one binds a new socket at the same path
+        let _ = std::fs::remove_file(socket_path);
+    }
+
+    let exe = std::env::current_exe()?;
+    info!("start session daemon: {:?} {:?}", exe, socket_path);
+
+    let mut command = std::process::Command::new(exe);
+    command
+        .arg(DAEMON_ARG)
+        .arg(socket_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null());
+    unsafe {
+        // detach from the session of the app, so the daemon survives it
+        command.pre_exec(|| {
+            libc::setsid();
+            Ok(())
+        });
+    }
+    command.spawn()?;
+
+    let started_at = Instant::now();
+    while started_at.elapsed() < DAEMON_START_TIMEOUT {
+        if UnixStream::connect(socket_path).is_ok() {
+            return Ok(());
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    Err(anyhow::anyhow!("session daemon is not started in {:?}", DAEMON_START_TIMEOUT).into())
+}
+
+/// Whether the daemon is of the same version as the app.
+fn query_version(stream: &mut UnixStream) -> Result<bool> {
+    send_message(stream, &DaemonRequest::Version)?;
+
+    stream.set_read_timeout(Some(VERSION_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    stream.set_read_timeout(None)?;
+
+    match serde_json::from_str::<DaemonResponse>(&line)? {
+        DaemonResponse::Version { protocol, version } => {
+            info!("session daemon version: {}, protocol: {}", version, protocol);
+            Ok(protocol == DAEMON_PROTOCOL && version == env!("CARGO_PKG_VERSION"))
+        }
+        response => Err(anyhow::anyhow!("unexpected response: {:?}", response).into()),
+    }
+}
+
+pub(crate) struct DaemonPty {
+    pub master: Box<dyn MasterPty + Send>,
+    pub child: Box<dyn Child + Send + Sync>,
+    /// The id of the session in the daemon, kept across attaches.
+    pub session_id: String,
+    /// The session was already running, its output is replayed.
+    pub attached: bool,
+}
+
+/// Attach to the session `attach` if it's still running in the daemon,
+/// otherwise spawn the command in a new session `id`.
+pub(crate) fn open_daemon_pty(
+    socket_path: &Path,
+    id: &str,
+    attach: Option<&str>,
+    command: CommandBuilder,
+    size: PtySize,
+    kill_grace_period: Duration,
+) -> Result<DaemonPty> {
+    if let Some(session_id) = attach {
+        let request = DaemonRequest::Attach {
+            id: session_id.to_string(),
+            size,
+        };
+        match connect(socket_path, &request, size, kill_grace_period) {
+            Ok((master, child)) => {
+                return Ok(DaemonPty {
+                    master,
+                    child,
+                    session_id: session_id.to_string(),
+                    attached: true,
+                });
+            }
+            Err(err) => warn!("attach session {} failed: {}", session_id, err),
+        }
+    }
+
+    let request = DaemonRequest::Spawn {
+        id: id.to_string(),
+        command,
+        size,
+    };
+    let (master, child) = connect(socket_path, &request, size, kill_grace_period)?;
+
+    Ok(DaemonPty {
+        master,
+        child,
+        session_id: id.to_string(),
+        attached: false,
+    })
+}
+
+/// End a session which is not attached, e.g. a tab the user didn't restore.
+///
+/// The session isn't attached, so its output isn't replayed to a connection
+/// which doesn't read it.
+pub(crate) fn kill_session(
+    socket_path: &Path,
+    session_id: &str,
+    kill_grace_period: Duration,
+) -> Result<()> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    let request = DaemonRequest::KillSession {
+        id: session_id.to_string(),
+        grace_period_ms: kill_grace_period.as_millis() as u64,
+    };
+    send_message(&mut stream, &request)?;
+
+    // the daemon closes the connection once the kill is started
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(());
+    }
+    match serde_json::from_str::<DaemonResponse>(&line)? {
+        DaemonResponse::Error { message } => Err(anyhow::anyhow!(message).into()),
+        response => Err(anyhow::anyhow!("unexpected response: {:?}", response).into()),
+    }
+}
+
+fn connect(
+    socket_path: &Path,
+    request: &DaemonRequest,
+    size: PtySize,
+    kill_grace_period: Duration,
+) -> Result<(Box<dyn MasterPty + Send>, Box<dyn Child + Send + Sync>)> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    send_message(&mut stream, request)?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+
+    let pid = match serde_json::from_str::<DaemonResponse>(&line)? {
+        DaemonResponse::Ready { pid } => pid,
+        DaemonResponse::Error { message } => return Err(anyhow::anyhow!(message).into()),
+        response => {
+            return Err(anyhow::anyhow!("unexpected response: {:?}", response).into());
+        }
+    };
+
+    let sender = Arc::new(Mutex::new(stream));
+    let exit = Arc::new((Mutex::new(None), Condvar::new()));
+
+    let master = DaemonMaster {
+        sender: sender.clone(),
+        reader: Mutex::new(Some(reader)),
+        exit: exit.clone(),
+        size: Mutex::new(size),
+        foreground: Arc::new(Mutex::new(None)),
+    };
+    let child = DaemonChild {
+        sender,
+        pid,
+        exit,
+        kill_grace_period,
+    };
+
+    Ok((Box::new(master), Box::new(child)))
+}
+
+type ExitSlot = Arc<(Mutex<Option<ExitStatus>>, Condvar)>;
+
+fn set_exit(exit: &ExitSlot, status: ExitStatus) {
+    let (lock, cvar) = &**exit;
+    let mut slot = lock.lock().unwrap();
+    if slot.is_none() {
+        *slot = Some(status);
+    }
+    cvar.notify_all();
+}
+
+fn send_request(sender: &Mutex<UnixStream>, request: &DaemonRequest) -> std::io::Result<()> {
+    let mut stream = sender.lock().unwrap();
+    send_message(&mut stream, request)
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err.to_string()))
+}
+
+/// The PTY master of a session in the daemon.
+struct DaemonMaster {
+    sender: Arc<Mutex<UnixStream>>,
+    reader: Mutex<Option<BufReader<UnixStream>>>,
+    exit: ExitSlot,
+    size: Mutex<PtySize>,
+    /// Pushed by the daemon, updated by the reader.
+    foreground: Arc<Mutex<Option<libc::pid_t>>>,
+}
+
+impl MasterPty for DaemonMaster {
+    fn resize(&self, size: PtySize) -> anyhow::Result<()> {
+        send_request(&self.sender, &DaemonRequest::Resize { size })?;
+        *self.size.lock().unwrap() = size;
+        Ok(())
+    }
+
+    fn get_size(&self) -> anyhow::Result<PtySize> {
+        Ok(*self.size.lock().unwrap())
+    }
+
+    fn try_clone_reader(&self) -> anyhow::Result<Box<dyn std::io::Read + Send>> {
+        let reader = self
+            .reader
+            .lock()
+            .unwrap()
+            .take()
+            .ok_or_else(|| anyhow::anyhow!("the reader of the session is taken"))?;
+        Ok(Box::new(DaemonReader {
+            reader,
+            pending: Vec::new(),
+            exit: self.exit.clone(),
+            foreground: self.foreground.clone(),
+        }))
+    }
+
+    fn take_writer(&self) -> anyhow::Result<Box<dyn std::io::Write + Send>> {
+        Ok(Box::new(DaemonWriter {
+            sender: self.sender.clone(),
+        }))
+    }
+
+    fn process_group_leader(&self) -> Option<libc::pid_t> {
+        *self.foreground.lock().unwrap()
+    }
+
+    fn as_raw_fd(&self) -> Option<std::os::unix::io::RawFd> {
+        None
+    }
+}
+
+impl Drop for DaemonMaster {
+    fn drop(&mut self) {
+        // The session is detached and keeps running in the daemon,
+        // the reader gets EOF.
+        let stream = self.sender.lock().unwrap();
+        let _ = stream.shutdown(std::net::Shutdown::Both);
+    }
+}
+
+struct DaemonReader {
+    reader: BufReader<UnixStream>,
+    pending: Vec<u8>,
+    exit: ExitSlot,
+    foreground: Arc<Mutex<Option<libc::pid_t>>>,
+}
+
+impl std::io::Read for DaemonReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.pending.is_empty() {
+            let mut line = String::new();
+            if self.reader.read_line(&mut line)? == 0 {
+                // the connection is closed, by us or the daemon
+                set_exit(&self.exit, ExitStatus::with_signal("SIGHUP"));
+                return Ok(0);
+            }
+
+            match serde_json::from_str::<DaemonResponse>(&line) {
+                Ok(DaemonResponse::Output { data }) => {
+                    self.pending = general_purpose::STANDARD
+                        .decode(data)
+                        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+                }
+                Ok(DaemonResponse::Exited { code, signal }) => {
+                    let status = match signal {
+                        Some(signal) => ExitStatus::with_signal(&signal),
+                        None => ExitStatus::with_exit_code(code),
+                    };
+                    set_exit(&self.exit, status);
+                    return Ok(0);
+                }
+                Ok(DaemonResponse::Error { message }) => warn!("session daemon error: {}", message),
+                Ok(DaemonResponse::Foreground { pgid }) => {
+                    *self.foreground.lock().unwrap() = pgid;
+                }
+                Ok(DaemonResponse::Ready { .. }) | Ok(DaemonResponse::Version { .. }) => (),
+                Err(err) => warn!("invalid response: {}", err),
+            }
+        }
+
+        let size = buf.len().min(self.pending.len());
+        buf[..size].copy_from_slice(&self.pending[..size]);
+        self.pending.drain(..size);
+        Ok(size)
+    }
+}
+
+struct DaemonWriter {
+    sender: Arc<Mutex<UnixStream>>,
+}
+
+impl std::io::Write for DaemonWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let request = DaemonRequest::Write {
+            data: general_purpose::STANDARD.encode(buf),
+        };
+        send_request(&self.sender, &request)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// The shell of a session in the daemon, its exit is reported by the reader.
+#[derive(Debug)]
+struct DaemonChild {
+    sender: Arc<Mutex<UnixStream>>,
+    pid: Option<u32>,
+    exit: ExitSlot,
+    kill_grace_period: Duration,
+}
+
+impl ChildKiller for DaemonChild {
+    fn kill(&mut self) -> std::io::Result<()> {
+        let request = DaemonRequest::Kill {
+            grace_period_ms: self.kill_grace_period.as_millis() as u64,
+        };
+        send_request(&self.sender, &request)
+    }
+
+    fn clone_killer(&self) -> Box<dyn ChildKiller + Send + Sync> {
+        Box::new(DaemonChild {
+            sender: self.sender.clone(),
+            pid: self.pid,
+            exit: self.exit.clone(),
+            kill_grace_period: self.kill_grace_period,
+        })
+    }
+}
+
+impl Child for DaemonChild {
+    fn try_wait(&mut self) -> std::io::Result<Option<ExitStatus>> {
+        let (lock, _) = &*self.exit;
+        Ok(lock.lock().unwrap().clone())
+    }
+
+    fn wait(&mut self) -> std::io::Result<ExitStatus> {
+        let (lock, cvar) = &*self.exit;
+        let mut slot = lock.lock().unwrap();
+        while slot.is_none() {
+            slot = cvar.wait(slot).unwrap();
+        }
+        Ok(slot.clone().unwrap())
+    }
+
+    fn process_id(&self) -> Option<u32> {
+        self.pid
+    }
+}
@@ -32,5 +32,27 @@ pub(crate) fn open_database(path: &Path) -> Result<rusqlite::Connection> {
     (),
   )?;
 
+  database.execute(
+    "CREATE TABLE IF NOT EXISTS sessions(
+      id TEXT PRIMARY KEY,
+      position INTEGER,
+      profile TEXT,
+      cwd TEXT,
+      scrollback TEXT
+    )",
+    (),
+  )?;
+
+  // the content a user has trusted, or not, of each project settings file
+  database.execute(
+    "CREATE TABLE IF NOT EXISTS project_trust(
+      path TEXT PRIMARY KEY,
+      content TEXT,
+      trusted INTEGER,
+      decided_at DATETIME DEFAULT CURRENT_TIMESTAMP
+    )",
+    (),
+  )?;
+
   Ok(database)
 }
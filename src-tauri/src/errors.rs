@@ -19,6 +19,18 @@ pub enum Error {
   TauriError(#[from] tauri::Error),
   #[error("no themes found")]
   NoThemesFound,
+  #[error("theme not found: {0}")]
+  ThemeNotFound(String),
+  #[error("profile not found: {0}")]
+  ProfileNotFound(String),
+  #[error("unsupported shell: {0}")]
+  UnsupportedShell(String),
+  #[error("terminal not found: {0}")]
+  TerminalNotFound(String),
+  #[error("playback not found: {0}")]
+  PlaybackNotFound(String),
+  #[error("invalid setting {0}: {1}")]
+  InvalidSetting(String, String),
   #[error(transparent)]
   TomlError(#[from] toml::de::Error),
   #[error(transparent)]
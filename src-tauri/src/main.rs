@@ -7,7 +7,10 @@ extern crate objc;
 
 mod app_path;
 mod app_state;
+mod asciicast;
+mod bindings;
 mod context_menu;
+mod daemon_client;
 mod database;
 pub mod errors;
 mod shell_integration;
@@ -15,31 +18,46 @@ mod logs;
 mod mac_ext;
 mod menu;
 mod messages;
+mod osc_scanner;
+mod output_buffer;
+mod process_killer;
 mod process_statistics;
-pub mod settings;
+mod project_settings;
+mod session_daemon;
+pub mod session_store;
+mod settings;
+mod settings_editor;
+mod shell;
 mod terminal_delegate;
+mod terminal_screen;
 mod theme_context;
 mod updater;
+mod utf8_chunker;
 
 use crate::mac_ext::WindowExt;
-use app_state::AppState;
+use app_state::{AppState, ProjectChange};
+use base64::{engine::general_purpose, Engine as _};
 pub use errors::Error;
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use messages::*;
 use portable_pty::ExitStatus;
-use process_statistics::StatResult;
+use process_statistics::{
+    ForegroundProcess, ListeningPort, ResourceAlert, ResourceSample, ResourceSampler,
+    SamplerOptions, StatResult, TerminalSample,
+};
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::{
     env, fs,
     io::Write,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
     vec,
 };
 use sysinfo::{System, SystemExt};
-use tauri::{async_runtime, AppHandle, Manager, State};
+use tauri::{async_runtime, AppHandle, Manager, RunEvent, State, WindowEvent};
 use terminal_delegate::TerminalDelegateEventHandler;
-use shell_integration::install_script;
+use shell::{ShellCommand, ShellKind};
+use shell_integration::{install_script, shell_integration_status, uninstall_script};
 // use portable_pty
 
 pub type Result<T> = std::result::Result<T, errors::Error>;
@@ -47,6 +65,8 @@ const CHANGELOG_LINK: &str = "https://www.terminalone.app/changelog";
 const DISCORD_LINK: &str = "https://discord.gg/8vmYtHSP5m";
 const TWITTER_LINK: &str = "https://twitter.com/terminalone_app";
 const APP_NAME: &str = "Terminal One";
+const DAEMON_SOCKET_NAME: &str = "daemon.sock";
+const RECORDINGS_DIR_NAME: &str = "recordings";
 
 struct MainTerminalEventHandler {
     window: tauri::Window,
@@ -57,20 +77,47 @@ impl TerminalDelegateEventHandler for MainTerminalEventHandler {
         &self,
         terminal_delegate: &terminal_delegate::TerminalDelegate,
         data: &[u8],
+        offset: u64,
     ) -> Result<()> {
         let id_str = terminal_delegate.id();
-        // data to string unchecked utf8
-        let data = unsafe { String::from_utf8_unchecked(data.to_vec()) };
+        let encoding = terminal_delegate.output_encoding();
+        // the data always ends on a character boundary
+        let data = match encoding {
+            PtyEncoding::Utf8 => String::from_utf8_lossy(data).into_owned(),
+            PtyEncoding::Base64 => general_purpose::STANDARD.encode(data),
+        };
         self.window.emit(
             messages::push_event::PTY_OUTPUT,
-            PtyResponse { id: id_str, data },
+            PtyResponse {
+                id: id_str,
+                data,
+                encoding,
+                offset,
+            },
         )?;
         Ok(())
     }
 
-    fn handle_exit(&self, id: String, _exit_code: ExitStatus) -> Result<()> {
-        self.window
-            .emit(messages::push_event::PTY_EXIT, PtyExitMessage { id })?;
+    fn handle_exit(
+        &self,
+        terminal_delegate: &terminal_delegate::TerminalDelegate,
+        exit_status: ExitStatus,
+    ) -> Result<()> {
+        // the tab is already gone or being restarted
+        if terminal_delegate.is_closed() {
+            info!("closed terminal exited: {}, id: {}", exit_status, terminal_delegate.id());
+            return Ok(());
+        }
+        self.window.emit(
+            messages::push_event::PTY_EXIT,
+            PtyExitMessage {
+                id: terminal_delegate.id(),
+                exit_code: exit_status.exit_code(),
+                signal: terminal_delegate::exit_signal(&exit_status),
+                duration_ms: terminal_delegate.uptime().as_millis() as u64,
+                action: terminal_delegate.exit_action(),
+            },
+        )?;
         Ok(())
     }
 
@@ -81,6 +128,203 @@ impl TerminalDelegateEventHandler for MainTerminalEventHandler {
         )?;
         Ok(())
     }
+
+    fn handle_cwd_changed(&self, id: String, cwd: String) -> Result<()> {
+        self.window.emit(
+            messages::push_event::CWD_CHANGED,
+            CwdChangedMessage {
+                id: id.clone(),
+                cwd: cwd.clone(),
+            },
+        )?;
+
+        let state = self.window.state::<AppState>();
+        if let Some(change) = state.inner().update_terminal_project(&id, &cwd)? {
+            emit_project_change(&self.window, change)?;
+        }
+        Ok(())
+    }
+
+    fn handle_title_changed(&self, id: String, title: String) -> Result<()> {
+        self.window.emit(
+            messages::push_event::TITLE_CHANGED,
+            TitleChangedMessage { id, title },
+        )?;
+        Ok(())
+    }
+
+    fn handle_foreground_changed(&self, id: String, process: ForegroundProcess) -> Result<()> {
+        self.window.emit(
+            messages::push_event::FOREGROUND_CHANGED,
+            ForegroundChangedMessage { id, process },
+        )?;
+        Ok(())
+    }
+
+    fn handle_command_finished(
+        &self,
+        id: String,
+        exit_code: Option<i32>,
+        duration: Option<Duration>,
+    ) -> Result<()> {
+        self.window.emit(
+            messages::push_event::COMMAND_FINISHED,
+            CommandFinishedMessage {
+                id,
+                exit_code,
+                duration_ms: duration.map(|d| d.as_millis() as u64),
+            },
+        )?;
+        Ok(())
+    }
+}
+
+fn emit_project_change(window: &tauri::Window, change: ProjectChange) -> Result<()> {
+    match change {
+        ProjectChange::Changed(message) => {
+            window.emit(messages::push_event::PROJECT_SETTINGS_CHANGED, message)?
+        }
+        ProjectChange::TrustRequested(message) => {
+            window.emit(messages::push_event::PROJECT_TRUST_REQUESTED, message)?
+        }
+    }
+    Ok(())
+}
+
+/// Use the settings and push `settings-changed` to every window,
+/// and `theme-changed` if `app.theme` has changed.
+fn apply_settings(app_handle: &AppHandle, settings: settings::Settings) -> Result<()> {
+    let state = app_handle.state::<AppState>();
+    let previous_theme = state.inner().settings().app.theme.clone();
+    let theme_changed = settings.app.theme != previous_theme;
+
+    state.inner().set_settings(settings.clone());
+    for window in app_handle.windows().values() {
+        menu::update_profile_menu(&window.menu_handle(), &settings)?;
+    }
+    app_handle.emit_all(messages::push_event::SETTINGS_CHANGED, settings)?;
+
+    if theme_changed {
+        match state.inner().current_theme() {
+            Ok(theme) => app_handle.emit_all(messages::push_event::THEME_CHANGED, theme)?,
+            Err(err) => error!("load theme error: {}", err),
+        }
+    }
+    Ok(())
+}
+
+struct MainPlaybackEventHandler {
+    window: tauri::Window,
+}
+
+impl asciicast::CastPlayerEventHandler for MainPlaybackEventHandler {
+    fn handle_output(&self, id: String, data: String) -> Result<()> {
+        // the same path as the output of a terminal, the offset is never acked
+        self.window.emit(
+            messages::push_event::PTY_OUTPUT,
+            PtyResponse {
+                id,
+                data,
+                encoding: PtyEncoding::Utf8,
+                offset: 0,
+            },
+        )?;
+        Ok(())
+    }
+
+    fn handle_resize(&self, id: String, cols: u16, rows: u16) -> Result<()> {
+        self.window.emit(
+            messages::push_event::PLAYBACK_RESIZED,
+            PlaybackResizedMessage { id, cols, rows },
+        )?;
+        Ok(())
+    }
+
+    fn handle_finished(&self, id: String) -> Result<()> {
+        self.window.emit(
+            messages::push_event::PLAYBACK_FINISHED,
+            PlaybackFinishedMessage { id },
+        )?;
+        Ok(())
+    }
+}
+
+struct MainResourceSamplerEventHandler {
+    app_handle: AppHandle,
+}
+
+impl process_statistics::ResourceSamplerEventHandler for MainResourceSamplerEventHandler {
+    fn terminals(&self) -> Vec<(String, u32)> {
+        self.app_handle.state::<AppState>().inner().terminal_pids()
+    }
+
+    fn handle_samples(&self, samples: Vec<TerminalSample>) -> Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+        self.app_handle
+            .emit_all(messages::push_event::STATS_UPDATED, StatsUpdatedMessage { samples })?;
+        Ok(())
+    }
+
+    fn handle_alert(&self, alert: ResourceAlert) -> Result<()> {
+        self.app_handle
+            .emit_all(messages::push_event::RESOURCE_ALERT, alert)?;
+        Ok(())
+    }
+
+    fn handle_ports_changed(&self, id: String, ports: Vec<ListeningPort>) -> Result<()> {
+        self.app_handle
+            .emit_all(messages::push_event::PORTS_CHANGED, PortsChangedMessage { id, ports })?;
+        Ok(())
+    }
+}
+
+struct MainSettingsEventHandler {
+    app_handle: AppHandle,
+}
+
+impl settings::SettingsWatcherEventHandler for MainSettingsEventHandler {
+    /// e.g. `app.theme` is edited in settings.toml
+    fn handle_settings_changed(&self, settings: settings::Settings) -> Result<()> {
+        apply_settings(&self.app_handle, settings)
+    }
+
+    fn handle_settings_error(&self, error: settings::SettingsError) -> Result<()> {
+        let state = self.app_handle.state::<AppState>();
+        state.inner().set_settings_error(Some(error.clone()));
+        self.app_handle
+            .emit_all(messages::push_event::SETTINGS_ERROR, error)?;
+        Ok(())
+    }
+}
+
+struct MainThemeEventHandler {
+    app_handle: AppHandle,
+}
+
+impl theme_context::ThemeWatcherEventHandler for MainThemeEventHandler {
+    fn handle_themes_changed(
+        &self,
+        theme_context: theme_context::ThemeContext,
+        errors: Vec<theme_context::ThemeError>,
+    ) -> Result<()> {
+        let state = self.app_handle.state::<AppState>();
+        state.inner().set_themes(theme_context, errors.clone());
+        self.app_handle.emit_all(
+            messages::push_event::THEME_ERRORS,
+            ThemeErrorsMessage { errors },
+        )?;
+
+        // the current theme may be the one edited
+        match state.inner().current_theme() {
+            Ok(theme) => self
+                .app_handle
+                .emit_all(messages::push_event::THEME_CHANGED, theme)?,
+            Err(err) => error!("reload theme error: {}", err),
+        }
+        Ok(())
+    }
 }
 
 #[tauri::command]
@@ -92,6 +336,7 @@ fn fetch_init_data(app: AppHandle, state: State<AppState>) -> Result<messages::I
 
     let settings = state.inner().settings();
     let docs = state.inner().fetch_all_ui_stores()?;
+    let restored_sessions = state.inner().restorable_sessions();
 
     debug!("init ui stores: {:?}", docs);
 
@@ -114,6 +359,9 @@ fn fetch_init_data(app: AppHandle, state: State<AppState>) -> Result<messages::I
         force_onboarding,
         ui_stores: serde_json::Value::Object(json_doc),
         settings: settings.as_ref().clone(),
+        settings_error: state.inner().settings_error(),
+        theme_errors: state.inner().theme_errors(),
+        restored_sessions,
     })
 }
 
@@ -124,15 +372,85 @@ fn new_terminal(
     state: State<AppState>,
     id: String,
     path: Option<String>,
-) -> Result<()> {
+    profile: Option<String>,
+    shell: Option<ShellOptions>,
+    restore: Option<String>,
+) -> Result<NewTerminalResponse> {
     let events_handler: Box<dyn TerminalDelegateEventHandler + Send + Sync> =
         Box::new(MainTerminalEventHandler {
             window: window.clone(),
         });
-    let _delegate = state.inner().new_terminal(id, path, events_handler)?;
+    let delegate =
+        state
+            .inner()
+            .new_terminal(id, path, profile, shell, restore, events_handler)?;
+
+    // the profile may come from the restored session
+    let settings = state.inner().settings();
+    let resp = delegate
+        .profile()
+        .as_ref()
+        .and_then(|name| settings.find_profile(name))
+        .map(|profile| NewTerminalResponse {
+            title: profile.title.clone(),
+            theme: profile.theme.clone(),
+        })
+        .unwrap_or_default();
+
+    Ok(resp)
+}
+
+/// The shell has exited and the action of its profile is `restart`.
+#[tauri::command]
+fn restart_terminal(window: tauri::Window, state: State<AppState>, id: &str) -> Result<()> {
+    let events_handler: Box<dyn TerminalDelegateEventHandler + Send + Sync> =
+        Box::new(MainTerminalEventHandler { window });
+    state.inner().restart_terminal(id, events_handler)?;
+
     Ok(())
 }
 
+/// Ask before quitting with running processes. Returns true if it can quit now,
+/// otherwise `quit` is called once the user has confirmed.
+fn confirm_quit(window: &tauri::Window, quit: impl FnOnce(tauri::Window) + Send + 'static) -> bool {
+    let state = window.state::<AppState>();
+    if state.inner().is_close_confirmed() {
+        return true;
+    }
+    let resp = state.inner().can_close(None);
+    if resp.can_close {
+        return true;
+    }
+
+    let message = format!(
+        "{}. Do you want to quit anyway?",
+        resp.message.unwrap_or_default()
+    );
+    let confirm_window = window.clone();
+    tauri::api::dialog::confirm(Some(window), APP_NAME, message, move |confirmed| {
+        if confirmed {
+            confirm_window.state::<AppState>().inner().confirm_close();
+            quit(confirm_window);
+        }
+    });
+    false
+}
+
+/// The app exits with the last window, and the sessions are saved on `RunEvent::Exit`.
+fn close_windows(app: &AppHandle) {
+    for window in app.windows().values() {
+        if let Err(err) = window.close() {
+            error!("close window error: {}", err);
+        }
+    }
+}
+
+/// The user doesn't want to restore the last session.
+#[tauri::command]
+fn discard_sessions(state: State<AppState>) {
+    state.inner().discard_restorable_sessions();
+}
+
 #[tauri::command]
 fn resize_pty(state: State<AppState>, id: &str, rows: u16, cols: u16) -> Result<()> {
     let delegate = state.inner().get_terminal_by_id(id);
@@ -156,6 +474,45 @@ fn get_terminal_statistics(state: State<AppState>, id: &str) -> StatResult {
     delegate.fetch_statistics()
 }
 
+/// The samples of the terminal kept by the sampler, oldest first.
+#[tauri::command]
+fn get_terminal_stats_history(sampler: State<ResourceSampler>, id: &str) -> Vec<ResourceSample> {
+    sampler.inner().history(id)
+}
+
+/// The TCP ports listened on by the processes of the terminal, as of the last sample.
+#[tauri::command]
+fn get_terminal_ports(sampler: State<ResourceSampler>, id: &str) -> Vec<ListeningPort> {
+    sampler.inner().ports(id)
+}
+
+#[tauri::command]
+fn get_terminal_info(state: State<AppState>, id: &str) -> TerminalInfo {
+    let delegate = state.inner().get_terminal_by_id(id);
+
+    delegate.info()
+}
+
+#[tauri::command]
+fn get_terminal_screen(state: State<AppState>, id: &str, styled: Option<bool>) -> ScreenResponse {
+    let delegate = state.inner().get_terminal_by_id(id);
+
+    delegate.screen(styled.unwrap_or(false))
+}
+
+#[tauri::command]
+fn get_terminal_scrollback(
+    state: State<AppState>,
+    id: &str,
+    start: usize,
+    count: usize,
+    styled: Option<bool>,
+) -> ScreenResponse {
+    let delegate = state.inner().get_terminal_by_id(id);
+
+    delegate.scrollback(start, count, styled.unwrap_or(false))
+}
+
 #[tauri::command]
 fn terminal_set_options(state: State<AppState>, id: &str, options: TermOptions) -> Result<()> {
     let delegate = state.inner().get_terminal_by_id(id);
@@ -165,6 +522,110 @@ fn terminal_set_options(state: State<AppState>, id: &str, options: TermOptions)
     Ok(())
 }
 
+#[tauri::command]
+fn ack_pty_output(state: State<AppState>, id: &str, offset: u64) {
+    // the ack may arrive after the terminal is removed
+    if let Some(delegate) = state.inner().find_terminal_by_id(id) {
+        delegate.ack_output(offset);
+    }
+}
+
+#[tauri::command]
+fn get_pty_output_metrics(state: State<AppState>, id: &str) -> OutputMetrics {
+    let delegate = state.inner().get_terminal_by_id(id);
+
+    delegate.output_metrics()
+}
+
+#[tauri::command]
+fn terminal_set_output_encoding(
+    state: State<AppState>,
+    id: &str,
+    encoding: PtyEncoding,
+) -> Result<()> {
+    let delegate = state.inner().get_terminal_by_id(id);
+
+    delegate.set_output_encoding(encoding);
+
+    Ok(())
+}
+
+/// Record the output of the terminal into an asciicast file,
+/// returns the path of the file.
+#[tauri::command]
+fn start_recording(state: State<AppState>, id: &str, path: Option<String>) -> Result<String> {
+    let delegate = state.inner().get_terminal_by_id(id);
+
+    let path = match path {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let app_data_dir = app_path::app_data_dir(APP_NAME).expect("no data dirs");
+            let now = chrono::Local::now().format("%Y%m%d-%H%M%S");
+            app_data_dir
+                .join(RECORDINGS_DIR_NAME)
+                .join(format!("{}-{}.cast", id, now))
+        }
+    };
+    delegate.start_recording(&path)?;
+
+    Ok(path.to_string_lossy().to_string())
+}
+
+#[tauri::command]
+fn stop_recording(state: State<AppState>, id: &str) -> Result<Option<String>> {
+    let delegate = state.inner().get_terminal_by_id(id);
+
+    let path = delegate.stop_recording()?;
+
+    Ok(path.map(|path| path.to_string_lossy().to_string()))
+}
+
+#[tauri::command]
+fn start_playback(
+    window: tauri::Window,
+    state: State<AppState>,
+    id: String,
+    path: &str,
+) -> Result<PlaybackInfo> {
+    let event_handler = Box::new(MainPlaybackEventHandler { window });
+
+    state
+        .inner()
+        .start_playback(id, Path::new(path), event_handler)
+}
+
+#[tauri::command]
+fn set_playback_paused(state: State<AppState>, id: &str, paused: bool) -> Result<()> {
+    let player = state.inner().get_playback_by_id(id)?;
+
+    player.set_paused(paused);
+
+    Ok(())
+}
+
+#[tauri::command]
+fn set_playback_speed(state: State<AppState>, id: &str, speed: f64) -> Result<()> {
+    let player = state.inner().get_playback_by_id(id)?;
+
+    player.set_speed(speed);
+
+    Ok(())
+}
+
+#[tauri::command]
+fn stop_playback(state: State<AppState>, id: &str) -> Result<()> {
+    state.inner().remove_playback_by_id(id);
+
+    Ok(())
+}
+
+/// Whether the terminal `id`, or all the terminals if `id` is not set,
+/// can be closed without asking.
+#[tauri::command]
+fn can_close(state: State<AppState>, id: Option<String>) -> CanCloseResponse {
+    state.inner().can_close(id.as_deref())
+}
+
 #[tauri::command]
 fn remove_terminal(state: State<AppState>, id: &str) -> Result<()> {
     state.inner().remove_terminal_by_id(id);
@@ -173,8 +634,88 @@ fn remove_terminal(state: State<AppState>, id: &str) -> Result<()> {
 }
 
 #[tauri::command]
-fn get_a_theme(state: State<AppState>) -> Result<ThemeResponse> {
-    state.inner().get_a_theme()
+fn current_theme(state: State<AppState>) -> Result<ThemeResponse> {
+    state.inner().current_theme()
+}
+
+#[tauri::command]
+fn list_themes(state: State<AppState>) -> Vec<String> {
+    state.inner().list_themes()
+}
+
+#[tauri::command]
+fn get_theme(state: State<AppState>, name: &str) -> Result<ThemeResponse> {
+    state.inner().get_theme(name)
+}
+
+/// Saved as `app.theme`, every window is told.
+#[tauri::command]
+fn set_theme(app: AppHandle, state: State<AppState>, name: &str) -> Result<ThemeResponse> {
+    let theme = state.inner().get_theme(name)?;
+
+    let app_data_dir = app_path::app_data_dir(APP_NAME).expect("no data dirs");
+    let settings_path = settings::settings_path(&app_data_dir);
+    let settings = settings_editor::update_setting(&settings_path, "app.theme", name.into())?;
+    state.inner().set_settings(settings);
+
+    app.emit_all(messages::push_event::THEME_CHANGED, theme.clone())?;
+    Ok(theme)
+}
+
+/// Write the setting to `settings.toml` and push `settings-changed` right away,
+/// the watcher sees the same settings later.
+#[tauri::command]
+fn update_setting(
+    app: AppHandle,
+    path: &str,
+    value: serde_json::Value,
+) -> Result<settings::Settings> {
+    let app_data_dir = app_path::app_data_dir(APP_NAME).expect("no data dirs");
+    let settings_path = settings::settings_path(&app_data_dir);
+    let settings = settings_editor::update_setting(&settings_path, path, value)?;
+    apply_settings(&app, settings.clone())?;
+    Ok(settings)
+}
+
+#[tauri::command]
+fn reset_setting(app: AppHandle, path: &str) -> Result<settings::Settings> {
+    let app_data_dir = app_path::app_data_dir(APP_NAME).expect("no data dirs");
+    let settings_path = settings::settings_path(&app_data_dir);
+    let settings = settings_editor::reset_setting(&settings_path, path)?;
+    apply_settings(&app, settings.clone())?;
+    Ok(settings)
+}
+
+/// The answer to `project-trust-requested` about its `content`,
+/// it applies to every terminal in the project.
+#[tauri::command]
+fn trust_project_settings(
+    window: tauri::Window,
+    state: State<AppState>,
+    path: &str,
+    content: &str,
+    trusted: bool,
+) -> Result<()> {
+    for change in state.inner().trust_project(path, content, trusted)? {
+        emit_project_change(&window, change)?;
+    }
+    Ok(())
+}
+
+/// Open `settings.toml` in the default editor, it's created if missing.
+#[tauri::command]
+fn open_settings_file() -> Result<()> {
+    let app_data_dir = app_path::app_data_dir(APP_NAME).expect("no data dirs");
+    let settings_path = settings::settings_path(&app_data_dir);
+    if !settings_path.exists() {
+        if let Some(parent) = settings_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&settings_path, "")?;
+    }
+    info!("open settings file: {:?}", settings_path);
+    open::that(settings_path)?;
+    Ok(())
 }
 
 #[tauri::command]
@@ -369,15 +910,32 @@ fn main() {
 
     logs::init_logs(app_log_dir.as_path());
 
-    debug!("debug env");
+    // The binary is started again as the session daemon, without any UI.
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() > 2 && args[1] == session_daemon::DAEMON_ARG {
+        if let Err(err) = session_daemon::run_daemon(Path::new(&args[2])) {
+            error!("session daemon error: {}", err);
+        }
+        return;
+    }
 
-    let menu = menu::generate_menu(APP_NAME);
+    debug!("debug env");
 
     let _ = std::fs::create_dir(&app_data_dir);
 
-    let settings = settings::read_init_settings(&app_data_dir);
+    let (settings, settings_error) = settings::read_init_settings(&app_data_dir);
     debug!("settings: {:?}", settings);
 
+    if let Err(err) = bindings::write_settings_schema(&app_data_dir.join("User")) {
+        warn!("write settings schema error: {}", err);
+    }
+
+    let menu = menu::generate_menu(APP_NAME, &settings);
+
+    let session_daemon_enabled = settings.app.session_daemon;
+
+    let shell_kind = ShellKind::from_program(&ShellCommand::from_settings(&settings.terminal).program);
+
     // print all envs
     // std::env::vars().for_each(|(k, v)| {
     //     info!("env: {}: {}", k, v);
@@ -410,16 +968,64 @@ fn main() {
             let state = app.state::<AppState>();
             state.inner().init_db(&app_data_dir)?;
 
+            if session_daemon_enabled {
+                let socket = app_data_dir.join(DAEMON_SOCKET_NAME);
+                if let Err(err) = state.inner().enable_session_daemon(socket) {
+                    // the terminals still work, they just don't survive the app
+                    error!("enable session daemon error: {}", err);
+                }
+            }
+
             let theme_path = app
                 .path_resolver()
                 .resolve_resource("themes")
                 .expect("failed to resolve resource");
 
-            state.inner().load_themes(&theme_path)?;
+            let user_theme_path = theme_context::user_themes_dir(&app_data_dir);
+            let _ = fs::create_dir_all(&user_theme_path);
+            // the user themes override the bundled ones
+            let theme_dirs = vec![theme_path, user_theme_path];
+            state.inner().load_themes(&theme_dirs);
 
             let app_handle = app.handle();
 
-            shell_integration::init_shell_integration(&app_handle, &local_shell_path).unwrap();
+            let theme_event_handler = MainThemeEventHandler {
+                app_handle: app_handle.clone(),
+            };
+            if let Err(err) = state
+                .inner()
+                .watch_themes(theme_dirs, Box::new(theme_event_handler))
+            {
+                error!("watch themes error: {}", err);
+            }
+
+            state.inner().set_settings_error(settings_error);
+            let settings_event_handler = MainSettingsEventHandler {
+                app_handle: app_handle.clone(),
+            };
+            if let Err(err) = state
+                .inner()
+                .watch_settings(&app_data_dir, Box::new(settings_event_handler))
+            {
+                error!("watch settings error: {}", err);
+            }
+
+            let statistics = state.inner().settings().statistics.clone();
+            let sampler = ResourceSampler::start(
+                SamplerOptions {
+                    interval: Duration::from_millis(statistics.interval.0.max(100)),
+                    history_len: statistics.history.0,
+                    memory_alert_bytes: statistics.memory_alert.map(|mb| mb * 1024 * 1024),
+                    cpu_alert_percent: statistics.cpu_alert,
+                    cpu_alert_duration: Duration::from_secs(statistics.cpu_alert_duration.0),
+                },
+                Box::new(MainResourceSamplerEventHandler {
+                    app_handle: app_handle.clone(),
+                }),
+            );
+            app.manage(sampler);
+
+            shell_integration::init_shell_integration(&app_handle, &local_shell_path, shell_kind).unwrap();
 
             updater::spawn_thread_to_check_update(app_handle);
 
@@ -428,13 +1034,37 @@ fn main() {
         .invoke_handler(tauri::generate_handler![
             fetch_init_data,
             new_terminal,
+            restart_terminal,
+            discard_sessions,
             send_terminal_data,
             get_terminal_statistics,
+            get_terminal_stats_history,
+            get_terminal_ports,
+            get_terminal_info,
+            get_terminal_screen,
+            get_terminal_scrollback,
             terminal_set_options,
+            terminal_set_output_encoding,
+            ack_pty_output,
+            get_pty_output_metrics,
             remove_terminal,
-            get_a_theme,
+            can_close,
+            start_recording,
+            stop_recording,
+            start_playback,
+            set_playback_paused,
+            set_playback_speed,
+            stop_playback,
+            current_theme,
+            list_themes,
+            get_theme,
+            set_theme,
             resize_pty,
             launch_url,
+            update_setting,
+            reset_setting,
+            open_settings_file,
+            trust_project_settings,
             fs_ls,
             fs_read_all,
             fs_stat,
@@ -448,10 +1078,27 @@ fn main() {
             open_context_menu,
             console_log,
             install_script,
+            uninstall_script,
+            shell_integration_status,
         ])
         .on_menu_event(|event| match event.menu_item_id() {
+            "quit" => {
+                let window = event.window();
+                if confirm_quit(window, |window| close_windows(&window.app_handle())) {
+                    close_windows(&window.app_handle());
+                }
+            }
             "settings" => {
-                info!("settings")
+                // the window opens the settings on the same event
+                let window = event.window();
+                if let Err(err) = window.show().and_then(|_| window.set_focus()) {
+                    error!("show settings error: {}", err);
+                }
+            }
+            "open-settings-file" => {
+                if let Err(err) = open_settings_file() {
+                    error!("open settings file error: {}", err);
+                }
             }
             "changelog" => {
                 let result = open::that(CHANGELOG_LINK);
@@ -473,6 +1120,34 @@ fn main() {
             }
             _ => {}
         })
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .on_window_event(|event| {
+            if let WindowEvent::CloseRequested { api, .. } = event.event() {
+                let can_close = confirm_quit(event.window(), |window| {
+                    if let Err(err) = window.close() {
+                        error!("close window error: {}", err);
+                    }
+                });
+                if !can_close {
+                    api.prevent_close();
+                }
+            }
+        })
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| {
+            if let RunEvent::ExitRequested { api, .. } = &event {
+                if let Some(window) = app_handle.get_window("main") {
+                    if !confirm_quit(&window, |window| close_windows(&window.app_handle())) {
+                        api.prevent_exit();
+                    }
+                }
+            }
+            if let RunEvent::Exit = event {
+                let state = app_handle.state::<AppState>();
+                if let Err(err) = state.inner().save_sessions() {
+                    error!("save sessions error: {}", err);
+                }
+                state.inner().detach_terminals();
+            }
+        });
 }
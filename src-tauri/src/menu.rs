@@ -1,4 +1,36 @@
-use tauri::{AboutMetadata, CustomMenuItem, Menu, MenuItem, Submenu};
+use crate::settings::Settings;
+use tauri::{AboutMetadata, CustomMenuItem, Menu, MenuHandle, MenuItem, Runtime, Submenu};
+
+/// Prefix of the menu item ids which open a new tab with a profile,
+/// followed by the index of the profile in `[[profiles]]`.
+pub const NEW_TAB_PROFILE_PREFIX: &str = "new-tab-profile:";
+
+/// The items of a menu can't be added or removed once the window is created,
+/// so a fixed number of profile items is made and renamed when the settings change.
+const PROFILE_MENU_SLOTS: usize = 9;
+
+fn profile_menu_id(index: usize) -> String {
+    format!("{}{}", NEW_TAB_PROFILE_PREFIX, index)
+}
+
+fn profile_menu_title(index: usize, settings: &Settings) -> String {
+    match settings.profiles.get(index) {
+        Some(profile) => format!("New {} Tab", profile.name),
+        None => "No Profile".to_string(),
+    }
+}
+
+/// Rename the profile items after `settings.toml` is reloaded,
+/// the items without a profile are disabled.
+pub fn update_profile_menu<R: Runtime>(menu: &MenuHandle<R>, settings: &Settings) -> tauri::Result<()> {
+    for index in 0..PROFILE_MENU_SLOTS {
+        if let Some(item) = menu.try_get_item(&profile_menu_id(index)) {
+            item.set_title(profile_menu_title(index, settings))?;
+            item.set_enabled(index < settings.profiles.len())?;
+        }
+    }
+    Ok(())
+}
 
 /// Creates a menu filled with default menu items and submenus.
 ///
@@ -52,12 +84,15 @@ use tauri::{AboutMetadata, CustomMenuItem, Menu, MenuItem, Submenu};
 ///     - Zoom
 ///     - Separator
 ///     - CloseWindow
-pub fn generate_menu(#[allow(unused)] app_name: &str) -> Menu {
+pub fn generate_menu(#[allow(unused)] app_name: &str, settings: &Settings) -> Menu {
     let mut menu = Menu::new();
     #[cfg(target_os = "macos")]
     {
         let mut settings_menu_item = CustomMenuItem::new("settings", "Settings");
         settings_menu_item.keyboard_accelerator = Some("CmdOrCtrl+,".to_string());
+        // the native item quits without asking about the running processes
+        let mut quit_menu_item = CustomMenuItem::new("quit", format!("Quit {}", app_name));
+        quit_menu_item.keyboard_accelerator = Some("CmdOrCtrl+Q".to_string());
         menu = menu.add_submenu(Submenu::new(
             app_name,
             Menu::new()
@@ -67,6 +102,7 @@ pub fn generate_menu(#[allow(unused)] app_name: &str) -> Menu {
                 ))
                 .add_native_item(MenuItem::Separator)
                 .add_item(settings_menu_item)
+                .add_item(CustomMenuItem::new("open-settings-file", "Open settings.toml"))
                 .add_native_item(MenuItem::Separator)
                 .add_native_item(MenuItem::Services)
                 .add_native_item(MenuItem::Separator)
@@ -74,7 +110,7 @@ pub fn generate_menu(#[allow(unused)] app_name: &str) -> Menu {
                 .add_native_item(MenuItem::HideOthers)
                 .add_native_item(MenuItem::ShowAll)
                 .add_native_item(MenuItem::Separator)
-                .add_native_item(MenuItem::Quit),
+                .add_item(quit_menu_item),
         ));
     }
 
@@ -85,8 +121,23 @@ pub fn generate_menu(#[allow(unused)] app_name: &str) -> Menu {
         let mut close_tab_menu_item = CustomMenuItem::new("close-tab", "Close Tab");
         close_tab_menu_item.keyboard_accelerator = Some("CmdOrCtrl+W".to_string());
 
-        let menu = Menu::new()
-            .add_item(new_tab_menu_item)
+        let mut menu = Menu::new().add_item(new_tab_menu_item);
+
+        let mut profile_menu = Menu::new();
+        for index in 0..PROFILE_MENU_SLOTS {
+            let mut item =
+                CustomMenuItem::new(profile_menu_id(index), profile_menu_title(index, settings));
+            if index >= settings.profiles.len() {
+                item = item.disabled();
+            }
+            profile_menu = profile_menu.add_item(item);
+        }
+        menu = menu.add_submenu(Submenu::new("New Tab with Profile", profile_menu));
+
+        menu = menu
+            .add_native_item(MenuItem::Separator)
+            .add_item(CustomMenuItem::new("toggle-recording", "Start/Stop Recording"))
+            .add_item(CustomMenuItem::new("play-recording", "Play Recording..."))
             .add_native_item(MenuItem::Separator)
             .add_item(close_tab_menu_item);
         menu
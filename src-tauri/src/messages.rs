@@ -1,36 +1,100 @@
-use crate::settings::Settings;
+use crate::process_statistics::{ForegroundProcess, ListeningPort, TerminalSample};
+use crate::settings::{ExitAction, Settings, SettingsError};
+use crate::theme_context::ThemeError;
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct InitMessage {
     pub home_dir: String,
     pub force_onboarding: bool,
+    /// The values saved with `ui_store`, by key.
+    #[schemars(with = "BTreeMap<String, serde_json::Value>")]
     pub ui_stores: serde_json::Value,
     pub settings: Settings,
+    /// Set if `settings` are the defaults because `settings.toml` is invalid.
+    pub settings_error: Option<SettingsError>,
+    /// The theme files which are skipped.
+    pub theme_errors: Vec<ThemeError>,
+    /// The tabs left open when the app quit last time.
+    pub restored_sessions: Vec<SavedSession>,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SavedSession {
+    pub id: String,
+    pub profile: Option<String>,
+    pub cwd: Option<String>,
+    pub scrollback: Option<String>,
+}
+
+/// How the PTY output is transported in `PtyResponse.data`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum PtyEncoding {
+    /// Decoded as UTF-8, invalid sequences are replaced with U+FFFD.
+    #[default]
+    Utf8,
+    /// The raw bytes encoded with base64, for programs
+    /// which don't output UTF-8.
+    Base64,
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct PtyResponse {
     pub id: String,
     pub data: String,
+    pub encoding: PtyEncoding,
+    /// Acked with `ack_pty_output` when the data is written.
+    pub offset: u64,
+}
+
+#[derive(Clone, Debug, Default, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct OutputMetrics {
+    pub received_bytes: u64,
+    pub sent_bytes: u64,
+    pub batches: u64,
+    /// How many times the reader paused because the frontend was behind.
+    pub throttled_count: u64,
+    pub throttled_ms: u64,
+    /// How many times the reader resumed without an ack.
+    pub ack_timeouts: u64,
+    /// The output which couldn't be sent to the frontend.
+    pub dropped_bytes: u64,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct ThemeResponse {
     pub name: String,
     pub json_content: Option<String>,
 }
 
-#[derive(Clone, Serialize)]
+/// Sent when the themes are reloaded, empty if every theme is valid.
+#[derive(Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ThemeErrorsMessage {
+    pub errors: Vec<ThemeError>,
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct PtyExitMessage {
     pub id: String,
+    pub exit_code: u32,
+    /// e.g. "Segmentation fault", when the shell is killed by a signal.
+    pub signal: Option<String>,
+    /// How long the shell has been running.
+    pub duration_ms: u64,
+    pub action: ExitAction,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct FileItem {
     pub filename: String,
@@ -38,13 +102,13 @@ pub(crate) struct FileItem {
     pub is_dir: bool,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct FsLsResponse {
     pub content: Vec<FileItem>,
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct FsStatResponse {
     pub modified_time: u64,
@@ -60,7 +124,7 @@ impl Default for FsLsResponse {
     }
 }
 
-#[derive(Clone, Serialize)]
+#[derive(Clone, Serialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct SpawnResult {
     pub output: String,
@@ -68,34 +132,61 @@ pub(crate) struct SpawnResult {
     pub code: Option<i32>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct BatchTestFilesReq {
     pub current_dir: String,
     pub files: Vec<String>,
 }
 
-#[derive(Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Serialize, Deserialize, PartialEq, Eq, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct TermOptions {
     pub path: String,
     pub watch_dirs: bool,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ShellOptions {
+    pub shell: Option<String>,
+    pub args: Option<Vec<String>>,
+    pub env: Option<BTreeMap<String, String>>,
+}
+
+#[derive(Clone, Default, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NewTerminalResponse {
+    pub title: Option<String>,
+    pub theme: Option<String>,
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ShellIntegrationStatus {
+    pub shell: String,
+    pub rc_file: String,
+    pub script_path: String,
+    pub installed: bool,
+    /// Scripts sourced by the rc file which are not the current one,
+    /// e.g. left by an older app bundle.
+    pub stale_paths: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct BatchTestFilesResp {
     pub files: Vec<i32>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct MenuItem {
     pub title: String,
     pub key: String,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct OpenContextMenuReq {
     pub id: String,
@@ -103,23 +194,215 @@ pub struct OpenContextMenuReq {
     pub position: Vec<f64>,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct OpenContextMenuClickedMessage {
     pub id: String,
     pub key: String,
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct FsChangedMessage {
     pub id: String,
     pub paths: Vec<String>,
 }
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum ShellPhase {
+    #[default]
+    Unknown,
+    /// OSC 133;A is received, the shell is printing the prompt.
+    Prompt,
+    /// OSC 133;B is received, the user is typing a command.
+    Input,
+    /// OSC 133;C is received, the command is running.
+    Running,
+}
+
+#[derive(Clone, Debug, Default, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TerminalInfo {
+    pub id: String,
+    pub cwd: Option<String>,
+    pub title: Option<String>,
+    pub phase: ShellPhase,
+    pub last_exit_code: Option<i32>,
+    /// Offsets of the latest OSC 133 marks in the output stream,
+    /// on the same basis as `PtyResponse::offset`.
+    pub prompt_start: Option<u64>,
+    pub command_start: Option<u64>,
+    pub output_start: Option<u64>,
+    pub output_end: Option<u64>,
+    /// Unknown for a session in the daemon.
+    pub foreground: Option<ForegroundProcess>,
+}
+
+/// The settings of a terminal after it has entered or left a project.
+#[derive(Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ProjectSettingsChangedMessage {
+    pub id: String,
+    /// The project file applied, none outside of a trusted project.
+    pub path: Option<String>,
+    pub settings: Settings,
+    /// The keys a project file is not allowed to set.
+    pub ignored_keys: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// A project file is seen for the first time, or has changed since it's trusted.
+#[derive(Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ProjectTrustRequestMessage {
+    pub id: String,
+    pub path: String,
+    pub content: String,
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ForegroundChangedMessage {
+    pub id: String,
+    pub process: ForegroundProcess,
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CwdChangedMessage {
+    pub id: String,
+    pub cwd: String,
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct TitleChangedMessage {
+    pub id: String,
+    pub title: String,
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CommandFinishedMessage {
+    pub id: String,
+    pub exit_code: Option<i32>,
+    pub duration_ms: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct RunningProcess {
+    pub name: String,
+    pub count: u32,
+}
+
+#[derive(Clone, Debug, Default, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CanCloseResponse {
+    pub can_close: bool,
+    /// The processes running in the foreground of the terminals, by name.
+    pub processes: Vec<RunningProcess>,
+    /// e.g. "vim and cargo are still running", to confirm with.
+    pub message: Option<String>,
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PlaybackInfo {
+    pub cols: u16,
+    pub rows: u16,
+    /// The length of the recording in seconds.
+    pub duration: f64,
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PlaybackResizedMessage {
+    pub id: String,
+    pub cols: u16,
+    pub rows: u16,
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PlaybackFinishedMessage {
+    pub id: String,
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct StatsUpdatedMessage {
+    pub samples: Vec<TerminalSample>,
+}
+
+#[derive(Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PortsChangedMessage {
+    pub id: String,
+    pub ports: Vec<ListeningPort>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase", tag = "type")]
+pub(crate) enum CellColor {
+    Indexed { index: u8 },
+    Rgb { r: u8, g: u8, b: u8 },
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ScreenCell {
+    pub contents: String,
+    /// `None` for the default color.
+    pub fg: Option<CellColor>,
+    pub bg: Option<CellColor>,
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub inverse: bool,
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ScreenLine {
+    pub text: String,
+    /// Only present if styled cells are requested.
+    pub cells: Option<Vec<ScreenCell>>,
+}
+
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ScreenResponse {
+    pub rows: u16,
+    pub cols: u16,
+    pub cursor_row: u16,
+    pub cursor_col: u16,
+    /// The lines of the scrollback and the visible screen.
+    pub total_lines: usize,
+    /// The index of the first line in `lines`.
+    pub start: usize,
+    pub lines: Vec<ScreenLine>,
+}
+
 pub(crate) mod push_event {
     pub static PTY_OUTPUT: &str = "pty-output";
     pub static PTY_EXIT: &str = "pty-exit";
     pub static FS_CHANGED: &str = "fs-changed";
+    pub static CWD_CHANGED: &str = "cwd-changed";
+    pub static TITLE_CHANGED: &str = "title-changed";
+    pub static FOREGROUND_CHANGED: &str = "foreground-changed";
+    pub static SETTINGS_CHANGED: &str = "settings-changed";
+    pub static SETTINGS_ERROR: &str = "settings-error";
+    pub static THEME_CHANGED: &str = "theme-changed";
+    pub static THEME_ERRORS: &str = "theme-errors";
+    pub static PROJECT_SETTINGS_CHANGED: &str = "project-settings-changed";
+    pub static PROJECT_TRUST_REQUESTED: &str = "project-trust-requested";
+    pub static COMMAND_FINISHED: &str = "command-finished";
+    pub static PLAYBACK_RESIZED: &str = "playback-resized";
+    pub static PLAYBACK_FINISHED: &str = "playback-finished";
+    pub static STATS_UPDATED: &str = "stats-updated";
+    pub static RESOURCE_ALERT: &str = "resource-alert";
+    pub static PORTS_CHANGED: &str = "ports-changed";
     pub static CONTEXT_MENU_CLICKED: &str = "context-menu-clicked";
 }
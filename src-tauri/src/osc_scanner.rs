@@ -0,0 +1,269 @@
+use log::warn;
+
+const ESC: u8 = 0x1b;
+const BEL: u8 = 0x07;
+
+// OSC sequences longer than this are dropped,
+// a broken program must not make us buffer forever.
+const MAX_OSC_LEN: usize = 4096;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum OscEvent {
+    /// OSC 7, reported by the shell integration scripts.
+    CwdChanged(String),
+    /// OSC 0 and OSC 2.
+    TitleChanged(String),
+    /// OSC 133;A
+    PromptStart,
+    /// OSC 133;B
+    CommandStart,
+    /// OSC 133;C
+    CommandExecuted,
+    /// OSC 133;D, with the exit code if the shell reports one.
+    CommandFinished(Option<i32>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScanState {
+    Ground,
+    Escape,
+    Osc,
+    OscEscape,
+}
+
+/// A streaming scanner for the OSC sequences in the PTY output.
+///
+/// The sequences may be split across reads,
+/// the partial state is kept until the next call of `feed`.
+pub(crate) struct OscScanner {
+    state: ScanState,
+    buffer: Vec<u8>,
+    overflow: bool,
+}
+
+impl OscScanner {
+    pub(crate) fn new() -> OscScanner {
+        OscScanner {
+            state: ScanState::Ground,
+            buffer: Vec::new(),
+            overflow: false,
+        }
+    }
+
+    /// Returns the events with the offset in `data` right after their terminator.
+    pub(crate) fn feed(&mut self, data: &[u8]) -> Vec<(usize, OscEvent)> {
+        let mut events = Vec::new();
+
+        for (index, &byte) in data.iter().enumerate() {
+            match self.state {
+                ScanState::Ground => {
+                    if byte == ESC {
+                        self.state = ScanState::Escape;
+                    }
+                }
+                ScanState::Escape => {
+                    self.state = match byte {
+                        b']' => {
+                            self.buffer.clear();
+                            self.overflow = false;
+                            ScanState::Osc
+                        }
+                        ESC => ScanState::Escape,
+                        _ => ScanState::Ground,
+                    };
+                }
+                ScanState::Osc => match byte {
+                    BEL => self.finish(&mut events, index + 1),
+                    ESC => self.state = ScanState::OscEscape,
+                    _ => self.push(byte),
+                },
+                ScanState::OscEscape => {
+                    if byte == b'\\' {
+                        self.finish(&mut events, index + 1);
+                    } else {
+                        // Not a string terminator, the sequence is cancelled
+                        // and the escape starts a new one.
+                        self.buffer.clear();
+                        self.state = ScanState::Escape;
+                        if byte == b']' {
+                            self.overflow = false;
+                            self.state = ScanState::Osc;
+                        }
+                    }
+                }
+            }
+        }
+
+        events
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.buffer.len() >= MAX_OSC_LEN {
+            self.overflow = true;
+            return;
+        }
+        self.buffer.push(byte);
+    }
+
+    fn finish(&mut self, events: &mut Vec<(usize, OscEvent)>, offset: usize) {
+        self.state = ScanState::Ground;
+
+        if self.overflow {
+            warn!("drop OSC sequence longer than {} bytes", MAX_OSC_LEN);
+            self.buffer.clear();
+            return;
+        }
+
+        let payload = String::from_utf8_lossy(&self.buffer).to_string();
+        self.buffer.clear();
+
+        if let Some(event) = parse_osc(&payload) {
+            events.push((offset, event));
+        }
+    }
+}
+
+fn parse_osc(payload: &str) -> Option<OscEvent> {
+    let (code, rest) = payload.split_once(';').unwrap_or((payload, ""));
+
+    match code {
+        "0" | "2" => Some(OscEvent::TitleChanged(rest.to_string())),
+        "7" => parse_file_url(rest).map(OscEvent::CwdChanged),
+        "133" => {
+            let mut params = rest.split(';');
+            match params.next()? {
+                "A" => Some(OscEvent::PromptStart),
+                "B" => Some(OscEvent::CommandStart),
+                "C" => Some(OscEvent::CommandExecuted),
+                "D" => {
+                    let exit_code = params.next().and_then(|code| code.parse::<i32>().ok());
+                    Some(OscEvent::CommandFinished(exit_code))
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Parse `file://hostname/percent/encoded/path` to a path.
+fn parse_file_url(url: &str) -> Option<String> {
+    let without_scheme = url.strip_prefix("file://")?;
+    let path_start = without_scheme.find('/')?;
+    Some(percent_decode(&without_scheme[path_start..]))
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut result = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        if bytes[index] == b'%' && index + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[index + 1..index + 3]).ok();
+            if let Some(byte) = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                result.push(byte);
+                index += 3;
+                continue;
+            }
+        }
+        result.push(bytes[index]);
+        index += 1;
+    }
+
+    String::from_utf8_lossy(&result).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(data: &[u8]) -> Vec<(usize, OscEvent)> {
+        OscScanner::new().feed(data)
+    }
+
+    #[test]
+    fn cwd() {
+        let data = b"ls\x1b]7;file://host/Users/me/my%20dir\x07$ ";
+        assert_eq!(
+            events(data),
+            vec![(
+                data.len() - 2,
+                OscEvent::CwdChanged("/Users/me/my dir".to_string())
+            )]
+        );
+    }
+
+    #[test]
+    fn title_with_string_terminator() {
+        assert_eq!(
+            events(b"\x1b]0;vim\x1b\\"),
+            vec![(9, OscEvent::TitleChanged("vim".to_string()))]
+        );
+        assert_eq!(
+            events(b"\x1b]2;top\x07"),
+            vec![(8, OscEvent::TitleChanged("top".to_string()))]
+        );
+    }
+
+    #[test]
+    fn command_marks() {
+        let data = b"\x1b]133;A\x07$ \x1b]133;B\x07ls\n\x1b]133;C\x07out\n\x1b]133;D;2\x07";
+        let result: Vec<OscEvent> = events(data).into_iter().map(|(_, event)| event).collect();
+        assert_eq!(
+            result,
+            vec![
+                OscEvent::PromptStart,
+                OscEvent::CommandStart,
+                OscEvent::CommandExecuted,
+                OscEvent::CommandFinished(Some(2)),
+            ]
+        );
+        assert_eq!(
+            events(b"\x1b]133;D\x07"),
+            vec![(8, OscEvent::CommandFinished(None))]
+        );
+    }
+
+    #[test]
+    fn split_across_reads() {
+        let data = b"abc\x1b]7;file://host/tmp\x1b\\def";
+        for split in 0..data.len() {
+            let mut scanner = OscScanner::new();
+            let mut result = scanner.feed(&data[..split]);
+            result.extend(
+                scanner
+                    .feed(&data[split..])
+                    .into_iter()
+                    .map(|(offset, event)| (split + offset, event)),
+            );
+            assert_eq!(
+                result,
+                vec![(data.len() - 3, OscEvent::CwdChanged("/tmp".to_string()))],
+                "split at {}",
+                split
+            );
+        }
+    }
+
+    #[test]
+    fn unknown_and_cancelled() {
+        assert!(events(b"\x1b]52;c;aGVsbG8=\x07").is_empty());
+        assert!(events(b"\x1b[31mred\x1b[0m").is_empty());
+        // an escape which is not a terminator cancels the sequence
+        assert_eq!(
+            events(b"\x1b]0;lost\x1b]2;kept\x07"),
+            vec![(17, OscEvent::TitleChanged("kept".to_string()))]
+        );
+    }
+
+    #[test]
+    fn overflow() {
+        let mut data = b"\x1b]0;".to_vec();
+        data.extend(vec![b'x'; MAX_OSC_LEN + 1]);
+        data.push(BEL);
+        data.extend(b"\x1b]2;next\x07");
+        let result: Vec<OscEvent> = events(&data).into_iter().map(|(_, event)| event).collect();
+        assert_eq!(result, vec![OscEvent::TitleChanged("next".to_string())]);
+    }
+}
@@ -0,0 +1,252 @@
+use crate::messages::OutputMetrics;
+use log::warn;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+// The output is coalesced for at most this long before it's emitted.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(8);
+
+// A batch is emitted right away when it's this large.
+const MAX_BATCH_BYTES: usize = 256 * 1024;
+
+// The reader pauses when this many bytes are buffered or not acked by the frontend,
+// and resumes when it drops under `LOW_WATERMARK`.
+const HIGH_WATERMARK: u64 = 2 * 1024 * 1024;
+const LOW_WATERMARK: u64 = 512 * 1024;
+
+// The frontend may never ack, e.g. the webview is reloading,
+// don't pause the child forever.
+const ACK_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct OutputState {
+    data: Vec<u8>,
+    first_pushed_at: Option<Instant>,
+    sent_bytes: u64,
+    acked_bytes: u64,
+    closed: bool,
+    metrics: OutputMetrics,
+}
+
+impl OutputState {
+    fn unacked(&self) -> u64 {
+        self.sent_bytes - self.acked_bytes + self.data.len() as u64
+    }
+}
+
+/// The PTY output between the reader thread and the thread emitting it.
+///
+/// The reader pushes what it reads, the emitter pops it in batches
+/// and the frontend acks the batches it has written.
+pub(crate) struct OutputBuffer {
+    state: Mutex<OutputState>,
+    readable: Condvar,
+    writable: Condvar,
+    ack_timeout: Duration,
+}
+
+impl OutputBuffer {
+    pub(crate) fn new() -> OutputBuffer {
+        OutputBuffer {
+            state: Mutex::new(OutputState {
+                data: Vec::new(),
+                first_pushed_at: None,
+                sent_bytes: 0,
+                acked_bytes: 0,
+                closed: false,
+                metrics: OutputMetrics::default(),
+            }),
+            readable: Condvar::new(),
+            writable: Condvar::new(),
+            ack_timeout: ACK_TIMEOUT,
+        }
+    }
+
+    /// Blocks while the frontend is behind, so the reader stops
+    /// reading and the child is slowed down by the PTY.
+    pub(crate) fn push(&self, data: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+
+        if state.unacked() > HIGH_WATERMARK && !state.closed {
+            let throttled_at = Instant::now();
+            state.metrics.throttled_count += 1;
+
+            while state.unacked() > LOW_WATERMARK && !state.closed {
+                let (next, timeout) = self.writable.wait_timeout(state, self.ack_timeout).unwrap();
+                state = next;
+                if timeout.timed_out() {
+                    warn!("no ack in {:?}, resume reading", self.ack_timeout);
+                    state.metrics.ack_timeouts += 1;
+                    state.acked_bytes = state.sent_bytes;
+                    break;
+                }
+            }
+
+            state.metrics.throttled_ms += throttled_at.elapsed().as_millis() as u64;
+        }
+
+        if state.first_pushed_at.is_none() {
+            state.first_pushed_at = Some(Instant::now());
+        }
+        state.data.extend_from_slice(data);
+        state.metrics.received_bytes += data.len() as u64;
+
+        self.readable.notify_one();
+    }
+
+    /// Blocks until a batch is ready, returns the batch and
+    /// the offset of its end in the output stream.
+    /// Returns `None` when the buffer is closed and drained.
+    pub(crate) fn pop_batch(&self) -> Option<(Vec<u8>, u64)> {
+        let mut state = self.state.lock().unwrap();
+
+        loop {
+            if let Some(first_pushed_at) = state.first_pushed_at {
+                let elapsed = first_pushed_at.elapsed();
+                if state.closed || elapsed >= FLUSH_INTERVAL || state.data.len() >= MAX_BATCH_BYTES {
+                    let batch = std::mem::take(&mut state.data);
+                    state.first_pushed_at = None;
+                    state.sent_bytes += batch.len() as u64;
+                    state.metrics.sent_bytes = state.sent_bytes;
+                    state.metrics.batches += 1;
+                    return Some((batch, state.sent_bytes));
+                }
+
+                let (next, _) = self
+                    .readable
+                    .wait_timeout(state, FLUSH_INTERVAL - elapsed)
+                    .unwrap();
+                state = next;
+                continue;
+            }
+
+            if state.closed {
+                return None;
+            }
+
+            state = self.readable.wait(state).unwrap();
+        }
+    }
+
+    /// The frontend has written the output up to `offset`.
+    pub(crate) fn ack(&self, offset: u64) {
+        let mut state = self.state.lock().unwrap();
+        if offset > state.acked_bytes {
+            state.acked_bytes = offset.min(state.sent_bytes);
+        }
+        self.writable.notify_all();
+    }
+
+    /// A batch popped from the buffer couldn't be sent.
+    pub(crate) fn drop_sent(&self, bytes: usize) {
+        let mut state = self.state.lock().unwrap();
+        state.metrics.dropped_bytes += bytes as u64;
+    }
+
+    pub(crate) fn close(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        self.readable.notify_all();
+        self.writable.notify_all();
+    }
+
+    pub(crate) fn metrics(&self) -> OutputMetrics {
+        let state = self.state.lock().unwrap();
+        state.metrics.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    fn buffer(ack_timeout: Duration) -> OutputBuffer {
+        OutputBuffer {
+            ack_timeout,
+            ..OutputBuffer::new()
+        }
+    }
+
+    #[test]
+    fn coalesce_small_pushes() {
+        let output = OutputBuffer::new();
+        output.push(b"a");
+        output.push(b"b");
+        output.push(b"c");
+        assert_eq!(output.pop_batch(), Some((b"abc".to_vec(), 3)));
+        output.push(b"d");
+        output.close();
+        assert_eq!(output.pop_batch(), Some((b"d".to_vec(), 4)));
+        assert_eq!(output.pop_batch(), None);
+
+        let metrics = output.metrics();
+        assert_eq!(metrics.received_bytes, 4);
+        assert_eq!(metrics.sent_bytes, 4);
+        assert_eq!(metrics.batches, 2);
+    }
+
+    #[test]
+    fn resume_on_ack() {
+        let output = Arc::new(buffer(Duration::from_secs(60)));
+        output.push(&vec![b'x'; HIGH_WATERMARK as usize + 1]);
+        let (_, offset) = output.pop_batch().unwrap();
+
+        let reader_output = output.clone();
+        let reader = std::thread::spawn(move || reader_output.push(b"y"));
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!reader.is_finished());
+
+        output.ack(offset);
+        reader.join().unwrap();
+        let metrics = output.metrics();
+        assert_eq!(metrics.throttled_count, 1);
+        assert_eq!(metrics.ack_timeouts, 0);
+    }
+
+    #[test]
+    fn resume_on_ack_timeout() {
+        let ack_timeout = Duration::from_millis(50);
+        let output = buffer(ack_timeout);
+        output.push(&vec![b'x'; HIGH_WATERMARK as usize + 1]);
+        output.pop_batch().unwrap();
+
+        // never acked
+        let started_at = Instant::now();
+        output.push(b"y");
+        assert!(started_at.elapsed() >= ack_timeout);
+
+        let metrics = output.metrics();
+        assert_eq!(metrics.throttled_count, 1);
+        assert_eq!(metrics.ack_timeouts, 1);
+        assert_eq!(metrics.received_bytes, HIGH_WATERMARK + 2);
+
+        // the unacked output is forgotten, the next push doesn't wait
+        let started_at = Instant::now();
+        output.push(b"z");
+        assert!(started_at.elapsed() < ack_timeout);
+        assert_eq!(output.metrics().throttled_count, 1);
+    }
+
+    #[test]
+    fn close_while_throttled() {
+        let output = Arc::new(buffer(Duration::from_secs(60)));
+        output.push(&vec![b'x'; HIGH_WATERMARK as usize + 1]);
+        output.pop_batch().unwrap();
+
+        let reader_output = output.clone();
+        let reader = std::thread::spawn(move || reader_output.push(b"y"));
+        std::thread::sleep(Duration::from_millis(50));
+        output.close();
+        reader.join().unwrap();
+        assert_eq!(output.metrics().ack_timeouts, 0);
+    }
+
+    #[test]
+    fn dropped() {
+        let output = OutputBuffer::new();
+        output.push(b"abc");
+        let (batch, _) = output.pop_batch().unwrap();
+        output.drop_sent(batch.len());
+        assert_eq!(output.metrics().dropped_bytes, 3);
+    }
+}
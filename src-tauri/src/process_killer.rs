@@ -0,0 +1,235 @@
+use log::{debug, info};
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
+use sysinfo::{Pid, PidExt, ProcessExt, ProcessRefreshKind, ProcessStatus, System, SystemExt};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Ends a shell with everything it has started.
+///
+/// The foreground process group gets SIGHUP first, as if the terminal
+/// is hung up. What's still alive after `grace_period`, e.g. background
+/// jobs or processes ignoring SIGHUP, gets SIGTERM, then SIGKILL.
+pub(crate) struct ProcessKiller {
+    shell_pid: u32,
+    foreground_pgid: Option<libc::pid_t>,
+    grace_period: Duration,
+}
+
+impl ProcessKiller {
+    pub(crate) fn new(
+        shell_pid: u32,
+        foreground_pgid: Option<libc::pid_t>,
+        grace_period: Duration,
+    ) -> ProcessKiller {
+        ProcessKiller {
+            shell_pid,
+            foreground_pgid,
+            grace_period,
+        }
+    }
+
+    /// Blocks until every process is gone or SIGKILL is sent.
+    pub(crate) fn kill(&self) {
+        let mut sys = System::new();
+        // Take the tree before anything exits, the orphans are
+        // reparented to init and can't be found from the shell then.
+        let tree = collect_process_tree(&mut sys, self.shell_pid);
+        debug!("kill process tree of {}: {:?}", self.shell_pid, tree);
+
+        if let Some(pgid) = self.foreground_pgid {
+            signal_group(pgid, libc::SIGHUP);
+        }
+        signal_group(self.shell_pid as libc::pid_t, libc::SIGHUP);
+        if self.wait_all_exited(&mut sys, &tree) {
+            return;
+        }
+
+        // The pids are checked again right before they're signaled,
+        // an exited process may have left its pid to another one.
+        info!("process tree of {} is alive, send SIGTERM", self.shell_pid);
+        for process in alive_processes(&mut sys, &tree) {
+            signal_process(process.pid, libc::SIGTERM);
+        }
+        if self.wait_all_exited(&mut sys, &tree) {
+            return;
+        }
+
+        info!("process tree of {} is alive, send SIGKILL", self.shell_pid);
+        for process in alive_processes(&mut sys, &tree) {
+            signal_process(process.pid, libc::SIGKILL);
+        }
+    }
+
+    fn wait_all_exited(&self, sys: &mut System, tree: &[TreeProcess]) -> bool {
+        let started_at = Instant::now();
+        loop {
+            if alive_processes(sys, tree).is_empty() {
+                return true;
+            }
+            if started_at.elapsed() >= self.grace_period {
+                return false;
+            }
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// A process of the tree as it was when the tree was taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TreeProcess {
+    pid: libc::pid_t,
+    parent: Option<libc::pid_t>,
+    start_time: u64,
+}
+
+/// The pid and all the descendants.
+fn collect_process_tree(sys: &mut System, root_pid: u32) -> Vec<TreeProcess> {
+    sys.refresh_processes_specifics(ProcessRefreshKind::new());
+
+    let mut pids = BTreeSet::new();
+    pids.insert(root_pid as libc::pid_t);
+
+    // the parents are not always listed before their children
+    loop {
+        let mut found = false;
+        for (pid, process) in sys.processes() {
+            let pid = pid.as_u32() as libc::pid_t;
+            if pids.contains(&pid) {
+                continue;
+            }
+            let is_child = process.parent().map_or(false, |parent| {
+                pids.contains(&(parent.as_u32() as libc::pid_t))
+            });
+            if is_child {
+                pids.insert(pid);
+                found = true;
+            }
+        }
+        if !found {
+            break;
+        }
+    }
+
+    pids.into_iter()
+        .filter_map(|pid| {
+            let process = sys.process(Pid::from(pid as usize))?;
+            Some(TreeProcess {
+                pid,
+                parent: process
+                    .parent()
+                    .map(|parent| parent.as_u32() as libc::pid_t),
+                start_time: process.start_time(),
+            })
+        })
+        .collect()
+}
+
+/// The processes of the tree which are still running, a zombie has exited
+/// and a pid which is reused by another process is not the same process.
+fn alive_processes(sys: &mut System, tree: &[TreeProcess]) -> Vec<TreeProcess> {
+    tree.iter()
+        .filter(|expected| {
+            let pid = Pid::from(expected.pid as usize);
+            if !sys.refresh_process_specifics(pid, ProcessRefreshKind::new()) {
+                return false;
+            }
+            let process = match sys.process(pid) {
+                Some(process) => process,
+                None => return false,
+            };
+            if process.status() == ProcessStatus::Zombie
+                || process.start_time() != expected.start_time
+            {
+                return false;
+            }
+            // an orphan is reparented out of the tree when its parent exits
+            let parent = process
+                .parent()
+                .map(|parent| parent.as_u32() as libc::pid_t);
+            parent == expected.parent
+                || parent.map_or(true, |parent| tree.iter().all(|other| other.pid != parent))
+        })
+        .copied()
+        .collect()
+}
+
+fn signal_group(pgid: libc::pid_t, signal: libc::c_int) {
+    if pgid > 0 {
+        unsafe {
+            libc::killpg(pgid, signal);
+        }
+    }
+}
+
+fn signal_process(pid: libc::pid_t, signal: libc::c_int) {
+    if pid > 0 {
+        unsafe {
+            libc::kill(pid, signal);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::CommandExt;
+    use std::process::Command;
+
+    fn spawn_shell(script: &str) -> std::process::Child {
+        let child = Command::new("/bin/sh")
+            .args(["-c", script])
+            .process_group(0)
+            .spawn()
+            .unwrap();
+        // let the shell start its children
+        std::thread::sleep(Duration::from_millis(200));
+        child
+    }
+
+    #[test]
+    fn kill_processes_ignoring_sighup() {
+        let mut child = spawn_shell("trap '' HUP; sleep 30 & sleep 30");
+        let mut sys = System::new();
+        let tree = collect_process_tree(&mut sys, child.id());
+        assert_eq!(tree.len(), 3, "{:?}", tree);
+
+        let started_at = Instant::now();
+        ProcessKiller::new(child.id(), None, Duration::from_millis(200)).kill();
+        child.wait().unwrap();
+
+        assert!(started_at.elapsed() < Duration::from_secs(5));
+        std::thread::sleep(Duration::from_millis(100));
+        assert!(alive_processes(&mut sys, &tree).is_empty());
+    }
+
+    #[test]
+    fn zombie_is_not_alive() {
+        let mut child = Command::new("/bin/sh")
+            .args(["-c", "exit 0"])
+            .spawn()
+            .unwrap();
+        // not waited, so it stays a zombie
+        std::thread::sleep(Duration::from_millis(200));
+        let mut sys = System::new();
+        let tree = collect_process_tree(&mut sys, child.id());
+        assert_eq!(tree.len(), 1);
+        assert!(alive_processes(&mut sys, &tree).is_empty());
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn reused_pid_is_not_alive() {
+        let mut child = spawn_shell("sleep 30");
+        let mut sys = System::new();
+        let mut tree = collect_process_tree(&mut sys, child.id());
+        assert_eq!(alive_processes(&mut sys, &tree).len(), tree.len());
+
+        // as if the pid belonged to a process started earlier
+        tree[0].start_time -= 1;
+        assert_eq!(alive_processes(&mut sys, &tree).len(), tree.len() - 1);
+
+        ProcessKiller::new(child.id(), None, Duration::from_millis(200)).kill();
+        child.wait().unwrap();
+    }
+}
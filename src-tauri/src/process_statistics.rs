@@ -1,9 +1,27 @@
-use log::{debug, warn};
+use crate::Result;
+use log::{error, info};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::process::Command;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use sysinfo::{Pid, PidExt, Process, ProcessExt, ProcessRefreshKind, System, SystemExt};
 
 const MAX_LEVEL: u32 = 10;
 
+// The terminals are polled one by one, they share the snapshot
+// taken by the first of them.
+const SNAPSHOT_MAX_AGE: Duration = Duration::from_millis(500);
+
+// `/proc` is read without forking, the ports are scanned on every tick.
+#[cfg(target_os = "linux")]
+const PORT_SCAN_MAX_AGE: Duration = Duration::ZERO;
+// `lsof` is forked, the ports are scanned again when the processes
+// of the terminals change, or after this long for a process which
+// starts listening later.
+#[cfg(not(target_os = "linux"))]
+const PORT_SCAN_MAX_AGE: Duration = Duration::from_secs(10);
+
 #[derive(Default)]
 pub struct StatResultBuilder {
     pub total_children_count: u32,
@@ -37,62 +55,34 @@ impl StatResultBuilder {
         }
     }
 
-    fn build_statistics_by_pid(&mut self, pid: u32, level: u32) {
+    fn build_statistics_by_pid(&mut self, table: &ProcessTable, pid: Pid, level: u32) {
         if level > MAX_LEVEL {
             return;
         }
 
-        let output = Command::new("pgrep")
-            .arg("-P")
-            .arg(pid.to_string())
-            .arg("-l")
-            .output()
-            .ok();
-        if output.is_none() {
-            return;
-        }
-        let output = output.unwrap();
-        let output_str = String::from_utf8_lossy(&output.stdout);
-
-        let output_is_all_whitespace = output_str.trim().is_empty();
-
-        if output_is_all_whitespace {
-            return;
-        }
-
-        let lines = output_str.split("\n");
-
-        for line in lines {
-            let columns = line.split(" ").collect::<Vec<&str>>();
-            if columns.len() < 2 {
-                continue;
-            }
-            let test_pid = columns[0].trim().parse::<u32>();
-            if test_pid.is_err() {
-                warn!("parse error: {}", columns[0]);
-                continue;
-            }
-            let process_name = columns[1].trim();
+        for child_pid in table.children_of(pid) {
+            let process = match table.process(*child_pid) {
+                Some(process) => process,
+                None => continue,
+            };
 
             if level == 1 {
                 self.first_level_children_names
-                    .push(process_name.to_string());
+                    .push(process.name().to_string());
             }
 
-            let pid = test_pid.unwrap();
-
-            let stat = fetch_cpu_mem_by_pid(pid);
+            let stat = table.cpu_mem(process);
 
             self.total_children_count += 1;
             self.cpu_usage += stat.cpu_usage;
             self.mem_usage += stat.mem_usage;
 
-            self.build_statistics_by_pid(pid, level + 1);
+            self.build_statistics_by_pid(table, *child_pid, level + 1);
         }
     }
 }
 
-#[derive(Default, Debug, Serialize, Deserialize, Clone)]
+#[derive(Default, Debug, Serialize, Deserialize, Clone, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct StatResult {
     pub total_children_count: u32,
@@ -101,85 +91,809 @@ pub struct StatResult {
     pub mem_usage: f64,
 }
 
+/// The resources used by a shell and all its descendants at a time.
+#[derive(Default, Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceSample {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub cpu_usage: f64,
+    /// Resident memory.
+    pub mem_bytes: u64,
+    pub mem_usage: f64,
+    pub children_count: u32,
+    /// Read and written since the previous sample.
+    pub read_bytes: u64,
+    pub written_bytes: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TerminalSample {
+    pub id: String,
+    pub sample: ResourceSample,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ResourceAlertKind {
+    Memory,
+    Cpu,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceAlert {
+    pub id: String,
+    pub kind: ResourceAlertKind,
+    pub value: f64,
+    pub threshold: f64,
+    /// e.g. "above 4.0 GB of memory (4.3 GB)"
+    pub message: String,
+}
+
+/// A TCP port listened on by a process of a terminal.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ListeningPort {
+    pub port: u16,
+    /// e.g. "127.0.0.1", "::" or "*"
+    pub address: String,
+    pub pid: u32,
+    pub process_name: String,
+}
+
+/// The leader of the foreground process group of a terminal.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ForegroundProcess {
+    pub pgid: u32,
+    pub name: String,
+    pub argv: Vec<String>,
+    pub cwd: Option<String>,
+    /// The shell itself is in the foreground, i.e. the terminal is idle.
+    pub is_shell: bool,
+}
+
 #[derive(Default, Debug, Clone, Copy)]
 struct CpuMemResult {
     pub cpu_usage: f64,
     pub mem_usage: f64,
 }
 
-fn fetch_cpu_mem_by_pid(pid: u32) -> CpuMemResult {
-    let output = Command::new("ps")
-        .arg("-o")
-        .arg("%cpu,%mem")
-        .arg("-p")
-        .arg(pid.to_string())
-        .output();
-    debug!("<==== output: {:?}", output);
-    if output.is_err() {
-        return CpuMemResult::default();
+/// A snapshot of all the processes, indexed by their parents.
+pub(crate) struct ProcessTable {
+    sys: System,
+    children: HashMap<Pid, Vec<Pid>>,
+    refreshed_at: Instant,
+}
+
+impl ProcessTable {
+    fn new() -> ProcessTable {
+        let mut sys = System::new();
+        sys.refresh_memory();
+        let mut table = ProcessTable {
+            sys,
+            children: HashMap::new(),
+            refreshed_at: Instant::now(),
+        };
+        table.refresh();
+        table
     }
-    let output = output.unwrap();
-    let output_str = String::from_utf8_lossy(&output.stdout);
-    let lines: Vec<&str> = output_str.split('\n').collect();
 
-    if lines.len() < 2 {
-        return CpuMemResult::default();
+    /// The CPU usage is measured since the last refresh,
+    /// so the same `System` is refreshed every time.
+    fn refresh(&mut self) {
+        self.sys.refresh_processes_specifics(
+            ProcessRefreshKind::new().with_cpu().with_disk_usage(),
+        );
+
+        self.children.clear();
+        for (pid, process) in self.sys.processes() {
+            if let Some(parent) = process.parent() {
+                self.children.entry(parent).or_default().push(*pid);
+            }
+        }
+        for children in self.children.values_mut() {
+            children.sort();
+        }
+
+        self.refreshed_at = Instant::now();
     }
 
-    let stat_line = lines[1].trim();
+    pub(crate) fn process(&self, pid: Pid) -> Option<&Process> {
+        self.sys.process(pid)
+    }
 
-    let mut stats = Vec::<f64>::new();
+    pub(crate) fn children_of(&self, pid: Pid) -> &[Pid] {
+        self.children.get(&pid).map_or(&[], |children| children.as_slice())
+    }
 
-    for column in stat_line.split(' ') {
-        let trimmed = column.trim();
-        if trimmed.is_empty() {
-            continue;
+    /// The pids of all the descendants, parents before their children.
+    pub(crate) fn descendants_of(&self, pid: Pid) -> Vec<Pid> {
+        let mut result = Vec::new();
+        let mut index = 0;
+        result.extend_from_slice(self.children_of(pid));
+        while index < result.len() {
+            let children = self.children_of(result[index]);
+            result.extend_from_slice(children);
+            index += 1;
         }
-        let test_stat = trimmed.parse::<f64>();
-        if test_stat.is_err() {
-            continue;
+        result
+    }
+
+    /// Sum the shell and its descendants, the I/O is the total since they started.
+    fn sample_tree(&self, pid: Pid) -> (ResourceSample, u64, u64) {
+        let mut sample = ResourceSample {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |duration| duration.as_millis() as u64),
+            ..ResourceSample::default()
+        };
+        let mut read_total = 0;
+        let mut written_total = 0;
+
+        let descendants = self.descendants_of(pid);
+        sample.children_count = descendants.len() as u32;
+
+        for pid in std::iter::once(pid).chain(descendants) {
+            let process = match self.process(pid) {
+                Some(process) => process,
+                None => continue,
+            };
+            let stat = self.cpu_mem(process);
+            sample.cpu_usage += stat.cpu_usage;
+            sample.mem_usage += stat.mem_usage;
+            sample.mem_bytes += process.memory();
+
+            let disk_usage = process.disk_usage();
+            read_total += disk_usage.total_read_bytes;
+            written_total += disk_usage.total_written_bytes;
         }
-        stats.push(test_stat.unwrap());
+
+        (sample, read_total, written_total)
     }
 
-    if stats.len() < 2 {
-        return CpuMemResult::default();
+    /// The same units as `ps -o %cpu,%mem`.
+    fn cpu_mem(&self, process: &Process) -> CpuMemResult {
+        let total_memory = self.sys.total_memory();
+        let mem_usage = if total_memory > 0 {
+            process.memory() as f64 * 100.0 / total_memory as f64
+        } else {
+            0.0
+        };
+        CpuMemResult {
+            cpu_usage: process.cpu_usage() as f64,
+            mem_usage,
+        }
     }
-    let cpu_usage = stats[0];
-    let mem_usage = stats[1];
+}
+
+static PROCESS_TABLE: Mutex<Option<ProcessTable>> = Mutex::new(None);
 
-    CpuMemResult {
-        cpu_usage,
-        mem_usage,
+/// Run `f` with a snapshot no older than `SNAPSHOT_MAX_AGE`.
+pub(crate) fn with_process_table<T>(f: impl FnOnce(&ProcessTable) -> T) -> T {
+    let mut table = PROCESS_TABLE.lock().unwrap();
+    match table.as_mut() {
+        Some(table) if table.refreshed_at.elapsed() >= SNAPSHOT_MAX_AGE => table.refresh(),
+        Some(_) => (),
+        None => *table = Some(ProcessTable::new()),
     }
+    f(table.as_ref().unwrap())
 }
 
 pub(crate) fn fetch_process_statistics_by_pid(pid: u32) -> StatResult {
-    let mut builder = StatResultBuilder::new();
+    with_process_table(|table| {
+        let mut builder = StatResultBuilder::new();
+
+        builder.build_statistics_by_pid(table, Pid::from_u32(pid), 1);
+
+        builder.build()
+    })
+}
+
+/// `None` if the leader of the group is not in the snapshot yet, or has exited.
+pub(crate) fn fetch_foreground_process(pgid: u32, shell_pid: Option<u32>) -> Option<ForegroundProcess> {
+    with_process_table(|table| {
+        let process = table.process(Pid::from_u32(pgid))?;
+        let cwd = process.cwd();
+        Some(ForegroundProcess {
+            pgid,
+            name: process.name().to_string(),
+            argv: process.cmd().to_vec(),
+            cwd: if cwd.as_os_str().is_empty() {
+                None
+            } else {
+                Some(cwd.to_string_lossy().to_string())
+            },
+            is_shell: shell_pid == Some(pgid),
+        })
+    })
+}
+
+pub(crate) struct SamplerOptions {
+    pub interval: Duration,
+    /// How many samples are kept per terminal.
+    pub history_len: usize,
+    pub memory_alert_bytes: Option<u64>,
+    pub cpu_alert_percent: Option<f64>,
+    /// The CPU usage has to stay above the threshold this long to alert.
+    pub cpu_alert_duration: Duration,
+}
+
+pub(crate) trait ResourceSamplerEventHandler {
+    /// The id and the pid of the shell of every terminal.
+    fn terminals(&self) -> Vec<(String, u32)>;
+    fn handle_samples(&self, samples: Vec<TerminalSample>) -> Result<()>;
+    fn handle_alert(&self, alert: ResourceAlert) -> Result<()>;
+    fn handle_ports_changed(&self, id: String, ports: Vec<ListeningPort>) -> Result<()>;
+}
+
+#[derive(Default)]
+struct TerminalHistory {
+    samples: VecDeque<ResourceSample>,
+    read_total: u64,
+    written_total: u64,
+    cpu_high_since: Option<Instant>,
+    memory_alerted: bool,
+    cpu_alerted: bool,
+    ports: Vec<ListeningPort>,
+}
+
+/// The pids whose ports were scanned last, see `PORT_SCAN_MAX_AGE`.
+#[derive(Default)]
+struct PortScan {
+    pids: Vec<Pid>,
+    scanned_at: Option<Instant>,
+}
+
+impl PortScan {
+    /// `pids` are sorted.
+    fn is_due(&self, pids: &[Pid], max_age: Duration) -> bool {
+        match self.scanned_at {
+            Some(scanned_at) => self.pids != pids || scanned_at.elapsed() >= max_age,
+            None => true,
+        }
+    }
+}
+
+/// Samples the resources of every terminal in the background
+/// and keeps the recent samples.
+#[derive(Clone)]
+pub(crate) struct ResourceSampler {
+    histories: Arc<Mutex<HashMap<String, TerminalHistory>>>,
+    port_scan: Arc<Mutex<PortScan>>,
+}
 
-    builder.build_statistics_by_pid(pid, 1);
+impl ResourceSampler {
+    fn new() -> ResourceSampler {
+        ResourceSampler {
+            histories: Arc::new(Mutex::new(HashMap::new())),
+            port_scan: Arc::new(Mutex::new(PortScan::default())),
+        }
+    }
+
+    pub(crate) fn start(
+        options: SamplerOptions,
+        event_handler: Box<dyn ResourceSamplerEventHandler + Send>,
+    ) -> ResourceSampler {
+        let sampler = ResourceSampler::new();
+
+        let thread_sampler = sampler.clone();
+        std::thread::spawn(move || {
+            info!("begin resource sampler, interval: {:?}", options.interval);
+            loop {
+                std::thread::sleep(options.interval);
+
+                let terminals = event_handler.terminals();
+                let (samples, alerts) = thread_sampler.sample(&options, terminals.clone());
+                let changed_ports = thread_sampler.scan_ports(terminals);
+
+                if let Err(err) = event_handler.handle_samples(samples) {
+                    error!("handle samples error: {}", err);
+                }
+                for alert in alerts {
+                    info!("resource alert: {:?}", alert);
+                    if let Err(err) = event_handler.handle_alert(alert) {
+                        error!("handle alert error: {}", err);
+                    }
+                }
+                for (id, ports) in changed_ports {
+                    info!("ports changed: {}, {:?}", id, ports);
+                    if let Err(err) = event_handler.handle_ports_changed(id, ports) {
+                        error!("handle ports changed error: {}", err);
+                    }
+                }
+            }
+        });
 
-    // let output = Command::new("pgrep")
-    //     .arg("-P")
-    //     .arg(pid.to_string())
-    //     .output()
-    //     .ok()?;
-    // let output_str = String::from_utf8_lossy(&output.stdout);
+        sampler
+    }
 
-    // let output_is_all_whitespace = output_str.trim().is_empty();
+    fn sample(
+        &self,
+        options: &SamplerOptions,
+        terminals: Vec<(String, u32)>,
+    ) -> (Vec<TerminalSample>, Vec<ResourceAlert>) {
+        let sampled: Vec<(String, ResourceSample, u64, u64)> = with_process_table(|table| {
+            terminals
+                .into_iter()
+                .map(|(id, pid)| {
+                    let (sample, read_total, written_total) =
+                        table.sample_tree(Pid::from_u32(pid));
+                    (id, sample, read_total, written_total)
+                })
+                .collect()
+        });
+
+        let mut histories = self.histories.lock().unwrap();
+        // forget the removed terminals
+        histories.retain(|id, _| sampled.iter().any(|(sampled_id, ..)| sampled_id == id));
+
+        let mut samples = Vec::with_capacity(sampled.len());
+        let mut alerts = Vec::new();
+        for (id, mut sample, read_total, written_total) in sampled {
+            let history = histories.entry(id.clone()).or_default();
+
+            // the totals drop when a process exits
+            if !history.samples.is_empty() {
+                sample.read_bytes = read_total.saturating_sub(history.read_total);
+                sample.written_bytes = written_total.saturating_sub(history.written_total);
+            }
+            history.read_total = read_total;
+            history.written_total = written_total;
 
-    // if output_is_all_whitespace {
-    //     return None;
-    // }
+            alerts.extend(check_alerts(options, &id, &sample, history));
 
-    // let test_pid = output_str.trim().parse::<u32>();
-    // if test_pid.is_err() {
-    //     warn!("parse error: {}", output_str);
-    //     return None;
-    // }
+            history.samples.push_back(sample.clone());
+            while history.samples.len() > options.history_len {
+                history.samples.pop_front();
+            }
 
-    // let pid = test_pid.unwrap();
-    // let stat = fetch_cpu_mem_by_pid(pid);
+            samples.push(TerminalSample { id, sample });
+        }
 
-    builder.build()
+        (samples, alerts)
+    }
+
+    /// Find the ports listened on by the processes of every terminal,
+    /// return the terminals whose ports have changed.
+    ///
+    /// Nothing is scanned if the processes are the same as in the
+    /// last scan, no older than `PORT_SCAN_MAX_AGE`.
+    fn scan_ports(&self, terminals: Vec<(String, u32)>) -> Vec<(String, Vec<ListeningPort>)> {
+        let trees: Vec<(String, Vec<(Pid, String)>)> = with_process_table(|table| {
+            terminals
+                .into_iter()
+                .map(|(id, pid)| {
+                    let pid = Pid::from_u32(pid);
+                    let tree = std::iter::once(pid)
+                        .chain(table.descendants_of(pid))
+                        .filter_map(|pid| {
+                            table
+                                .process(pid)
+                                .map(|process| (pid, process.name().to_string()))
+                        })
+                        .collect();
+                    (id, tree)
+                })
+                .collect()
+        });
+
+        let mut all_pids: Vec<Pid> = trees
+            .iter()
+            .flat_map(|(_, tree)| tree.iter().map(|(pid, _)| *pid))
+            .collect();
+        all_pids.sort();
+        {
+            let mut port_scan = self.port_scan.lock().unwrap();
+            if !port_scan.is_due(&all_pids, PORT_SCAN_MAX_AGE) {
+                return Vec::new();
+            }
+            port_scan.scanned_at = Some(Instant::now());
+            port_scan.pids = all_pids.clone();
+        }
+        let sockets = listening_sockets_by_pid(&all_pids);
+
+        let mut histories = self.histories.lock().unwrap();
+        let mut changed = Vec::new();
+        for (id, tree) in trees {
+            let mut ports: Vec<ListeningPort> = Vec::new();
+            for (pid, name) in tree {
+                for (address, port) in sockets.get(&pid).into_iter().flatten() {
+                    // the same port is often listened on both IPv4 and IPv6
+                    if ports.iter().any(|listening| listening.port == *port) {
+                        continue;
+                    }
+                    ports.push(ListeningPort {
+                        port: *port,
+                        address: address.clone(),
+                        pid: pid.as_u32(),
+                        process_name: name.clone(),
+                    });
+                }
+            }
+            ports.sort_by_key(|listening| listening.port);
+
+            let history = match histories.get_mut(&id) {
+                Some(history) => history,
+                None => continue,
+            };
+            if history.ports != ports {
+                history.ports = ports.clone();
+                changed.push((id, ports));
+            }
+        }
+
+        changed
+    }
+
+    pub(crate) fn ports(&self, id: &str) -> Vec<ListeningPort> {
+        let histories = self.histories.lock().unwrap();
+        histories
+            .get(id)
+            .map(|history| history.ports.clone())
+            .unwrap_or_default()
+    }
+
+    pub(crate) fn history(&self, id: &str) -> Vec<ResourceSample> {
+        let histories = self.histories.lock().unwrap();
+        histories
+            .get(id)
+            .map(|history| history.samples.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// An alert is sent once when a threshold is crossed,
+/// and again only after the usage has dropped below it.
+fn check_alerts(
+    options: &SamplerOptions,
+    id: &str,
+    sample: &ResourceSample,
+    history: &mut TerminalHistory,
+) -> Vec<ResourceAlert> {
+    let mut alerts = Vec::new();
+
+    if let Some(threshold) = options.memory_alert_bytes {
+        let is_above = sample.mem_bytes > threshold;
+        if is_above && !history.memory_alerted {
+            alerts.push(ResourceAlert {
+                id: id.to_string(),
+                kind: ResourceAlertKind::Memory,
+                value: sample.mem_bytes as f64,
+                threshold: threshold as f64,
+                message: format!(
+                    "above {} of memory ({})",
+                    format_bytes(threshold),
+                    format_bytes(sample.mem_bytes)
+                ),
+            });
+        }
+        history.memory_alerted = is_above;
+    }
+
+    if let Some(threshold) = options.cpu_alert_percent {
+        if sample.cpu_usage > threshold {
+            let since = *history.cpu_high_since.get_or_insert_with(Instant::now);
+            if since.elapsed() >= options.cpu_alert_duration && !history.cpu_alerted {
+                history.cpu_alerted = true;
+                alerts.push(ResourceAlert {
+                    id: id.to_string(),
+                    kind: ResourceAlertKind::Cpu,
+                    value: sample.cpu_usage,
+                    threshold,
+                    message: format!(
+                        "above {:.0}% CPU for {} seconds",
+                        threshold,
+                        since.elapsed().as_secs()
+                    ),
+                });
+            }
+        } else {
+            history.cpu_high_since = None;
+            history.cpu_alerted = false;
+        }
+    }
+
+    alerts
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+    const MB: f64 = 1024.0 * 1024.0;
+    let bytes = bytes as f64;
+    if bytes >= GB {
+        format!("{:.1} GB", bytes / GB)
+    } else {
+        format!("{:.0} MB", bytes / MB)
+    }
+}
+
+/// The TCP sockets in LISTEN state, as (address, port), of each of the pids.
+///
+/// On Linux the sockets in `/proc/net/tcp{,6}` are matched to the
+/// processes by the inodes of their fds.
+#[cfg(target_os = "linux")]
+fn listening_sockets_by_pid(pids: &[Pid]) -> HashMap<Pid, Vec<(String, u16)>> {
+    const TCP_LISTEN: &str = "0A";
+
+    // inode -> (address, port)
+    let mut sockets: HashMap<u64, (String, u16)> = HashMap::new();
+    for path in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(_) => continue,
+        };
+        // sl local_address rem_address st tx_queue:rx_queue tr:tm->when retrnsmt uid timeout inode
+        for line in content.lines().skip(1) {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.len() < 10 || fields[3] != TCP_LISTEN {
+                continue;
+            }
+            let inode = match fields[9].parse::<u64>() {
+                Ok(inode) if inode > 0 => inode,
+                _ => continue,
+            };
+            if let Some(local) = parse_proc_net_address(fields[1]) {
+                sockets.insert(inode, local);
+            }
+        }
+    }
+
+    let mut result = HashMap::new();
+    if sockets.is_empty() {
+        return result;
+    }
+
+    for pid in pids {
+        let fd_dir = format!("/proc/{}/fd", pid.as_u32());
+        // the fds of the processes of other users can't be read
+        let entries = match std::fs::read_dir(fd_dir) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        for entry in entries.flatten() {
+            let link = match std::fs::read_link(entry.path()) {
+                Ok(link) => link,
+                Err(_) => continue,
+            };
+            let inode = link
+                .to_str()
+                .and_then(|link| link.strip_prefix("socket:["))
+                .and_then(|link| link.strip_suffix(']'))
+                .and_then(|inode| inode.parse::<u64>().ok());
+            if let Some(socket) = inode.and_then(|inode| sockets.get(&inode)) {
+                result
+                    .entry(*pid)
+                    .or_insert_with(Vec::new)
+                    .push(socket.clone());
+            }
+        }
+    }
+
+    result
+}
+
+/// `0100007F:1F90` is 127.0.0.1:8080, the address is printed in native byte order
+/// by 32-bit words.
+#[cfg(target_os = "linux")]
+fn parse_proc_net_address(field: &str) -> Option<(String, u16)> {
+    let (address, port) = field.split_once(':')?;
+    let port = u16::from_str_radix(port, 16).ok()?;
+
+    let mut bytes = Vec::with_capacity(16);
+    for index in (0..address.len()).step_by(8) {
+        let word = u32::from_str_radix(address.get(index..index + 8)?, 16).ok()?;
+        bytes.extend_from_slice(&word.to_ne_bytes());
+    }
+    let address = match bytes.len() {
+        4 => std::net::Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]).to_string(),
+        16 => {
+            let bytes: [u8; 16] = bytes.try_into().ok()?;
+            std::net::Ipv6Addr::from(bytes).to_string()
+        }
+        _ => return None,
+    };
+
+    Some((address, port))
+}
+
+/// The TCP sockets in LISTEN state, as (address, port), of each of the pids.
+///
+/// There is no `/proc` on macOS, one `lsof` is run for all the pids.
+#[cfg(not(target_os = "linux"))]
+fn listening_sockets_by_pid(pids: &[Pid]) -> HashMap<Pid, Vec<(String, u16)>> {
+    let mut result = HashMap::new();
+    if pids.is_empty() {
+        return result;
+    }
+
+    let pid_list = pids
+        .iter()
+        .map(|pid| pid.as_u32().to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    let output = std::process::Command::new("lsof")
+        .args(["-a", "-nP", "-iTCP", "-sTCP:LISTEN", "-Fpn", "-p", &pid_list])
+        .output();
+    let output = match output {
+        Ok(output) => output,
+        Err(err) => {
+            error!("lsof error: {}", err);
+            return result;
+        }
+    };
+
+    // `p<pid>` starts the lines of a process, `n<address>:<port>` is a socket
+    let mut current_pid = None;
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Some(pid) = line.strip_prefix('p') {
+            current_pid = pid.parse::<u32>().ok().map(Pid::from_u32);
+        } else if let (Some(name), Some(pid)) = (line.strip_prefix('n'), current_pid) {
+            if let Some((address, port)) = name.rsplit_once(':') {
+                if let Ok(port) = port.parse::<u16>() {
+                    let address = address.trim_start_matches('[').trim_end_matches(']');
+                    result
+                        .entry(pid)
+                        .or_insert_with(Vec::new)
+                        .push((address.to_string(), port));
+                }
+            }
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::os::unix::io::AsRawFd;
+    use std::os::unix::process::CommandExt;
+    use std::process::{Child, Command};
+
+    /// Start `sleep` with `listener` open, it's closed on exec otherwise.
+    fn spawn_listening_child(listener: &TcpListener) -> Child {
+        let fd = listener.as_raw_fd();
+        let mut command = Command::new("sleep");
+        command.arg("30");
+        unsafe {
+            command.pre_exec(move || {
+                if libc::fcntl(fd, libc::F_SETFD, 0) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        command.spawn().unwrap()
+    }
+
+    /// `sh` with the children `sleep` and `sh`, which has the child `sleep`.
+    fn spawn_process_tree() -> Child {
+        let mut command = Command::new("sh");
+        command
+            .args(["-c", "sleep 30 & sh -c 'sleep 30; :' & wait"])
+            .process_group(0);
+        command.spawn().unwrap()
+    }
+
+    fn kill_process_group(child: &mut Child) {
+        unsafe {
+            libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+        }
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn fetch_process_statistics_counts_tree() {
+        let mut child = spawn_process_tree();
+
+        // the processes are started one by one, wait for the last snapshot
+        let started_at = Instant::now();
+        let mut stat = fetch_process_statistics_by_pid(child.id());
+        while stat.total_children_count < 3 && started_at.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(SNAPSHOT_MAX_AGE);
+            stat = fetch_process_statistics_by_pid(child.id());
+        }
+        kill_process_group(&mut child);
+
+        assert_eq!(stat.total_children_count, 3);
+        let mut names = stat.first_level_children_names.clone();
+        names.sort();
+        assert_eq!(names, vec!["sh".to_string(), "sleep".to_string()]);
+        assert!(stat.cpu_usage >= 0.0);
+        assert!(stat.mem_usage > 0.0);
+    }
+
+    #[test]
+    fn scan_ports_attributes_to_terminal() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mut listening = spawn_listening_child(&listener);
+        let mut idle = Command::new("sleep").arg("30").spawn().unwrap();
+        // the children are in the next snapshot
+        std::thread::sleep(SNAPSHOT_MAX_AGE);
+
+        let sampler = ResourceSampler::new();
+        {
+            let mut histories = sampler.histories.lock().unwrap();
+            histories.insert("listening".to_string(), TerminalHistory::default());
+            histories.insert("idle".to_string(), TerminalHistory::default());
+        }
+        let changed = sampler.scan_ports(vec![
+            ("listening".to_string(), listening.id()),
+            ("idle".to_string(), idle.id()),
+        ]);
+
+        listening.kill().unwrap();
+        listening.wait().unwrap();
+        idle.kill().unwrap();
+        idle.wait().unwrap();
+
+        assert_eq!(changed.len(), 1);
+        let (id, ports) = &changed[0];
+        assert_eq!(id, "listening");
+        assert_eq!(ports.len(), 1);
+        assert_eq!(ports[0].port, port);
+        assert_eq!(ports[0].address, "127.0.0.1");
+        assert_eq!(ports[0].pid, listening.id());
+        assert_eq!(sampler.ports("listening"), *ports);
+        assert!(sampler.ports("idle").is_empty());
+    }
+
+    #[test]
+    fn port_scan_is_due_when_processes_change() {
+        let pids = vec![Pid::from_u32(10), Pid::from_u32(11)];
+        let mut port_scan = PortScan::default();
+        assert!(port_scan.is_due(&pids, Duration::from_secs(10)));
+
+        port_scan.pids = pids.clone();
+        port_scan.scanned_at = Some(Instant::now());
+        assert!(!port_scan.is_due(&pids, Duration::from_secs(10)));
+        assert!(port_scan.is_due(&pids[..1], Duration::from_secs(10)));
+        assert!(port_scan.is_due(&[Pid::from_u32(10), Pid::from_u32(12)], Duration::from_secs(10)));
+
+        port_scan.scanned_at = Some(Instant::now() - Duration::from_secs(10));
+        assert!(port_scan.is_due(&pids, Duration::from_secs(10)));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parse_proc_net_address_ipv4() {
+        assert_eq!(
+            parse_proc_net_address("0100007F:1F90"),
+            Some(("127.0.0.1".to_string(), 8080))
+        );
+        assert_eq!(
+            parse_proc_net_address("00000000:0016"),
+            Some(("0.0.0.0".to_string(), 22))
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parse_proc_net_address_ipv6() {
+        assert_eq!(
+            parse_proc_net_address("00000000000000000000000001000000:1F90"),
+            Some(("::1".to_string(), 8080))
+        );
+        assert_eq!(
+            parse_proc_net_address("00000000000000000000000000000000:0050"),
+            Some(("::".to_string(), 80))
+        );
+        assert_eq!(
+            parse_proc_net_address("0000000000000000FFFF00000100007F:0050"),
+            Some(("::ffff:127.0.0.1".to_string(), 80))
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn parse_proc_net_address_invalid() {
+        assert_eq!(parse_proc_net_address("0100007F"), None);
+        assert_eq!(parse_proc_net_address("0100007F:XYZ"), None);
+        assert_eq!(parse_proc_net_address("0100:1F90"), None);
+    }
 }
@@ -0,0 +1,277 @@
+use crate::settings::{ExitAction, FontSize, Scrollback, Settings};
+use crate::Result;
+use log::warn;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use toml::Table;
+
+pub(crate) const PROJECT_SETTINGS_FILE: &str = ".terminalone.toml";
+
+/// The keys a project file may set, the others are ignored.
+/// Nothing which runs outside the project, e.g. the keys or the extensions.
+const ALLOWED_KEYS: &[&str] = &[
+    "terminal.font-size",
+    "terminal.scrollback",
+    "terminal.shell",
+    "terminal.args",
+    "terminal.env",
+    "terminal.on-exit",
+];
+
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ProjectSettings {
+    #[serde(default)]
+    terminal: ProjectTerminalSettings,
+}
+
+#[derive(Default, Debug, Clone, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct ProjectTerminalSettings {
+    #[serde(default)]
+    font_size: Option<FontSize>,
+    #[serde(default)]
+    scrollback: Option<Scrollback>,
+    #[serde(default)]
+    shell: Option<String>,
+    #[serde(default)]
+    args: Option<Vec<String>>,
+    /// Added to `terminal.env` of the global settings.
+    #[serde(default)]
+    env: Option<BTreeMap<String, String>>,
+    #[serde(default)]
+    on_exit: Option<ExitAction>,
+}
+
+/// A `.terminalone.toml` found above a cwd.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ProjectFile {
+    pub path: PathBuf,
+    pub content: String,
+}
+
+/// The nearest project file in `cwd` or its ancestors.
+pub(crate) fn find_project_file(cwd: &Path) -> Option<ProjectFile> {
+    for dir in cwd.ancestors() {
+        let path = dir.join(PROJECT_SETTINGS_FILE);
+        if !path.is_file() {
+            continue;
+        }
+        match std::fs::read_to_string(&path) {
+            Ok(content) => return Some(ProjectFile { path, content }),
+            Err(err) => warn!("read project settings error: {}, {:?}", err, path),
+        }
+    }
+    None
+}
+
+/// Layer the project file over `settings`, return the result
+/// and the keys which are not allowed.
+pub(crate) fn apply_project_file(
+    settings: &Settings,
+    file: &ProjectFile,
+) -> Result<(Settings, Vec<String>)> {
+    let table: Table = toml::from_str(&file.content)?;
+
+    let mut ignored_keys = Vec::new();
+    collect_ignored_keys(&table, "", &mut ignored_keys);
+    if !ignored_keys.is_empty() {
+        warn!("ignored project settings: {:?}, {:?}", ignored_keys, file.path);
+    }
+
+    let project: ProjectSettings = toml::Value::Table(table).try_into()?;
+    let project = project.terminal;
+
+    let mut result = settings.clone();
+    let terminal = &mut result.terminal;
+    if let Some(font_size) = project.font_size {
+        terminal.font_size = font_size;
+    }
+    if let Some(scrollback) = project.scrollback {
+        terminal.scrollback = scrollback;
+    }
+    if project.shell.is_some() {
+        terminal.shell = project.shell;
+        // the args of another shell don't apply
+        terminal.args = None;
+    }
+    if project.args.is_some() {
+        terminal.args = project.args;
+    }
+    if let Some(env) = project.env {
+        terminal.env.get_or_insert_with(BTreeMap::new).extend(env);
+    }
+    if let Some(on_exit) = project.on_exit {
+        terminal.on_exit = on_exit;
+    }
+
+    Ok((result, ignored_keys))
+}
+
+fn collect_ignored_keys(table: &Table, prefix: &str, result: &mut Vec<String>) {
+    for (key, value) in table {
+        let path = if prefix.is_empty() {
+            key.clone()
+        } else {
+            format!("{}.{}", prefix, key)
+        };
+        if ALLOWED_KEYS.contains(&path.as_str()) {
+            continue;
+        }
+        let is_parent = ALLOWED_KEYS
+            .iter()
+            .any(|allowed| allowed.starts_with(&format!("{}.", path)));
+        match value.as_table() {
+            Some(child) if is_parent => collect_ignored_keys(child, &path, result),
+            _ => result.push(path),
+        }
+    }
+}
+
+/// The decision of the user about a project file.
+/// It's bound to the content, the user is asked again when the file changes.
+pub(crate) fn load_trust(db: &rusqlite::Connection, file: &ProjectFile) -> Result<Option<bool>> {
+    let mut stmt = db.prepare("SELECT content, trusted FROM project_trust WHERE path = ?")?;
+    let mut rows = stmt.query([file.path.to_string_lossy()])?;
+
+    let row = match rows.next()? {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+    let content: String = row.get(0)?;
+    let trusted: bool = row.get(1)?;
+
+    if content != file.content {
+        return Ok(None);
+    }
+    Ok(Some(trusted))
+}
+
+pub(crate) fn save_trust(db: &rusqlite::Connection, file: &ProjectFile, trusted: bool) -> Result<()> {
+    db.execute(
+        "INSERT OR REPLACE INTO project_trust (path, content, trusted) VALUES (?, ?, ?)",
+        (file.path.to_string_lossy(), &file.content, trusted),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project_file(content: &str) -> ProjectFile {
+        ProjectFile {
+            path: PathBuf::from("/projects/app").join(PROJECT_SETTINGS_FILE),
+            content: content.to_string(),
+        }
+    }
+
+    fn memory_database() -> rusqlite::Connection {
+        crate::database::open_database(Path::new(":memory:")).unwrap()
+    }
+
+    #[test]
+    fn apply_allowed_keys() {
+        let mut settings = Settings::default();
+        settings.terminal.args = Some(vec!["-l".to_string()]);
+        settings.terminal.env = Some(BTreeMap::from([("A".to_string(), "1".to_string())]));
+        let file = project_file(
+            r#"
+[terminal]
+font-size = 18
+shell = "/bin/zsh"
+on-exit = "keep"
+
+[terminal.env]
+B = "2"
+"#,
+        );
+
+        let (result, ignored_keys) = apply_project_file(&settings, &file).unwrap();
+
+        assert!(ignored_keys.is_empty());
+        assert_eq!(result.terminal.font_size.0, 18);
+        assert_eq!(result.terminal.scrollback.0, settings.terminal.scrollback.0);
+        assert_eq!(result.terminal.shell.as_deref(), Some("/bin/zsh"));
+        // the args of the global shell
+        assert_eq!(result.terminal.args, None);
+        assert_eq!(result.terminal.on_exit, ExitAction::Keep);
+        assert_eq!(
+            result.terminal.env,
+            Some(BTreeMap::from([
+                ("A".to_string(), "1".to_string()),
+                ("B".to_string(), "2".to_string()),
+            ]))
+        );
+    }
+
+    #[test]
+    fn disallowed_keys_are_ignored() {
+        let settings = Settings::default();
+        let file = project_file(
+            r#"
+extensions = ["evil"]
+
+[app]
+theme = "Evil"
+
+[keys.bindings]
+"mod+l" = "send-hex-codes:0x0c"
+
+[terminal]
+font-size = 18
+font-family = "Evil Mono"
+"#,
+        );
+
+        let (result, mut ignored_keys) = apply_project_file(&settings, &file).unwrap();
+        ignored_keys.sort();
+
+        assert_eq!(
+            ignored_keys,
+            vec!["app", "extensions", "keys", "terminal.font-family"]
+        );
+        assert_eq!(result.terminal.font_size.0, 18);
+        assert_eq!(result.app.theme, settings.app.theme);
+        assert_eq!(result.keys.bindings, settings.keys.bindings);
+    }
+
+    #[test]
+    fn invalid_file_is_an_error() {
+        let settings = Settings::default();
+        for content in ["[terminal", "[terminal]\nfont-size = \"big\"\n"] {
+            assert!(apply_project_file(&settings, &project_file(content)).is_err());
+        }
+    }
+
+    #[test]
+    fn trust_is_kept_while_content_is_unchanged() {
+        let db = memory_database();
+        let file = project_file("[terminal]\nfont-size = 18\n");
+        assert_eq!(load_trust(&db, &file).unwrap(), None);
+
+        save_trust(&db, &file, true).unwrap();
+        assert_eq!(load_trust(&db, &file).unwrap(), Some(true));
+
+        save_trust(&db, &file, false).unwrap();
+        assert_eq!(load_trust(&db, &file).unwrap(), Some(false));
+    }
+
+    #[test]
+    fn trust_is_revoked_when_content_changes() {
+        let db = memory_database();
+        let file = project_file("[terminal]\nfont-size = 18\n");
+        save_trust(&db, &file, true).unwrap();
+
+        let changed = project_file("[terminal]\nshell = \"/tmp/evil\"\n");
+        assert_eq!(load_trust(&db, &changed).unwrap(), None);
+
+        // another project with the same content
+        let other = ProjectFile {
+            path: PathBuf::from("/projects/other").join(PROJECT_SETTINGS_FILE),
+            content: file.content.clone(),
+        };
+        assert_eq!(load_trust(&db, &other).unwrap(), None);
+    }
+}
@@ -0,0 +1,548 @@
+use crate::process_killer::ProcessKiller;
+use crate::terminal_delegate::exit_signal;
+use crate::Result;
+use base64::{engine::general_purpose, Engine as _};
+use log::{debug, error, info, warn};
+use portable_pty::{native_pty_system, ChildKiller, CommandBuilder, MasterPty, PtySize};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The argument to run the binary as the session daemon,
+/// followed by the path of the socket.
+pub(crate) const DAEMON_ARG: &str = "--session-daemon";
+
+/// Bumped when the requests or the responses change,
+/// the app replaces a daemon which doesn't speak its protocol.
+pub(crate) const DAEMON_PROTOCOL: u32 = 2;
+
+// The daemon exits when it has had no session and no connection for this long.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+// Same as the poller of the app, the foreground is pushed when it changes.
+const FOREGROUND_POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+// The output kept for a detached session, replayed when it's attached again.
+const REPLAY_BYTES: usize = 256 * 1024;
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Sent by the app, one JSON object per line.
+///
+/// Every terminal uses its own connection, closing the connection
+/// detaches the session and leaves the shell running.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub(crate) enum DaemonRequest {
+    Spawn {
+        id: String,
+        command: CommandBuilder,
+        size: PtySize,
+    },
+    Attach {
+        id: String,
+        size: PtySize,
+    },
+    Write {
+        data: String,
+    },
+    Resize {
+        size: PtySize,
+    },
+    /// End the shell with all the processes it has started.
+    Kill {
+        grace_period_ms: u64,
+    },
+    Detach,
+    /// End the session `id` without attaching it, so nothing is replayed.
+    /// On a connection without a session, closed once the kill is started.
+    KillSession {
+        id: String,
+        grace_period_ms: u64,
+    },
+    /// Answered with `Version`, on a connection without a session.
+    Version,
+    /// End every session and exit, e.g. the app is updated.
+    Shutdown {
+        grace_period_ms: u64,
+    },
+}
+
+/// Sent by the daemon, one JSON object per line.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub(crate) enum DaemonResponse {
+    Ready { pid: Option<u32> },
+    Output { data: String },
+    Exited { code: u32, signal: Option<String> },
+    Error { message: String },
+    /// The foreground process group of the PTY has changed.
+    Foreground { pgid: Option<i32> },
+    Version { protocol: u32, version: String },
+}
+
+impl DaemonResponse {
+    /// The version of this binary.
+    pub(crate) fn current_version() -> DaemonResponse {
+        DaemonResponse::Version {
+            protocol: DAEMON_PROTOCOL,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+}
+
+pub(crate) fn send_message<T: Serialize>(stream: &mut UnixStream, message: &T) -> Result<()> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+struct SessionState {
+    replay: VecDeque<u8>,
+    attached: Option<Arc<Mutex<UnixStream>>>,
+    foreground: Option<i32>,
+    exited: bool,
+}
+
+struct DaemonSession {
+    id: String,
+    pid: Option<u32>,
+    master: Mutex<Box<dyn MasterPty + Send>>,
+    writer: Mutex<Box<dyn Write + Send>>,
+    killer: Mutex<Box<dyn ChildKiller + Send + Sync>>,
+    state: Mutex<SessionState>,
+}
+
+impl DaemonSession {
+    /// Send to the attached connection, detach it if it's gone.
+    fn send_to_attached(&self, state: &mut SessionState, response: &DaemonResponse) {
+        if let Some(conn) = state.attached.as_ref() {
+            let mut stream = conn.lock().unwrap();
+            if let Err(err) = send_message(&mut stream, response) {
+                warn!("session {} lost its connection: {}", self.id, err);
+                drop(stream);
+                state.attached = None;
+            }
+        }
+    }
+
+    fn push_output(&self, data: &[u8]) {
+        let mut state = self.state.lock().unwrap();
+
+        state.replay.extend(data);
+        let overflow = state.replay.len().saturating_sub(REPLAY_BYTES);
+        state.replay.drain(..overflow);
+
+        let response = DaemonResponse::Output {
+            data: general_purpose::STANDARD.encode(data),
+        };
+        self.send_to_attached(&mut state, &response);
+    }
+
+    /// Returns false when the shell has exited.
+    fn update_foreground(&self) -> bool {
+        let pgid = self.master.lock().unwrap().process_group_leader();
+        let mut state = self.state.lock().unwrap();
+        if state.exited {
+            return false;
+        }
+        if state.foreground != pgid {
+            state.foreground = pgid;
+            self.send_to_attached(&mut state, &DaemonResponse::Foreground { pgid });
+        }
+        true
+    }
+
+    /// The session stays detached if the replay can't be sent.
+    fn attach(&self, conn: Arc<Mutex<UnixStream>>) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+
+        // under the lock, so no output is sent between the replay and the new output
+        let result = self.send_replay(&mut conn.lock().unwrap(), &state);
+        if result.is_ok() {
+            state.attached = Some(conn);
+        }
+        result
+    }
+
+    fn send_replay(&self, stream: &mut UnixStream, state: &SessionState) -> Result<()> {
+        send_message(stream, &DaemonResponse::Ready { pid: self.pid })?;
+        let (front, back) = state.replay.as_slices();
+        let replay = [front, back].concat();
+        if !replay.is_empty() {
+            send_message(
+                stream,
+                &DaemonResponse::Output {
+                    data: general_purpose::STANDARD.encode(replay),
+                },
+            )?;
+        }
+        if state.foreground.is_some() {
+            send_message(
+                stream,
+                &DaemonResponse::Foreground {
+                    pgid: state.foreground,
+                },
+            )?;
+        }
+        Ok(())
+    }
+
+    fn kill(&self, grace_period: Duration) {
+        let pid = match self.pid {
+            Some(pid) => pid,
+            None => {
+                let _ = self.killer.lock().unwrap().kill();
+                return;
+            }
+        };
+        let foreground_pgid = self.master.lock().unwrap().process_group_leader();
+        let killer = ProcessKiller::new(pid, foreground_pgid, grace_period);
+        std::thread::spawn(move || killer.kill());
+    }
+
+    fn detach(&self, conn: &Arc<Mutex<UnixStream>>) {
+        let mut state = self.state.lock().unwrap();
+        let is_attached = state
+            .attached
+            .as_ref()
+            .map_or(false, |attached| Arc::ptr_eq(attached, conn));
+        if is_attached {
+            state.attached = None;
+            info!("session {} detached", self.id);
+        }
+    }
+}
+
+type Sessions = Arc<Mutex<HashMap<String, Arc<DaemonSession>>>>;
+
+/// Run the daemon until it has been idle for `IDLE_TIMEOUT`,
+/// it owns the PTYs so the shells survive the app.
+pub(crate) fn run_daemon(socket_path: &Path) -> Result<()> {
+    let _ = std::fs::remove_file(socket_path);
+    let listener = UnixListener::bind(socket_path)?;
+    info!("session daemon listening on {:?}", socket_path);
+
+    let sessions: Sessions = Arc::new(Mutex::new(HashMap::new()));
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    watch_idle(socket_path, sessions.clone(), connections.clone())?;
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                error!("accept error: {}", err);
+                continue;
+            }
+        };
+        let sessions = sessions.clone();
+        let connections = connections.clone();
+        connections.fetch_add(1, Ordering::SeqCst);
+        std::thread::spawn(move || {
+            if let Err(err) = handle_connection(stream, sessions) {
+                error!("connection error: {}", err);
+            }
+            connections.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    Ok(())
+}
+
+/// Exit when there is nothing to keep, the app starts the daemon again.
+fn watch_idle(socket_path: &Path, sessions: Sessions, connections: Arc<AtomicUsize>) -> Result<()> {
+    // A newer daemon may have replaced the socket, only ours is removed.
+    let socket_ino = std::fs::metadata(socket_path)?.ino();
+    let socket_path = socket_path.to_path_buf();
+
+    std::thread::spawn(move || {
+        let mut idle_since = Instant::now();
+        loop {
+            std::thread::sleep(IDLE_CHECK_INTERVAL);
+            let busy = !sessions.lock().unwrap().is_empty()
+                || connections.load(Ordering::SeqCst) > 0;
+            if busy {
+                idle_since = Instant::now();
+                continue;
+            }
+            if idle_since.elapsed() < IDLE_TIMEOUT {
+                continue;
+            }
+
+            info!("session daemon is idle, exit");
+            if std::fs::metadata(&socket_path).map_or(false, |meta| meta.ino() == socket_ino) {
+                let _ = std::fs::remove_file(&socket_path);
+            }
+            std::process::exit(0);
+        }
+    });
+
+    Ok(())
+}
+
+fn handle_connection(stream: UnixStream, sessions: Sessions) -> Result<()> {
+    let conn = Arc::new(Mutex::new(stream.try_clone()?));
+    let reader = BufReader::new(stream);
+    let mut session: Option<Arc<DaemonSession>> = None;
+
+    for line in reader.lines() {
+        let line = line?;
+        let request: DaemonRequest = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(err) => {
+                warn!("invalid request: {}", err);
+                continue;
+            }
+        };
+
+        let current = session.clone();
+        let result = match (request, current.as_ref()) {
+            (DaemonRequest::Spawn { id, command, size }, None) => {
+                spawn_session(&id, command, size, &sessions).map(|spawned| {
+                    if let Err(err) = spawned.attach(conn.clone()) {
+                        error!("attach session {} error: {}", id, err);
+                    }
+                    session = Some(spawned);
+                })
+            }
+            (DaemonRequest::Attach { id, size }, None) => {
+                let found = sessions.lock().unwrap().get(&id).cloned();
+                match found {
+                    Some(found) => {
+                        let _ = found.master.lock().unwrap().resize(size);
+                        // the requests of the connection, e.g. Kill, are still handled
+                        match found.attach(conn.clone()) {
+                            Ok(()) => info!("session {} attached", id),
+                            Err(err) => error!("attach session {} error: {}", id, err),
+                        }
+                        session = Some(found);
+                        Ok(())
+                    }
+                    None => Err(anyhow::anyhow!("session not found: {}", id).into()),
+                }
+            }
+            (DaemonRequest::Write { data }, Some(session)) => general_purpose::STANDARD
+                .decode(data)
+                .map_err(anyhow::Error::from)
+                .and_then(|data| Ok(session.writer.lock().unwrap().write_all(&data)?))
+                .map_err(Into::into),
+            (DaemonRequest::Resize { size }, Some(session)) => {
+                session.master.lock().unwrap().resize(size).map_err(Into::into)
+            }
+            (DaemonRequest::Kill { grace_period_ms }, Some(session)) => {
+                session.kill(Duration::from_millis(grace_period_ms));
+                Ok(())
+            }
+            (DaemonRequest::Detach, _) => break,
+            (DaemonRequest::KillSession { id, grace_period_ms }, None) => {
+                let found = sessions.lock().unwrap().get(&id).cloned();
+                match found {
+                    Some(found) => {
+                        info!("kill session {}", id);
+                        found.kill(Duration::from_millis(grace_period_ms));
+                        break;
+                    }
+                    None => Err(anyhow::anyhow!("session not found: {}", id).into()),
+                }
+            }
+            (DaemonRequest::Version, None) => {
+                let mut stream = conn.lock().unwrap();
+                send_message(&mut stream, &DaemonResponse::current_version())
+            }
+            (DaemonRequest::Shutdown { grace_period_ms }, None) => {
+                // the daemon exits once the shells are gone, see `watch_idle`
+                let all: Vec<Arc<DaemonSession>> =
+                    sessions.lock().unwrap().values().cloned().collect();
+                info!("shutdown, kill {} sessions", all.len());
+                for session in all {
+                    session.kill(Duration::from_millis(grace_period_ms));
+                }
+                break;
+            }
+            (request, _) => Err(anyhow::anyhow!("unexpected request: {:?}", request).into()),
+        };
+
+        if let Err(err) = result {
+            let mut stream = conn.lock().unwrap();
+            send_message(
+                &mut stream,
+                &DaemonResponse::Error {
+                    message: err.to_string(),
+                },
+            )?;
+        }
+    }
+
+    // the app is closed or crashed, keep the shell running
+    if let Some(session) = session {
+        session.detach(&conn);
+    }
+
+    Ok(())
+}
+
+fn spawn_session(
+    id: &str,
+    command: CommandBuilder,
+    size: PtySize,
+    sessions: &Sessions,
+) -> Result<Arc<DaemonSession>> {
+    if sessions.lock().unwrap().contains_key(id) {
+        return Err(anyhow::anyhow!("session already exists: {}", id).into());
+    }
+
+    let pair = native_pty_system().openpty(size)?;
+    let mut child = pair.slave.spawn_command(command)?;
+    drop(pair.slave);
+
+    let mut reader = pair.master.try_clone_reader()?;
+    let writer = pair.master.take_writer()?;
+
+    let session = Arc::new(DaemonSession {
+        id: id.to_string(),
+        pid: child.process_id(),
+        master: Mutex::new(pair.master),
+        writer: Mutex::new(writer),
+        killer: Mutex::new(child.clone_killer()),
+        state: Mutex::new(SessionState {
+            replay: VecDeque::new(),
+            attached: None,
+            foreground: None,
+            exited: false,
+        }),
+    });
+    sessions
+        .lock()
+        .unwrap()
+        .insert(id.to_string(), session.clone());
+    info!("session {} spawned, pid: {:?}", id, session.pid);
+
+    let reader_session = session.clone();
+    std::thread::spawn(move || {
+        let mut buffer = vec![0; READ_BUFFER_SIZE];
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(size) => reader_session.push_output(&buffer[0..size]),
+                Err(err) => {
+                    debug!("session {} read error: {}", reader_session.id, err);
+                    break;
+                }
+            }
+        }
+    });
+
+    // the app can't ask the PTY it doesn't own
+    let foreground_session = session.clone();
+    std::thread::spawn(move || {
+        while foreground_session.update_foreground() {
+            std::thread::sleep(FOREGROUND_POLL_INTERVAL);
+        }
+    });
+
+    let monitor_session = session.clone();
+    let monitor_sessions = sessions.clone();
+    std::thread::spawn(move || {
+        let response = match child.wait() {
+            Ok(status) => DaemonResponse::Exited {
+                code: status.exit_code(),
+                signal: exit_signal(&status),
+            },
+            Err(err) => DaemonResponse::Error {
+                message: err.to_string(),
+            },
+        };
+        info!("session {} exited: {:?}", monitor_session.id, response);
+
+        monitor_sessions.lock().unwrap().remove(&monitor_session.id);
+
+        let mut state = monitor_session.state.lock().unwrap();
+        state.exited = true;
+        monitor_session.send_to_attached(&mut state, &response);
+    });
+
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spawn_with_full_replay(sessions: &Sessions, id: &str) -> Arc<DaemonSession> {
+        let mut command = CommandBuilder::new("sh");
+        command.args(["-c", "head -c 1000000 /dev/zero | tr '\\0' x; sleep 60"]);
+        let session = spawn_session(id, command, PtySize::default(), sessions).unwrap();
+
+        let started_at = Instant::now();
+        while session.state.lock().unwrap().replay.len() < REPLAY_BYTES {
+            assert!(started_at.elapsed() < Duration::from_secs(5), "replay is not full");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        session
+    }
+
+    fn wait_session_removed(sessions: &Sessions, id: &str) {
+        let started_at = Instant::now();
+        while sessions.lock().unwrap().contains_key(id) {
+            assert!(started_at.elapsed() < Duration::from_secs(5), "session is alive");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn kill_session_does_not_replay() {
+        let sessions: Sessions = Arc::new(Mutex::new(HashMap::new()));
+        spawn_with_full_replay(&sessions, "kill");
+
+        let socket_path =
+            std::env::temp_dir().join(format!("t1-daemon-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&socket_path);
+        let listener = UnixListener::bind(&socket_path).unwrap();
+        let handler_sessions = sessions.clone();
+        let handler = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            handle_connection(stream, handler_sessions)
+        });
+
+        crate::daemon_client::kill_session(&socket_path, "kill", Duration::from_millis(100))
+            .unwrap();
+        handler.join().unwrap().unwrap();
+        wait_session_removed(&sessions, "kill");
+
+        let err = crate::daemon_client::kill_session(&socket_path, "kill", Duration::ZERO);
+        assert!(err.is_err());
+        let _ = std::fs::remove_file(&socket_path);
+    }
+
+    #[test]
+    fn kill_after_failed_attach() {
+        let sessions: Sessions = Arc::new(Mutex::new(HashMap::new()));
+        let session = spawn_with_full_replay(&sessions, "attach");
+
+        let (mut client, server) = UnixStream::pair().unwrap();
+        let handler_sessions = sessions.clone();
+        let handler = std::thread::spawn(move || handle_connection(server, handler_sessions));
+
+        // the replay doesn't fit in the socket buffer and is never read
+        let attach = DaemonRequest::Attach {
+            id: "attach".to_string(),
+            size: PtySize::default(),
+        };
+        send_message(&mut client, &attach).unwrap();
+        send_message(&mut client, &DaemonRequest::Kill { grace_period_ms: 100 }).unwrap();
+        drop(client);
+
+        handler.join().unwrap().unwrap();
+        wait_session_removed(&sessions, "attach");
+        assert!(session.state.lock().unwrap().attached.is_none());
+    }
+}
@@ -0,0 +1,41 @@
+use crate::messages::SavedSession;
+use crate::Result;
+
+pub(crate) fn save_sessions(db: &rusqlite::Connection, sessions: &[SavedSession]) -> Result<()> {
+  db.execute("DELETE FROM sessions", ())?;
+
+  let mut stmt = db.prepare(
+    "INSERT INTO sessions (id, position, profile, cwd, scrollback) VALUES (?, ?, ?, ?, ?)",
+  )?;
+
+  for (position, session) in sessions.iter().enumerate() {
+    stmt.execute((
+      &session.id,
+      position as i64,
+      &session.profile,
+      &session.cwd,
+      &session.scrollback,
+    ))?;
+  }
+
+  Ok(())
+}
+
+pub(crate) fn load_sessions(db: &rusqlite::Connection) -> Result<Vec<SavedSession>> {
+  let mut stmt = db.prepare(
+    "SELECT id, profile, cwd, scrollback FROM sessions ORDER BY position",
+  )?;
+
+  let session_iter = stmt.query_map([], |row| {
+    Ok(SavedSession {
+      id: row.get(0)?,
+      profile: row.get(1)?,
+      cwd: row.get(2)?,
+      scrollback: row.get(3)?,
+    })
+  })?;
+
+  let result = session_iter.collect::<rusqlite::Result<Vec<SavedSession>>>()?;
+
+  Ok(result)
+}
@@ -1,22 +1,65 @@
+use schemars::JsonSchema;
 use serde::{Serialize, Deserialize};
 use toml::Table;
+use std::collections::BTreeMap;
 use std::path::{PathBuf, Path};
-use log::error;
+use std::time::Duration;
+use log::{error, info, warn};
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use toml_edit::Document;
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "kebab-case")]
 pub struct Settings {
+  #[serde(default)]
+  pub version: SettingsVersion,
   #[serde(default)]
   pub terminal: TerminalSettings,
   #[serde(default)]
   pub app: AppSettings,
   #[serde(default)]
+  #[schemars(with = "Option<BTreeMap<String, serde_json::Value>>")]
   pub extensions: Option<Table>,
   #[serde(default)]
   pub keys: KeysSettings,
+  #[serde(default)]
+  pub profiles: Vec<ProfileSettings>,
+  #[serde(default)]
+  pub statistics: StatisticsSettings,
+}
+
+impl Settings {
+  pub fn find_profile(&self, name: &str) -> Option<&ProfileSettings> {
+    self.profiles.iter().find(|profile| profile.name == name)
+  }
+}
+
+impl ProfileSettings {
+  /// `cwd` with a leading `~` replaced by the home directory.
+  pub fn expanded_cwd(&self) -> Option<String> {
+    let cwd = self.cwd.as_ref()?;
+    let rest = match cwd.strip_prefix('~') {
+      Some(rest) if rest.is_empty() || rest.starts_with('/') => rest,
+      _ => return Some(cwd.clone()),
+    };
+    match dirs::home_dir() {
+      Some(home_dir) => Some(format!("{}{}", home_dir.to_string_lossy(), rest)),
+      None => Some(cwd.clone()),
+    }
+  }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The version of the format of `settings.toml`. Bump it with a step
+/// in `MIGRATIONS` when a key is renamed or moved.
+pub const SETTINGS_VERSION: u32 = 1;
+
+/// A file without `version` is older than the migrations, i.e. version 0.
+/// The current version is only written when the file is saved.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SettingsVersion(pub u32);
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct FontSize(pub u32);
 
 impl Default for FontSize {
@@ -25,7 +68,7 @@ impl Default for FontSize {
   } 
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct Scrollback(pub u32);
 
 impl Default for Scrollback {
@@ -34,17 +77,60 @@ impl Default for Scrollback {
   } 
 }
 
+/// In milliseconds, how long the processes of a closed terminal have
+/// to exit before they get SIGTERM, then SIGKILL.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct KillGracePeriod(pub u64);
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+impl Default for KillGracePeriod {
+  fn default() -> Self {
+    KillGracePeriod(3000)
+  }
+}
+
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "kebab-case")]
 pub struct TerminalSettings {
   #[serde(default)]
   pub font_size: FontSize,
   #[serde(default)]
   pub scrollback: Scrollback,
+  /// The shell to spawn, `$SHELL` or the passwd entry if not set.
+  #[serde(default)]
+  pub shell: Option<String>,
+  #[serde(default)]
+  pub args: Option<Vec<String>>,
+  #[serde(default)]
+  pub env: Option<BTreeMap<String, String>>,
+  #[serde(default)]
+  pub kill_grace_period: KillGracePeriod,
+  /// The processes which may be running when a terminal is closed without asking.
+  #[serde(default)]
+  pub confirm_close_allowlist: Vec<String>,
+  #[serde(default)]
+  pub on_exit: ExitAction,
+}
+
+/// What happens to the tab when its shell exits.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum ExitAction {
+  /// Close the tab.
+  Close,
+  /// Keep the tab open with how the shell has exited.
+  Keep,
+  /// Start the shell again in the same tab.
+  Restart,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Default for ExitAction {
+  fn default() -> Self {
+    ExitAction::Close
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 pub struct AutoUpdate(pub bool);
 
 impl Default for AutoUpdate {
@@ -53,40 +139,495 @@ impl Default for AutoUpdate {
   } 
 }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "kebab-case")]
 pub struct AppSettings {
   #[serde(default)]
   pub auto_update: AutoUpdate,
+  /// Run the shells in a background daemon, so they survive the app.
+  #[serde(default)]
+  pub session_daemon: bool,
+  /// The name of the theme, the default one if not set.
+  #[serde(default)]
+  pub theme: Option<String>,
 }
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+/// A named preset of `[[profiles]]` to open a terminal with.
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct ProfileSettings {
+  pub name: String,
+  #[serde(default)]
+  pub shell: Option<String>,
+  #[serde(default)]
+  pub args: Option<Vec<String>>,
+  #[serde(default)]
+  pub cwd: Option<String>,
+  #[serde(default)]
+  pub env: Option<BTreeMap<String, String>>,
+  #[serde(default)]
+  pub title: Option<String>,
+  #[serde(default)]
+  pub theme: Option<String>,
+  /// Overrides `terminal.on-exit`.
+  #[serde(default)]
+  pub on_exit: Option<ExitAction>,
+}
+
+/// In milliseconds, how often the resources of the terminals are sampled.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SampleInterval(pub u64);
+
+impl Default for SampleInterval {
+  fn default() -> Self {
+    SampleInterval(2000)
+  }
+}
+
+/// How many samples are kept per terminal.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SampleHistory(pub usize);
+
+impl Default for SampleHistory {
+  fn default() -> Self {
+    SampleHistory(300)
+  }
+}
+
+/// In seconds, how long the CPU usage has to stay above `cpu-alert`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct CpuAlertDuration(pub u64);
+
+impl Default for CpuAlertDuration {
+  fn default() -> Self {
+    CpuAlertDuration(60)
+  }
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub struct StatisticsSettings {
+  #[serde(default)]
+  pub interval: SampleInterval,
+  #[serde(default)]
+  pub history: SampleHistory,
+  /// In megabytes, alert when a terminal uses more memory.
+  #[serde(default)]
+  pub memory_alert: Option<u64>,
+  /// In percent of one core, alert when a terminal uses more CPU.
+  #[serde(default)]
+  pub cpu_alert: Option<f64>,
+  #[serde(default)]
+  pub cpu_alert_duration: CpuAlertDuration,
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "kebab-case")]
 pub struct KeysSettings {
+  /// A hotkey to its action, e.g. `"mod+l" = "send-hex-codes:0x0c"`.
   #[serde(default)]
+  #[schemars(with = "Option<BTreeMap<String, String>>")]
   pub bindings: Option<Table>,
 }
 
-pub(crate) fn read_init_settings(app_dir: &Path) -> Settings {
+/// Why `settings.toml` can't be used, the line and column are 1-based.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsError {
+  pub path: String,
+  pub message: String,
+  pub line: Option<usize>,
+  pub column: Option<usize>,
+}
+
+pub(crate) fn settings_path(app_dir: &Path) -> PathBuf {
+  PathBuf::from(app_dir).join("User").join("settings.toml")
+}
+
+/// The initial settings, and why they are the defaults if the file is invalid
+/// or which of its keys are unknown.
+pub(crate) fn read_init_settings(app_dir: &Path) -> (Settings, Option<SettingsError>) {
   let user_path = PathBuf::from(app_dir).join("User");
   let _ = std::fs::create_dir(&user_path);
-  let settings_path = user_path.clone().join("settings.toml");
+  let settings_path = settings_path(app_dir);
+
+  match read_settings(&settings_path) {
+    Ok(result) => result,
+    Err(err) => {
+      error!("Error parsing settings: {:?}", err);
+      (Settings::default(), Some(err))
+    }
+  }
+}
 
-  let test_read = std::fs::read_to_string(&settings_path);
-  if test_read.is_err() {
-    return Settings::default();
+/// A missing file is the default settings. The settings are returned with an
+/// error listing the unknown keys of the file, if any, they're ignored.
+pub(crate) fn read_settings(
+  settings_path: &Path,
+) -> Result<(Settings, Option<SettingsError>), SettingsError> {
+  let file_content = match std::fs::read_to_string(settings_path) {
+    Ok(content) => content,
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+      return Ok((Settings::default(), None))
+    }
+    Err(err) => {
+      return Err(SettingsError {
+        path: settings_path.to_string_lossy().to_string(),
+        message: err.to_string(),
+        line: None,
+        column: None,
+      })
+    }
+  };
+
+  let file_content = migrate_settings_file(settings_path, file_content);
+
+  let (settings, unknown_keys) = parse_settings(&file_content).map_err(|err| {
+    let position = err.span().map(|span| line_column(&file_content, span.start));
+    SettingsError {
+      path: settings_path.to_string_lossy().to_string(),
+      message: err.message().to_string(),
+      line: position.map(|(line, _)| line),
+      column: position.map(|(_, column)| column),
+    }
+  })?;
+
+  let unknown_keys_error = if unknown_keys.is_empty() {
+    None
+  } else {
+    Some(SettingsError {
+      path: settings_path.to_string_lossy().to_string(),
+      message: format!("unknown settings are ignored: {}", unknown_keys.join(", ")),
+      line: None,
+      column: None,
+    })
+  };
+  Ok((settings, unknown_keys_error))
+}
+
+/// The settings and the keys `Settings` doesn't have, e.g. a typo
+/// or a key of a newer version.
+pub(crate) fn parse_settings(content: &str) -> Result<(Settings, Vec<String>), toml::de::Error> {
+  let mut unknown_keys = Vec::new();
+  let settings = serde_ignored::deserialize(toml::Deserializer::new(content), |path| {
+    unknown_keys.push(path.to_string())
+  })?;
+  for key in &unknown_keys {
+    warn!("unknown setting: {}", key);
   }
+  Ok((settings, unknown_keys))
+}
 
-  let file_content = test_read.unwrap();
-  // parse to toml
+/// Write the file at once, the watcher never sees a half written file.
+pub(crate) fn write_settings_file(settings_path: &Path, content: &str) -> std::io::Result<()> {
+  if let Some(parent) = settings_path.parent() {
+    std::fs::create_dir_all(parent)?;
+  }
+  let temp_path = settings_path.with_extension("toml.tmp");
+  std::fs::write(&temp_path, content)?;
+  std::fs::rename(&temp_path, settings_path)
+}
 
-  let test_parse_settings = toml::from_str(&file_content);
+/// A step upgrading `settings.toml` from the previous version.
+struct Migration {
+  /// The version after the step.
+  version: u32,
+  description: &'static str,
+  migrate: fn(&mut Document),
+}
 
-  match test_parse_settings {
-    Ok(settings) => settings,
-    Err(err) => {
-      error!("Error parsing settings: {:?}", err);
-      Settings::default()
+/// In order, one step per version. `version` is set by `migrate_settings`.
+const MIGRATIONS: &[Migration] = &[Migration {
+  version: 1,
+  description: "add the version key",
+  migrate: |_| {},
+}];
+
+/// `None` if `version` isn't a version, e.g. a string or a negative number.
+fn document_version(document: &Document) -> Option<u32> {
+  match document.get("version") {
+    Some(item) => item.as_integer().and_then(|version| u32::try_from(version).ok()),
+    None => Some(0),
+  }
+}
+
+/// Upgrade the document from `version` to `SETTINGS_VERSION`, return the applied steps.
+fn migrate_settings(document: &mut Document, version: u32) -> Vec<&'static Migration> {
+  let mut applied = Vec::new();
+  for migration in MIGRATIONS {
+    if migration.version <= version {
+      continue;
+    }
+    (migration.migrate)(document);
+    document["version"] = toml_edit::value(i64::from(migration.version));
+    applied.push(migration);
+  }
+  applied
+}
+
+/// Upgrade an older `settings.toml`, the original is kept as
+/// `settings.toml.v<version>.bak`. The content to parse is returned,
+/// the unchanged one if it can't be migrated.
+pub(crate) fn migrate_settings_file(settings_path: &Path, content: String) -> String {
+  // an invalid file or version is reported by `parse_settings`
+  let mut document: Document = match content.parse() {
+    Ok(document) => document,
+    Err(_) => return content,
+  };
+  let version = match document_version(&document) {
+    Some(version) => version,
+    None => return content,
+  };
+
+  if version > SETTINGS_VERSION {
+    warn!(
+      "settings version {} is newer than {}, some settings may be ignored",
+      version, SETTINGS_VERSION
+    );
+    return content;
+  }
+
+  let applied = migrate_settings(&mut document, version);
+  if applied.is_empty() {
+    return content;
+  }
+  for migration in &applied {
+    info!(
+      "settings migrated to version {}: {}",
+      migration.version, migration.description
+    );
+  }
+
+  let backup_path = settings_path.with_extension(format!("toml.v{}.bak", version));
+  if !backup_path.exists() {
+    if let Err(err) = std::fs::write(&backup_path, &content) {
+      // the original is not overwritten without a backup
+      error!("backup settings error: {}, {:?}", err, backup_path);
+      return document.to_string();
+    }
+    info!("settings backed up: {:?}", backup_path);
+  }
+
+  let migrated = document.to_string();
+  if let Err(err) = write_settings_file(settings_path, &migrated) {
+    error!("write migrated settings error: {}, {:?}", err, settings_path);
+  }
+  migrated
+}
+
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+  let before = &content[..offset.min(content.len())];
+  let line = before.matches('\n').count() + 1;
+  let column = before
+    .rsplit('\n')
+    .next()
+    .map_or(0, |line| line.chars().count())
+    + 1;
+  (line, column)
+}
+
+pub(crate) trait SettingsWatcherEventHandler {
+  fn handle_settings_changed(&self, settings: Settings) -> crate::Result<()>;
+  fn handle_settings_error(&self, error: SettingsError) -> crate::Result<()>;
+}
+
+/// Re-read `settings.toml` when it changes. The directory is watched,
+/// editors often replace the file instead of writing it.
+pub(crate) fn watch_settings(
+  app_dir: &Path,
+  event_handler: Box<dyn SettingsWatcherEventHandler + Send>,
+) -> crate::Result<Debouncer<RecommendedWatcher>> {
+  let settings_path = settings_path(app_dir);
+  let watched_path = settings_path.clone();
+  let mut debouncer = new_debouncer(
+    Duration::from_millis(300),
+    None,
+    move |res: DebounceEventResult| {
+      let events = match res {
+        Ok(events) => events,
+        Err(errors) => {
+          error!("watch settings error: {:?}", errors);
+          return;
+        }
+      };
+      // the paths may be reported canonicalized
+      let file_name = watched_path.file_name();
+      if !events.iter().any(|event| event.path.file_name() == file_name) {
+        return;
+      }
+
+      let result = match read_settings(&watched_path) {
+        Ok((settings, unknown_keys_error)) => {
+          info!("settings changed: {:?}", watched_path);
+          event_handler
+            .handle_settings_changed(settings)
+            .and_then(|_| match unknown_keys_error {
+              // after the change, which clears the previous error
+              Some(err) => event_handler.handle_settings_error(err),
+              None => Ok(()),
+            })
+        }
+        Err(err) => {
+          // the previous settings are kept
+          warn!("invalid settings: {:?}", err);
+          event_handler.handle_settings_error(err)
+        }
+      };
+      if let Err(err) = result {
+        error!("handle settings changed error: {}", err);
+      }
+    },
+  )?;
+
+  if let Some(user_path) = settings_path.parent() {
+    debouncer
+      .watcher()
+      .watch(user_path, RecursiveMode::NonRecursive)?;
+  }
+
+  Ok(debouncer)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn temp_settings_path(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("t1-settings-{}-{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join("settings.toml")
+  }
+
+  fn migrate(content: &str) -> (Document, Vec<u32>) {
+    let mut document: Document = content.parse().unwrap();
+    let version = document_version(&document).unwrap();
+    let applied = migrate_settings(&mut document, version)
+      .iter()
+      .map(|migration| migration.version)
+      .collect();
+    (document, applied)
+  }
+
+  #[test]
+  fn default_version_is_zero() {
+    assert_eq!(Settings::default().version.0, 0);
+    assert_eq!(parse_settings("").unwrap().0.version.0, 0);
+    assert_eq!(parse_settings("version = 1").unwrap().0.version.0, 1);
+  }
+
+  #[test]
+  fn migrations_are_in_order() {
+    let versions: Vec<u32> = MIGRATIONS.iter().map(|migration| migration.version).collect();
+    let expected: Vec<u32> = (1..=SETTINGS_VERSION).collect();
+    assert_eq!(versions, expected);
+  }
+
+  #[test]
+  fn migrate_v0_to_v1() {
+    let content = "# my settings\n[terminal]\nfont-size = 14 # bigger\n";
+    let (document, applied) = migrate(content);
+
+    assert_eq!(applied, vec![1]);
+    assert_eq!(document_version(&document), Some(1));
+    let migrated = document.to_string();
+    assert!(migrated.contains("# my settings"));
+    assert!(migrated.contains("font-size = 14 # bigger"));
+    let (settings, _) = parse_settings(&migrated).unwrap();
+    assert_eq!(settings.version.0, 1);
+    assert_eq!(settings.terminal.font_size.0, 14);
+  }
+
+  #[test]
+  fn migrate_v0_to_v1_keeps_unknown_keys() {
+    let content = concat!(
+      "typo = true\n",
+      "[terminal]\nfont-size = 14\nfont-family = \"Menlo\"\n",
+      "[unknown]\nkey = 1\n",
+    );
+    let (document, applied) = migrate(content);
+
+    assert_eq!(applied, vec![1]);
+    assert_eq!(document["typo"].as_bool(), Some(true));
+    assert_eq!(document["terminal"]["font-family"].as_str(), Some("Menlo"));
+    assert_eq!(document["unknown"]["key"].as_integer(), Some(1));
+    // they're ignored, not an error
+    let (settings, mut unknown_keys) = parse_settings(&document.to_string()).unwrap();
+    assert_eq!(settings.terminal.font_size.0, 14);
+    unknown_keys.sort();
+    assert_eq!(unknown_keys, vec!["terminal.font-family", "typo", "unknown"]);
+  }
+
+  #[test]
+  fn migrate_current_version_is_noop() {
+    let content = format!("version = {}\n[terminal]\nfont-size = 14\n", SETTINGS_VERSION);
+    let (document, applied) = migrate(&content);
+
+    assert!(applied.is_empty());
+    assert_eq!(document.to_string(), content);
+  }
+
+  #[test]
+  fn migrate_settings_file_backs_up_original() {
+    let settings_path = temp_settings_path("backup");
+    let content = "[terminal]\nfont-size = 14\n";
+    std::fs::write(&settings_path, content).unwrap();
+
+    let migrated = migrate_settings_file(&settings_path, content.to_string());
+
+    let backup_path = settings_path.with_extension("toml.v0.bak");
+    assert_eq!(std::fs::read_to_string(backup_path).unwrap(), content);
+    assert_eq!(std::fs::read_to_string(&settings_path).unwrap(), migrated);
+    assert_eq!(read_settings(&settings_path).unwrap().0.version.0, SETTINGS_VERSION);
+    std::fs::remove_dir_all(settings_path.parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn migrate_settings_file_keeps_newer_version() {
+    let settings_path = temp_settings_path("newer");
+    let content = format!("version = {}\nnew-key = 1\n", SETTINGS_VERSION + 1);
+    std::fs::write(&settings_path, &content).unwrap();
+
+    assert_eq!(migrate_settings_file(&settings_path, content.clone()), content);
+    assert_eq!(std::fs::read_to_string(&settings_path).unwrap(), content);
+    std::fs::remove_dir_all(settings_path.parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn unknown_keys_are_reported() {
+    let settings_path = temp_settings_path("unknown");
+    let content = format!(
+      "version = {}\ntypo = 1\n[terminal]\nfont-size = 14\nfont-szie = 15\n",
+      SETTINGS_VERSION
+    );
+    std::fs::write(&settings_path, content).unwrap();
+
+    let (settings, error) = read_settings(&settings_path).unwrap();
+    assert_eq!(settings.terminal.font_size.0, 14);
+    let error = error.unwrap();
+    assert_eq!(error.path, settings_path.to_string_lossy());
+    assert!(error.message.contains("terminal.font-szie"), "{}", error.message);
+    assert!(error.message.contains("typo"), "{}", error.message);
+
+    let content = format!("version = {}\n[terminal]\nfont-size = 14\n", SETTINGS_VERSION);
+    std::fs::write(&settings_path, content).unwrap();
+    assert!(read_settings(&settings_path).unwrap().1.is_none());
+    std::fs::remove_dir_all(settings_path.parent().unwrap()).unwrap();
+  }
+
+  #[test]
+  fn invalid_version_is_reported() {
+    for version in ["\"1\"", "1.5", "-1"] {
+      let settings_path = temp_settings_path("invalid-version");
+      let content = format!("# my settings\nversion = {}\n", version);
+      std::fs::write(&settings_path, &content).unwrap();
+
+      let error = read_settings(&settings_path).unwrap_err();
+      assert_eq!(error.line, Some(2), "{}", version);
+      // not migrated over the invalid version
+      assert_eq!(std::fs::read_to_string(&settings_path).unwrap(), content);
+      assert!(!settings_path.with_extension("toml.v0.bak").exists());
+      std::fs::remove_dir_all(settings_path.parent().unwrap()).unwrap();
     }
   }
 }
@@ -0,0 +1,325 @@
+use crate::settings::{
+    migrate_settings_file, parse_settings, write_settings_file, Settings, SETTINGS_VERSION,
+};
+use crate::{Error, Result};
+use log::info;
+use std::path::Path;
+use toml_edit::{Array, Document, InlineTable, Item, Value};
+
+/// Set the setting at `path`, e.g. `terminal.font-size` or `profiles.0.shell`,
+/// keeping the comments and the order of `settings.toml`.
+pub(crate) fn update_setting(
+    settings_path: &Path,
+    path: &str,
+    value: serde_json::Value,
+) -> Result<Settings> {
+    check_setting_path(path)?;
+    let value = json_to_toml(value).map_err(|message| invalid_setting(path, message))?;
+
+    edit_settings(settings_path, path, |document| {
+        let segments: Vec<&str> = path.split('.').collect();
+        set_item(document.as_item_mut(), &segments, value)
+            .map_err(|message| invalid_setting(path, message))
+    })
+}
+
+/// Remove the setting at `path`, so its default is used.
+pub(crate) fn reset_setting(settings_path: &Path, path: &str) -> Result<Settings> {
+    check_setting_path(path)?;
+
+    edit_settings(settings_path, path, |document| {
+        let segments: Vec<&str> = path.split('.').collect();
+        remove_item(document.as_item_mut(), &segments);
+        Ok(())
+    })
+}
+
+/// The file is migrated first, the keys of an older version would be
+/// edited otherwise. It's only written if the edited settings are valid.
+fn edit_settings(
+    settings_path: &Path,
+    path: &str,
+    edit: impl FnOnce(&mut Document) -> Result<()>,
+) -> Result<Settings> {
+    let content = match std::fs::read_to_string(settings_path) {
+        Ok(content) => migrate_settings_file(settings_path, content),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            format!("version = {}\n", SETTINGS_VERSION)
+        }
+        Err(err) => return Err(err.into()),
+    };
+    let mut document: Document = content
+        .parse()
+        .map_err(|err: toml_edit::TomlError| invalid_setting(path, err.to_string()))?;
+
+    edit(&mut document)?;
+
+    let content = document.to_string();
+    let (settings, _) =
+        parse_settings(&content).map_err(|err| invalid_setting(path, err.message().to_string()))?;
+
+    write_settings_file(settings_path, &content)?;
+
+    info!("setting changed: {}", path);
+
+    Ok(settings)
+}
+
+fn invalid_setting(path: &str, message: impl Into<String>) -> Error {
+    Error::InvalidSetting(path.to_string(), message.into())
+}
+
+/// Reject the paths `Settings` doesn't have, they would be ignored silently.
+/// The free-form tables, e.g. `extensions` and `keys.bindings`, and the
+/// elements of arrays are not checked.
+fn check_setting_path(path: &str) -> Result<()> {
+    let mut node = serde_json::to_value(Settings::default())?;
+    for segment in path.split('.') {
+        if segment.is_empty() {
+            return Err(invalid_setting(path, "empty key"));
+        }
+        node = match node {
+            serde_json::Value::Object(mut map) => match map.remove(segment) {
+                Some(child) => child,
+                None => return Err(invalid_setting(path, "unknown setting")),
+            },
+            _ => return Ok(()),
+        };
+    }
+    Ok(())
+}
+
+fn set_item(item: &mut Item, segments: &[&str], value: Value) -> std::result::Result<(), String> {
+    let (segment, rest) = segments.split_first().ok_or("empty path")?;
+
+    if let Ok(index) = segment.parse::<usize>() {
+        let child = item
+            .get_mut(index)
+            .ok_or_else(|| format!("no element {}", index))?;
+        if rest.is_empty() {
+            return replace_value(child, value);
+        }
+        return set_item(child, rest, value);
+    }
+
+    let table = item
+        .as_table_like_mut()
+        .ok_or_else(|| format!("{} is not in a table", segment))?;
+    if rest.is_empty() {
+        return match table.get_mut(segment) {
+            Some(child) => replace_value(child, value),
+            None => {
+                table.insert(segment, Item::Value(value));
+                Ok(())
+            }
+        };
+    }
+    if table.get(segment).is_none() {
+        let mut child = toml_edit::Table::new();
+        // only `[a.b]` is written, not an empty `[a]`
+        child.set_implicit(true);
+        table.insert(segment, Item::Table(child));
+    }
+    set_item(table.get_mut(segment).unwrap(), rest, value)
+}
+
+/// Keep the comment after the value which is replaced.
+fn replace_value(item: &mut Item, mut value: Value) -> std::result::Result<(), String> {
+    if let Some(existing) = item.as_value() {
+        *value.decor_mut() = existing.decor().clone();
+    }
+    *item = Item::Value(value);
+    Ok(())
+}
+
+fn remove_item(item: &mut Item, segments: &[&str]) {
+    let (segment, rest) = match segments.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+
+    if let Ok(index) = segment.parse::<usize>() {
+        if rest.is_empty() {
+            if let Some(array) = item.as_array_mut() {
+                if index < array.len() {
+                    array.remove(index);
+                }
+            } else if let Some(array) = item.as_array_of_tables_mut() {
+                if index < array.len() {
+                    array.remove(index);
+                }
+            }
+        } else if let Some(child) = item.get_mut(index) {
+            remove_item(child, rest);
+        }
+        return;
+    }
+
+    let table = match item.as_table_like_mut() {
+        Some(table) => table,
+        None => return,
+    };
+    if rest.is_empty() {
+        table.remove(segment);
+    } else if let Some(child) = table.get_mut(segment) {
+        remove_item(child, rest);
+    }
+}
+
+fn json_to_toml(value: serde_json::Value) -> std::result::Result<Value, String> {
+    let result = match value {
+        serde_json::Value::Null => {
+            return Err("null can't be written, reset the setting instead".to_string())
+        }
+        serde_json::Value::Bool(value) => Value::from(value),
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(value) => Value::from(value),
+            None => Value::from(number.as_f64().ok_or("invalid number")?),
+        },
+        serde_json::Value::String(value) => Value::from(value),
+        serde_json::Value::Array(values) => {
+            let mut array = Array::new();
+            for value in values {
+                array.push(json_to_toml(value)?);
+            }
+            Value::Array(array)
+        }
+        serde_json::Value::Object(map) => {
+            let mut table = InlineTable::new();
+            for (key, value) in map {
+                table.insert(&key, json_to_toml(value)?);
+            }
+            Value::InlineTable(table)
+        }
+    };
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::FontSize;
+    use std::path::PathBuf;
+
+    /// `settings.toml` in a new directory, with `content` if any.
+    fn temp_settings(name: &str, content: Option<&str>) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("t1-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let settings_path = dir.join("settings.toml");
+        if let Some(content) = content {
+            std::fs::write(&settings_path, content).unwrap();
+        }
+        settings_path
+    }
+
+    fn remove_temp_settings(settings_path: &Path) {
+        std::fs::remove_dir_all(settings_path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn edit_migrates_first() {
+        let settings_path =
+            temp_settings("settings-editor", Some("[terminal]\nfont-size = 14\n"));
+
+        let settings =
+            update_setting(&settings_path, "terminal.scrollback", serde_json::json!(5000)).unwrap();
+
+        assert_eq!(settings.version.0, SETTINGS_VERSION);
+        assert_eq!(settings.terminal.font_size.0, 14);
+        assert_eq!(settings.terminal.scrollback.0, 5000);
+        assert!(settings_path.with_extension("toml.v0.bak").exists());
+        remove_temp_settings(&settings_path);
+    }
+
+    #[test]
+    fn edit_missing_file_writes_version() {
+        let settings_path = temp_settings("settings-new", None);
+
+        let settings =
+            update_setting(&settings_path, "terminal.font-size", serde_json::json!(16)).unwrap();
+
+        assert_eq!(settings.version.0, SETTINGS_VERSION);
+        let content = std::fs::read_to_string(&settings_path).unwrap();
+        assert!(content.starts_with(&format!("version = {}\n", SETTINGS_VERSION)));
+        remove_temp_settings(&settings_path);
+    }
+
+    #[test]
+    fn update_keeps_comments_and_order() {
+        let content = format!(
+            concat!(
+                "version = {}\n\n",
+                "# the terminal\n",
+                "[terminal]\n",
+                "# bigger\n",
+                "font-size = 20 # pixels\n",
+                "scrollback = 5000\n",
+            ),
+            SETTINGS_VERSION
+        );
+        let settings_path = temp_settings("settings-comments", Some(&content));
+
+        let settings =
+            update_setting(&settings_path, "terminal.font-size", serde_json::json!(16)).unwrap();
+
+        assert_eq!(settings.terminal.font_size.0, 16);
+        assert_eq!(
+            std::fs::read_to_string(&settings_path).unwrap(),
+            content.replace("font-size = 20", "font-size = 16")
+        );
+        remove_temp_settings(&settings_path);
+    }
+
+    #[test]
+    fn reset_removes_the_setting() {
+        let content = format!(
+            "version = {}\n\n[terminal]\n# bigger\nfont-size = 20\nscrollback = 5000\n",
+            SETTINGS_VERSION
+        );
+        let settings_path = temp_settings("settings-reset", Some(&content));
+
+        let settings = reset_setting(&settings_path, "terminal.font-size").unwrap();
+
+        assert_eq!(settings.terminal.font_size.0, FontSize::default().0);
+        assert_eq!(settings.terminal.scrollback.0, 5000);
+        let content = std::fs::read_to_string(&settings_path).unwrap();
+        assert!(!content.contains("font-size"));
+        assert!(content.contains("scrollback = 5000"));
+
+        // a setting which isn't in the file
+        reset_setting(&settings_path, "terminal.font-size").unwrap();
+        remove_temp_settings(&settings_path);
+    }
+
+    #[test]
+    fn unknown_paths_are_rejected() {
+        let content = format!("version = {}\n", SETTINGS_VERSION);
+        let settings_path = temp_settings("settings-unknown", Some(&content));
+
+        for path in ["terminal.font-sise", "nope", "terminal..font-size", ""] {
+            let err = update_setting(&settings_path, path, serde_json::json!(16)).unwrap_err();
+            assert!(matches!(err, Error::InvalidSetting(..)), "{}", path);
+            let err = reset_setting(&settings_path, path).unwrap_err();
+            assert!(matches!(err, Error::InvalidSetting(..)), "{}", path);
+        }
+        assert_eq!(std::fs::read_to_string(&settings_path).unwrap(), content);
+
+        // the free-form tables aren't checked
+        update_setting(&settings_path, "keys.bindings.mod+t", serde_json::json!("new-tab"))
+            .unwrap();
+        remove_temp_settings(&settings_path);
+    }
+
+    #[test]
+    fn invalid_value_is_not_written() {
+        let content = format!("version = {}\n", SETTINGS_VERSION);
+        let settings_path = temp_settings("settings-invalid", Some(&content));
+
+        let err = update_setting(&settings_path, "terminal.font-size", serde_json::json!("big"))
+            .unwrap_err();
+        assert!(matches!(err, Error::InvalidSetting(..)));
+        assert_eq!(std::fs::read_to_string(&settings_path).unwrap(), content);
+        remove_temp_settings(&settings_path);
+    }
+}
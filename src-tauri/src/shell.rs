@@ -0,0 +1,128 @@
+use crate::messages::ShellOptions;
+use crate::settings::{ProfileSettings, TerminalSettings};
+use log::warn;
+use std::collections::BTreeMap;
+use std::ffi::CStr;
+use std::path::Path;
+
+const FALLBACK_SHELL: &str = "/bin/sh";
+
+// `-l` is understood by zsh, bash and fish alike,
+// unlike `--login` which bash rejects after a short option.
+const DEFAULT_SHELL_ARGS: [&str; 2] = ["-i", "-l"];
+
+/// The program, arguments and extra environment
+/// used to spawn the shell of a terminal.
+#[derive(Debug, Clone)]
+pub(crate) struct ShellCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    pub envs: BTreeMap<String, String>,
+}
+
+impl ShellCommand {
+    pub(crate) fn from_settings(settings: &TerminalSettings) -> ShellCommand {
+        let program = settings
+            .shell
+            .clone()
+            .unwrap_or_else(default_login_shell);
+        let args = settings.args.clone().unwrap_or_else(default_shell_args);
+        let envs = settings.env.clone().unwrap_or_default();
+
+        ShellCommand {
+            program,
+            args,
+            envs,
+        }
+    }
+
+    pub(crate) fn apply_profile(&mut self, profile: &ProfileSettings) {
+        self.apply_options(&ShellOptions {
+            shell: profile.shell.clone(),
+            args: profile.args.clone(),
+            env: profile.env.clone(),
+        });
+    }
+
+    /// Apply the options of a single tab on top of the settings.
+    /// The default arguments are used if the tab changes the
+    /// shell without specifying any arguments.
+    pub(crate) fn apply_options(&mut self, options: &ShellOptions) {
+        if let Some(shell) = &options.shell {
+            self.program = shell.clone();
+            self.args = default_shell_args();
+        }
+
+        if let Some(args) = &options.args {
+            self.args = args.clone();
+        }
+
+        if let Some(env) = &options.env {
+            for (key, value) in env {
+                self.envs.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn default_shell_args() -> Vec<String> {
+    DEFAULT_SHELL_ARGS.iter().map(|s| s.to_string()).collect()
+}
+
+/// Find the login shell of the current user,
+/// `$SHELL` first, then the passwd entry.
+pub(crate) fn default_login_shell() -> String {
+    if let Ok(shell) = std::env::var("SHELL") {
+        if !shell.trim().is_empty() {
+            return shell;
+        }
+    }
+
+    if let Some(shell) = passwd_shell() {
+        return shell;
+    }
+
+    warn!("no login shell found, fallback to {}", FALLBACK_SHELL);
+    FALLBACK_SHELL.to_string()
+}
+
+fn passwd_shell() -> Option<String> {
+    unsafe {
+        let pw = libc::getpwuid(libc::getuid());
+        if pw.is_null() || (*pw).pw_shell.is_null() {
+            return None;
+        }
+        let shell = CStr::from_ptr((*pw).pw_shell).to_str().ok()?;
+        if shell.is_empty() {
+            return None;
+        }
+        Some(shell.to_string())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ShellKind {
+    Zsh,
+    Bash,
+    Fish,
+    Unknown,
+}
+
+impl ShellKind {
+    /// Detect the kind of shell by the file name of the program,
+    /// e.g. `/opt/homebrew/bin/fish`.
+    pub(crate) fn from_program(program: &str) -> ShellKind {
+        let name = Path::new(program)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(program)
+            .trim_start_matches('-');
+
+        match name {
+            "zsh" => ShellKind::Zsh,
+            "bash" => ShellKind::Bash,
+            "fish" => ShellKind::Fish,
+            _ => ShellKind::Unknown,
+        }
+    }
+}
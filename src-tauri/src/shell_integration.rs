@@ -1,8 +1,11 @@
 use std::{path::{Path, PathBuf}, io::Write, str::FromStr};
 
-use crate::Result;
-use log::info;
+use crate::messages::{ShellIntegrationStatus, ShellOptions};
+use crate::shell::{ShellCommand, ShellKind};
+use crate::{AppState, Error, Result};
+use log::{info, warn};
 use lazy_static::lazy_static;
+use tauri::State;
 
 struct CopyFilesTuple {
     src: &'static str,
@@ -11,7 +14,7 @@ struct CopyFilesTuple {
 
 lazy_static! {
 
-  static ref SHELL_COPY_FILES: Vec<CopyFilesTuple> = vec![
+  static ref ZSH_COPY_FILES: Vec<CopyFilesTuple> = vec![
     CopyFilesTuple {
       src: "t1-rc.zsh",
       dest: ".zshrc",
@@ -30,12 +33,87 @@ lazy_static! {
     },
   ];
 
+  static ref BASH_COPY_FILES: Vec<CopyFilesTuple> = vec![
+    CopyFilesTuple {
+      src: "t1-rc.bash",
+      dest: "t1-rc.bash",
+    },
+  ];
+
+  static ref FISH_COPY_FILES: Vec<CopyFilesTuple> = vec![
+    CopyFilesTuple {
+      src: "t1-rc.fish",
+      dest: "t1-rc.fish",
+    },
+  ];
+
+}
+
+fn copy_files(kind: ShellKind) -> &'static [CopyFilesTuple] {
+    match kind {
+        ShellKind::Zsh => &ZSH_COPY_FILES,
+        ShellKind::Bash => &BASH_COPY_FILES,
+        ShellKind::Fish => &FISH_COPY_FILES,
+        ShellKind::Unknown => &[],
+    }
+}
+
+/// The script sourced by the rc file of the shell.
+fn rc_script_name(kind: ShellKind) -> Option<&'static str> {
+    match kind {
+        ShellKind::Zsh => Some("t1-rc.zsh"),
+        ShellKind::Bash => Some("t1-rc.bash"),
+        ShellKind::Fish => Some("t1-rc.fish"),
+        ShellKind::Unknown => None,
+    }
+}
+
+// A bash login shell reads the first of them which exists, never `.bashrc`.
+const BASH_LOGIN_FILES: [&str; 3] = [".bash_profile", ".bash_login", ".profile"];
+
+/// The rc file read by the shell when it starts in a terminal.
+fn rc_file_path(kind: ShellKind, login: bool, home_dir: &Path) -> Option<PathBuf> {
+    let name = match kind {
+        ShellKind::Zsh => ".zshrc",
+        ShellKind::Bash if login => BASH_LOGIN_FILES
+            .iter()
+            .find(|name| home_dir.join(name).exists())
+            .copied()
+            .unwrap_or(BASH_LOGIN_FILES[0]),
+        ShellKind::Bash => ".bashrc",
+        ShellKind::Fish => ".config/fish/config.fish",
+        ShellKind::Unknown => return None,
+    };
+    Some(home_dir.join(name))
 }
 
+/// `-l`, `--login` or combined short options, e.g. `-il`.
+fn is_login_shell(args: &[String]) -> bool {
+    args.iter().any(|arg| {
+        arg == "--login" || (arg.starts_with('-') && !arg.starts_with("--") && arg.contains('l'))
+    })
+}
+
+// The block appended to the rc file is wrapped by these markers,
+// so it can be found and stripped exactly.
+const BLOCK_BEGIN: &str = "# >>> Terminal One >>>";
+const BLOCK_END: &str = "# <<< Terminal One <<<";
+
+// Older versions only wrote a header line followed by the `source` line.
+const LEGACY_BLOCK_HEADER: &str = "# Terminal One";
+
+const SOURCE_PREFIX: &str = "source ";
+
+const MAX_BACKUPS: u32 = 5;
+
 fn escape_shell_path(path: &str) -> String {
     path.replace(" ", "\\ ")
 }
 
+fn unescape_shell_path(path: &str) -> String {
+    path.replace("\\ ", " ")
+}
+
 #[cfg(debug_assertions)]
 pub fn get_shell_path(app_data_dir: &Path) -> Result<PathBuf> {
     let mut shell_path_buf = app_data_dir.to_path_buf();
@@ -54,8 +132,8 @@ pub fn get_shell_path(app_data_dir: &Path) -> Result<PathBuf> {
     Ok(shell_path_buf)
 }
 
-pub fn init_shell_integration(app_handle: &tauri::AppHandle, shell_path: &Path) -> Result<()> {
-    info!("Shell path: {:?}", shell_path);
+pub fn init_shell_integration(app_handle: &tauri::AppHandle, shell_path: &Path, kind: ShellKind) -> Result<()> {
+    info!("Shell path: {:?}, kind: {:?}", shell_path, kind);
 
     // mkdir -p shell_path if not exists
     if !shell_path.exists() {
@@ -67,7 +145,7 @@ pub fn init_shell_integration(app_handle: &tauri::AppHandle, shell_path: &Path)
         .resolve_resource("shell_integration")
         .expect("no shell integration found");
 
-    for tuple in SHELL_COPY_FILES.iter() {
+    for tuple in copy_files(kind).iter() {
       let script_path = {
         let mut tmp = app_shell_path.clone();
         tmp.push(tuple.src);
@@ -87,60 +165,301 @@ pub fn init_shell_integration(app_handle: &tauri::AppHandle, shell_path: &Path)
     Ok(())
 }
 
-#[tauri::command]
-pub fn install_script(app_handle: tauri::AppHandle) -> Result<()> {
-  let shell_path = app_handle
-        .path_resolver()
-        .resolve_resource("shell_integration")
-        .expect("no shell integration found");
-  info!("begin install script");
-
-  let post_commit_str = {
-      let mut result = "\n# Terminal One\nsource ".to_string();
-      let precommit_dir = {
-          let mut tmp = shell_path.clone();
-          tmp.push("t1-rc.zsh");
-          tmp
-      };
-      let escaped_path = escape_shell_path(precommit_dir.to_str().unwrap());
-      result += escaped_path.as_str();
-      result += "\n";
-      result
-  };
+struct RcTarget {
+    program: String,
+    rc_path: PathBuf,
+    script_path: PathBuf,
+}
+
+impl RcTarget {
+    fn new(app_handle: &tauri::AppHandle, state: &AppState, shell: Option<String>) -> Result<RcTarget> {
+        let shell_path = app_handle
+            .path_resolver()
+            .resolve_resource("shell_integration")
+            .expect("no shell integration found");
+
+        // the shell is spawned with these args, they decide the rc file
+        let mut command = ShellCommand::from_settings(&state.settings().terminal);
+        if shell.is_some() {
+            command.apply_options(&ShellOptions {
+                shell,
+                ..Default::default()
+            });
+        }
+        let program = command.program;
+        let kind = ShellKind::from_program(&program);
 
-  let home_dir = dirs::home_dir().unwrap().to_str().unwrap().to_string();
+        let home_dir = dirs::home_dir().unwrap().to_str().unwrap().to_string();
+        let home_dir = PathBuf::from_str(&home_dir)?;
+        let rc_path = rc_file_path(kind, is_login_shell(&command.args), &home_dir);
+
+        let (script_name, rc_path) = match (rc_script_name(kind), rc_path) {
+            (Some(script_name), Some(rc_path)) => (script_name, rc_path),
+            _ => return Err(Error::UnsupportedShell(program)),
+        };
+
+        let mut script_path = shell_path;
+        script_path.push(script_name);
+
+        Ok(RcTarget {
+            program,
+            rc_path,
+            script_path,
+        })
+    }
+
+    fn block(&self) -> String {
+        let escaped_path = escape_shell_path(self.script_path.to_str().unwrap());
+        format!("\n{}\n{}{}\n{}\n", BLOCK_BEGIN, SOURCE_PREFIX, escaped_path, BLOCK_END)
+    }
 
-  let mut path = PathBuf::from_str(&home_dir)?;
-  path.push(".zshrc");
+    fn status(&self, content: &str) -> ShellIntegrationStatus {
+        let (_, sourced_paths) = strip_integration_blocks(content);
+        let script_path = self.script_path.to_str().unwrap().to_string();
 
-  let exist = path.exists();
+        let installed = sourced_paths.iter().any(|path| path == &script_path);
+        let stale_paths = sourced_paths
+            .into_iter()
+            .filter(|path| path != &script_path)
+            .collect();
+
+        ShellIntegrationStatus {
+            shell: self.program.clone(),
+            rc_file: self.rc_path.to_str().unwrap().to_string(),
+            script_path,
+            installed,
+            stale_paths,
+        }
+    }
+}
+
+/// Remove every block added by Terminal One, including the legacy ones.
+/// Returns the remaining content and the script paths sourced by the removed blocks.
+/// A block without its end marker is kept, the lines after it belong to the user.
+fn strip_integration_blocks(content: &str) -> (String, Vec<String>) {
+    let mut lines: Vec<&str> = Vec::new();
+    let mut sourced_paths = Vec::new();
+    let mut iter = content.lines().peekable();
+    let mut changed = false;
+
+    while let Some(line) = iter.next() {
+        if line == BLOCK_BEGIN {
+            let mut block = iter.clone();
+            let mut block_paths = Vec::new();
+            let mut terminated = false;
+            for block_line in block.by_ref() {
+                if block_line == BLOCK_END {
+                    terminated = true;
+                    break;
+                }
+                if let Some(path) = block_line.strip_prefix(SOURCE_PREFIX) {
+                    block_paths.push(unescape_shell_path(path));
+                }
+            }
+            if !terminated {
+                warn!("shell integration block without end marker is kept");
+                lines.push(line);
+                continue;
+            }
+            iter = block;
+            sourced_paths.extend(block_paths);
+        } else if line == LEGACY_BLOCK_HEADER
+            && iter.peek().map_or(false, |next| next.starts_with(SOURCE_PREFIX))
+        {
+            let source_line = iter.next().unwrap();
+            sourced_paths.push(unescape_shell_path(&source_line[SOURCE_PREFIX.len()..]));
+        } else {
+            lines.push(line);
+            continue;
+        }
+
+        changed = true;
+
+        // the empty line before the block is added by us as well
+        if lines.last() == Some(&"") {
+            lines.pop();
+        }
+    }
+
+    if !changed {
+        return (content.to_string(), sourced_paths);
+    }
+
+    let mut result = lines.join("\n");
+    if !result.is_empty() {
+        result.push('\n');
+    }
+    (result, sourced_paths)
+}
+
+fn backup_path(path: &Path, index: u32) -> PathBuf {
+    let mut result = path.as_os_str().to_os_string();
+    result.push(format!(".bak.{}", index));
+    PathBuf::from(result)
+}
+
+/// Copy the rc file to `<rc>.bak.1`, shifting the older backups,
+/// only the latest `MAX_BACKUPS` are kept.
+fn backup_rc_file(path: &Path) -> Result<()> {
+    let oldest = backup_path(path, MAX_BACKUPS);
+    if oldest.exists() {
+        std::fs::remove_file(&oldest)?;
+    }
+
+    for index in (1..MAX_BACKUPS).rev() {
+        let from = backup_path(path, index);
+        if from.exists() {
+            std::fs::rename(&from, backup_path(path, index + 1))?;
+        }
+    }
+
+    let latest = backup_path(path, 1);
+    std::fs::copy(path, &latest)?;
+    info!("backup {:?} to {:?}", path, latest);
+
+    Ok(())
+}
+
+fn write_rc_file(path: &Path, content: &str) -> Result<()> {
+    if path.exists() {
+        backup_rc_file(path)?;
+    } else if let Some(parent) = path.parent() {
+        // e.g. ~/.config/fish may not exist yet
+        std::fs::create_dir_all(parent)?;
+    }
+
+    // The rc file is often a symlink into a dotfiles repo,
+    // the target is replaced, not the link.
+    let target = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let mut temp_path = target.as_os_str().to_os_string();
+    temp_path.push(".t1.tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    // the shell never reads a half written file
+    let mut file = std::fs::File::create(&temp_path)?;
+    file.write_all(content.as_bytes())?;
+    file.sync_all()?;
+    if let Ok(metadata) = std::fs::metadata(&target) {
+        std::fs::set_permissions(&temp_path, metadata.permissions())?;
+    }
+    std::fs::rename(&temp_path, &target)?;
+
+    info!("write new content to {:?}", target);
+
+    Ok(())
+}
+
+#[tauri::command]
+pub fn shell_integration_status(app_handle: tauri::AppHandle, state: State<AppState>, shell: Option<String>) -> Result<ShellIntegrationStatus> {
+  let target = RcTarget::new(&app_handle, state.inner(), shell)?;
 
   // read the content of path as string, if the file does not exist, return ""
-  let content = std::fs::read_to_string(&path).unwrap_or("".to_string());
+  let content = std::fs::read_to_string(&target.rc_path).unwrap_or("".to_string());
+
+  Ok(target.status(&content))
+}
+
+/// Install the script, stale blocks left by an older app bundle are replaced.
+#[tauri::command]
+pub fn install_script(app_handle: tauri::AppHandle, state: State<AppState>, shell: Option<String>) -> Result<()> {
+  let target = RcTarget::new(&app_handle, state.inner(), shell)?;
+  info!("begin install script for {}", target.program);
+
+  let content = std::fs::read_to_string(&target.rc_path).unwrap_or("".to_string());
 
-  // check if the precommit_str is already in the content
-  let already_exist = content.contains(post_commit_str.as_str());
-  if already_exist {
+  let status = target.status(&content);
+  if status.installed && status.stale_paths.is_empty() {
     info!("precommit already exist");
     return Ok(());
   }
 
-  let new_content = content + post_commit_str.as_str();
-  if exist {
-    let backup_path = path.to_str().unwrap().to_string() + ".bak";
-    // move path to backup_path
-    std::fs::rename(&path, &backup_path)?;
-    info!("backup .zshrc to {}", backup_path);
+  if !status.stale_paths.is_empty() {
+    warn!("replace stale shell integration: {:?}", status.stale_paths);
   }
 
-  // open the file and write the new content with file options
-  let mut file = std::fs::OpenOptions::new()
-      .write(true)
-      .create(true)
-      .open(&path)?;
-  file.write_all(new_content.as_bytes())?;
+  let (content, _) = strip_integration_blocks(&content);
+  let new_content = content + target.block().as_str();
+
+  write_rc_file(&target.rc_path, &new_content)
+}
 
-  info!("write new content to .zshrc");
+#[tauri::command]
+pub fn uninstall_script(app_handle: tauri::AppHandle, state: State<AppState>, shell: Option<String>) -> Result<()> {
+  let target = RcTarget::new(&app_handle, state.inner(), shell)?;
+  info!("begin uninstall script for {}", target.program);
+
+  let test_read = std::fs::read_to_string(&target.rc_path);
+  if test_read.is_err() {
+    info!("{:?} not found, nothing to uninstall", target.rc_path);
+    return Ok(());
+  }
+
+  let content = test_read.unwrap();
+  let (new_content, removed) = strip_integration_blocks(&content);
+  if removed.is_empty() {
+    info!("shell integration is not installed");
+    return Ok(());
+  }
+
+  write_rc_file(&target.rc_path, &new_content)
+}
 
-  Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(path: &str) -> String {
+        format!("\n{}\n{}{}\n{}\n", BLOCK_BEGIN, SOURCE_PREFIX, path, BLOCK_END)
+    }
+
+    #[test]
+    fn strip_block() {
+        let content = format!("export A=1\n{}alias b=c\n", block("/app/t1-rc.zsh"));
+        let (result, paths) = strip_integration_blocks(&content);
+        assert_eq!(result, "export A=1\nalias b=c\n");
+        assert_eq!(paths, vec!["/app/t1-rc.zsh".to_string()]);
+    }
+
+    #[test]
+    fn strip_escaped_path() {
+        let content = block("/Applications/Terminal\\ One.app/t1-rc.zsh");
+        let (result, paths) = strip_integration_blocks(&content);
+        assert_eq!(result, "");
+        assert_eq!(paths, vec!["/Applications/Terminal One.app/t1-rc.zsh".to_string()]);
+    }
+
+    #[test]
+    fn keep_unterminated_block() {
+        let content = format!(
+            "export A=1\n{}\n{}/app/t1-rc.zsh\nalias b=c\nexport D=2\n",
+            BLOCK_BEGIN, SOURCE_PREFIX
+        );
+        let (result, paths) = strip_integration_blocks(&content);
+        assert_eq!(result, content);
+        assert!(paths.is_empty());
+    }
+
+    #[test]
+    fn strip_multiple_blocks() {
+        let content = format!(
+            "export A=1\n{}{}\n{}/old/t1-rc.zsh\nalias b=c\n",
+            block("/app/t1-rc.zsh"),
+            LEGACY_BLOCK_HEADER,
+            SOURCE_PREFIX
+        );
+        let (result, paths) = strip_integration_blocks(&content);
+        assert_eq!(result, "export A=1\nalias b=c\n");
+        assert_eq!(
+            paths,
+            vec!["/app/t1-rc.zsh".to_string(), "/old/t1-rc.zsh".to_string()]
+        );
+    }
+
+    #[test]
+    fn keep_unrelated_content() {
+        let content = "# Terminal One\nexport A=1\n";
+        let (result, paths) = strip_integration_blocks(content);
+        assert_eq!(result, content);
+        assert!(paths.is_empty());
+    }
 }
@@ -1,13 +1,39 @@
-use crate::messages::TermOptions;
-use crate::process_statistics::{fetch_process_statistics_by_pid, StatResult};
+use crate::asciicast::CastRecorder;
+use crate::daemon_client::open_daemon_pty;
+use crate::messages::{
+    OutputMetrics, PtyEncoding, ScreenResponse, ShellPhase, TermOptions, TerminalInfo,
+};
+use crate::osc_scanner::{OscEvent, OscScanner};
+use crate::output_buffer::OutputBuffer;
+use crate::terminal_screen::TerminalScreen;
+use crate::process_killer::ProcessKiller;
+use crate::process_statistics::{
+    fetch_foreground_process, fetch_process_statistics_by_pid, ForegroundProcess, StatResult,
+};
+use crate::settings::ExitAction;
+use crate::shell::ShellCommand;
+use crate::utf8_chunker::Utf8Chunker;
 use crate::Result;
 use log::{debug, error, info, warn};
 use notify_debouncer_mini::{new_debouncer, notify::*, DebounceEventResult, Debouncer};
-use portable_pty::{native_pty_system, Child, CommandBuilder, ExitStatus, MasterPty, PtySize};
+use portable_pty::{
+    native_pty_system, Child, ChildKiller, CommandBuilder, ExitStatus, MasterPty, PtySize,
+};
 use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+// `tcgetpgrp` is cheap, the process is only looked up when the group changes.
+const FOREGROUND_POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+// A shell which can't start would be restarted forever.
+const MIN_RESTART_UPTIME: Duration = Duration::from_secs(1);
+
+const INIT_ROWS: u16 = 24;
+const INIT_COLS: u16 = 80;
 
 #[allow(dead_code)]
 const ZDOTDIR: &str = "ZDOTDIR";
@@ -15,51 +41,123 @@ const ZDOTDIR: &str = "ZDOTDIR";
 const USER_ZDOTDIR: &str = "USER_ZDOTDIR";
 
 pub(crate) trait TerminalDelegateEventHandler {
-    fn handle_data(&self, terminal: &TerminalDelegate, data: &[u8]) -> Result<()>;
-    fn handle_exit(&self, id: String, exit_statue: ExitStatus) -> Result<()>;
+    /// `offset` is where the data ends in the output stream,
+    /// the frontend acks it when the data is written.
+    fn handle_data(&self, terminal: &TerminalDelegate, data: &[u8], offset: u64) -> Result<()>;
+    /// Also called for a terminal which is closed, with the final status
+    /// of its shell once it's killed, `terminal.is_closed()` is true then.
+    fn handle_exit(&self, terminal: &TerminalDelegate, exit_status: ExitStatus) -> Result<()>;
     fn handle_fs_changed(&self, id: String, path: Vec<String>) -> Result<()>;
+    fn handle_cwd_changed(&self, id: String, cwd: String) -> Result<()>;
+    fn handle_title_changed(&self, id: String, title: String) -> Result<()>;
+    fn handle_foreground_changed(&self, id: String, process: ForegroundProcess) -> Result<()>;
+    fn handle_command_finished(
+        &self,
+        id: String,
+        exit_code: Option<i32>,
+        duration: Option<Duration>,
+    ) -> Result<()>;
+}
+
+/// The signal which ended the process, `ExitStatus` only shows it.
+pub(crate) fn exit_signal(status: &ExitStatus) -> Option<String> {
+    status
+        .to_string()
+        .strip_prefix("Terminated by ")
+        .map(|signal| signal.to_string())
+}
+
+/// Where the PTY of a terminal lives.
+#[derive(Debug, Clone)]
+pub(crate) enum PtyBackend {
+    /// Owned by the app, the shell exits with it.
+    Local,
+    /// Owned by the session daemon listening on `socket`,
+    /// the session `attach` is reattached if it's still running.
+    Daemon {
+        socket: PathBuf,
+        attach: Option<String>,
+    },
+}
+
+/// The changes of `TerminalInfo` which are pushed to the event handler.
+enum InfoChanged {
+    Cwd(String),
+    Title(String),
+    CommandFinished(Option<i32>, Option<Duration>),
 }
 
 #[derive(Clone)]
 pub(crate) struct TerminalDelegate {
     inner: Arc<Mutex<TerminalDelegateInner>>,
     _event_handler: Arc<Mutex<Box<dyn TerminalDelegateEventHandler + Send>>>,
+    output: Arc<OutputBuffer>,
+    screen: Arc<Mutex<TerminalScreen>>,
+    recorder: Arc<Mutex<Option<CastRecorder>>>,
 }
 
 impl TerminalDelegate {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
         id: String,
         path: Option<String>,
         shell_path: PathBuf,
+        shell: ShellCommand,
         envs: BTreeMap<String, Option<String>>,
+        scrollback: usize,
+        preamble: Option<Vec<u8>>,
+        backend: PtyBackend,
+        kill_grace_period: Duration,
         event_handler: Box<dyn TerminalDelegateEventHandler + Send>,
     ) -> Result<TerminalDelegate> {
-        let (inner, mut child) = TerminalDelegateInner::new(
+        let (inner, mut child, attached) = TerminalDelegateInner::new(
             id.clone(),
             path,
             shell_path,
+            shell,
             envs,
+            backend,
+            kill_grace_period,
         )?;
 
         let event_handler = Arc::new(Mutex::new(event_handler));
+        let output = Arc::new(OutputBuffer::new());
+        let screen = Arc::new(Mutex::new(TerminalScreen::new(
+            INIT_ROWS,
+            INIT_COLS,
+            scrollback,
+        )));
         let delegate = TerminalDelegate {
             inner: Arc::new(Mutex::new(inner)),
             _event_handler: event_handler.clone(),
+            output: output.clone(),
+            screen: screen.clone(),
+            recorder: Arc::new(Mutex::new(None)),
         };
 
-        // <-- thread to read from the child process and send to the terminal
+        // e.g. the restored scrollback, shown before anything of the shell.
+        // An attached session replays its own output instead.
+        if let Some(preamble) = preamble.filter(|_| !attached) {
+            screen.lock().unwrap().process(preamble.as_ref());
+            output.push(preamble.as_ref());
+            // The offsets of the OSC marks and of `PtyResponse` share one basis,
+            // the whole stream sent to the frontend.
+            delegate.inner.lock().unwrap().output_offset = preamble.len() as u64;
+        }
+
+        // <-- thread to read from the child process
         let delegate_clone = delegate.clone();
         let mut reader = delegate_clone.try_clone_reader()?;
         let reader_id = id.clone();
         let reader_event_handler = event_handler.clone();
+        let reader_output = output.clone();
+        let reader_screen = screen.clone();
         std::thread::spawn(move || {
             info!("begin reader thread: {}", reader_id);
+            let mut scanner = OscScanner::new();
+            // Consume the output from the child
+            let mut buffer: Vec<u8> = vec![0; READ_BUFFER_SIZE];
             loop {
-                // Consume the output from the child
-                let mut buffer: Vec<u8> = vec![0; 4096];
-                // let mut s = String::new();
-                // reader.read_to_string(&mut s).unwrap();
-
                 let test_size = reader.read(&mut buffer);
 
                 if test_size.is_err() {
@@ -75,17 +173,130 @@ impl TerminalDelegate {
                     break;
                 }
 
-                {
+                let osc_events = scanner.feed(buffer[0..size].as_ref());
+                let changes = delegate_clone.apply_osc_events(size, osc_events);
+
+                if !changes.is_empty() {
                     let event_handler_lock = reader_event_handler.lock().unwrap();
-                    event_handler_lock
-                        .handle_data(&delegate_clone, buffer[0..size].as_ref())
-                        .unwrap();
+                    for change in changes {
+                        let result = match change {
+                            InfoChanged::Cwd(cwd) => {
+                                event_handler_lock.handle_cwd_changed(reader_id.clone(), cwd)
+                            }
+                            InfoChanged::Title(title) => event_handler_lock
+                                .handle_title_changed(reader_id.clone(), title),
+                            InfoChanged::CommandFinished(exit_code, duration) => {
+                                event_handler_lock.handle_command_finished(
+                                    reader_id.clone(),
+                                    exit_code,
+                                    duration,
+                                )
+                            }
+                        };
+                        if let Err(err) = result {
+                            error!("handle info changed error: {}, id: {}", err, reader_id);
+                        }
+                    }
+                }
+
+                {
+                    let mut screen = reader_screen.lock().unwrap();
+                    screen.process(buffer[0..size].as_ref());
                 }
+
+                // blocks when the frontend is behind
+                reader_output.push(buffer[0..size].as_ref());
             }
+            reader_output.close();
             info!("end reader thread: {}", reader_id);
         });
 
-        let monitor_id = id.clone();
+        // <-- thread to send the output to the terminal in batches
+        let emitter_delegate = delegate.clone();
+        let emitter_id = id.clone();
+        let emitter_event_handler = event_handler.clone();
+        let emitter_output = output.clone();
+        std::thread::spawn(move || {
+            let mut chunker = Utf8Chunker::new();
+            while let Some((batch, offset)) = emitter_output.pop_batch() {
+                // Never split a multibyte character between two events,
+                // the output of a non UTF-8 program is sent as it is.
+                let data = match emitter_delegate.output_encoding() {
+                    PtyEncoding::Utf8 => chunker.push(batch.as_ref()),
+                    PtyEncoding::Base64 => chunker.push_raw(batch.as_ref()),
+                };
+                if data.is_empty() {
+                    continue;
+                }
+                emitter_delegate.record_output(data.as_ref());
+                let event_handler_lock = emitter_event_handler.lock().unwrap();
+                let result = event_handler_lock.handle_data(&emitter_delegate, data.as_ref(), offset);
+                if let Err(err) = result {
+                    error!("handle data error: {}, id: {}", err, emitter_id);
+                    emitter_output.drop_sent(data.len());
+                }
+            }
+
+            let rest = chunker.flush();
+            if !rest.is_empty() {
+                emitter_delegate.record_output(rest.as_ref());
+                let offset = emitter_output.metrics().sent_bytes;
+                let event_handler_lock = emitter_event_handler.lock().unwrap();
+                let result = event_handler_lock.handle_data(&emitter_delegate, rest.as_ref(), offset);
+                if let Err(err) = result {
+                    error!("handle data error: {}, id: {}", err, emitter_id);
+                    emitter_output.drop_sent(rest.len());
+                }
+            }
+            if let Err(err) = emitter_delegate.stop_recording() {
+                error!("stop recording error: {}, id: {}", err, emitter_id);
+            }
+            info!("end emitter thread: {}", emitter_id);
+        });
+
+        // <-- thread to watch the foreground process group
+        let foreground_delegate = delegate.clone();
+        let foreground_id = id.clone();
+        let foreground_event_handler = event_handler.clone();
+        std::thread::spawn(move || {
+            let mut last_pgid = None;
+            loop {
+                std::thread::sleep(FOREGROUND_POLL_INTERVAL);
+                let (pgid, shell_pid) = {
+                    let inner = foreground_delegate.inner.lock().unwrap();
+                    let master = match inner.master.as_ref() {
+                        Some(master) => master,
+                        None => break,
+                    };
+                    // the daemon owns the PTY of its sessions
+                    match master.process_group_leader() {
+                        Some(pgid) if pgid > 0 => (pgid as u32, inner.process_id),
+                        _ => continue,
+                    }
+                };
+                if last_pgid == Some(pgid) {
+                    continue;
+                }
+                // retried on the next poll
+                let process = match fetch_foreground_process(pgid, shell_pid) {
+                    Some(process) => process,
+                    None => continue,
+                };
+                last_pgid = Some(pgid);
+                debug!("foreground changed: {:?}, id: {}", process, foreground_id);
+
+                foreground_delegate.inner.lock().unwrap().info.foreground = Some(process.clone());
+                let event_handler_lock = foreground_event_handler.lock().unwrap();
+                if let Err(err) =
+                    event_handler_lock.handle_foreground_changed(foreground_id.clone(), process)
+                {
+                    error!("handle foreground changed error: {}, id: {}", err, foreground_id);
+                }
+            }
+            info!("end foreground thread: {}", foreground_id);
+        });
+
+        let monitor_delegate = delegate.clone();
         let monitor_event_handler = event_handler.clone();
         std::thread::spawn(move || {
             let test_wait = child.wait();
@@ -95,12 +306,17 @@ impl TerminalDelegate {
             }
             let wait_result = test_wait.unwrap();
             info!("wait result: {}, id: {}", wait_result, id);
+            // Reported before the terminal is marked closed,
+            // `is_closed` is only true when it was closed on purpose.
             {
                 let event_handler_lock = monitor_event_handler.lock().unwrap();
-                event_handler_lock
-                    .handle_exit(monitor_id, wait_result)
-                    .unwrap();
+                if let Err(err) = event_handler_lock.handle_exit(&monitor_delegate, wait_result) {
+                    error!("handle exit error: {}, id: {}", err, id);
+                }
             }
+            // The PTY is kept open until the shell exits when it's closed,
+            // so a daemon session can report how it's ended.
+            monitor_delegate.inner.lock().unwrap().close();
         });
 
         if cfg!(target_os = "macos") {
@@ -133,17 +349,170 @@ impl TerminalDelegate {
         self.inner.lock().unwrap().id.clone()
     }
 
+    pub(crate) fn output_encoding(&self) -> PtyEncoding {
+        self.inner.lock().unwrap().output_encoding
+    }
+
+    pub(crate) fn set_output_encoding(&self, encoding: PtyEncoding) {
+        self.inner.lock().unwrap().output_encoding = encoding;
+    }
+
+    pub(crate) fn ack_output(&self, offset: u64) {
+        self.output.ack(offset);
+    }
+
+    pub(crate) fn output_metrics(&self) -> OutputMetrics {
+        self.output.metrics()
+    }
+
+    /// The id of the session in the daemon, to attach it on next launch.
+    pub(crate) fn session_id(&self) -> String {
+        let inner = self.inner.lock().unwrap();
+        inner.session_id.clone().unwrap_or_else(|| inner.id.clone())
+    }
+
+    pub(crate) fn profile(&self) -> Option<String> {
+        self.inner.lock().unwrap().profile.clone()
+    }
+
+    pub(crate) fn set_profile(&self, profile: Option<String>) {
+        self.inner.lock().unwrap().profile = profile;
+    }
+
+    /// What to do now that the shell has exited, a shell which exits
+    /// right after starting is kept instead of restarted.
+    pub(crate) fn exit_action(&self) -> ExitAction {
+        let inner = self.inner.lock().unwrap();
+        if inner.exit_action == ExitAction::Restart
+            && inner.started_at.elapsed() < MIN_RESTART_UPTIME
+        {
+            return ExitAction::Keep;
+        }
+        inner.exit_action
+    }
+
+    pub(crate) fn set_exit_action(&self, exit_action: ExitAction) {
+        self.inner.lock().unwrap().exit_action = exit_action;
+    }
+
+    /// How long the shell has been running.
+    pub(crate) fn uptime(&self) -> Duration {
+        self.inner.lock().unwrap().started_at.elapsed()
+    }
+
+    /// The cwd reported by the shell, or the path it's started in.
+    pub(crate) fn cwd(&self) -> Option<String> {
+        let inner = self.inner.lock().unwrap();
+        inner.info.cwd.clone().or_else(|| inner.init_path.clone())
+    }
+
+    /// The last `max_lines` lines of the scrollback and the screen as plain text.
+    pub(crate) fn snapshot(&self, max_lines: usize) -> String {
+        let mut screen = self.screen.lock().unwrap();
+        let total_lines = screen.total_lines();
+        let start = total_lines.saturating_sub(max_lines);
+        let resp = screen.lines(start, max_lines, false);
+
+        let mut lines: Vec<String> = resp.lines.into_iter().map(|line| line.text).collect();
+        while lines.last().map_or(false, |line| line.is_empty()) {
+            lines.pop();
+        }
+        lines.join("\n")
+    }
+
+    pub(crate) fn info(&self) -> TerminalInfo {
+        self.inner.lock().unwrap().info.clone()
+    }
+
+    fn apply_osc_events(&self, size: usize, events: Vec<(usize, OscEvent)>) -> Vec<InfoChanged> {
+        let mut inner = self.inner.lock().unwrap();
+        let base_offset = inner.output_offset;
+        inner.output_offset += size as u64;
+
+        events
+            .into_iter()
+            .filter_map(|(offset, event)| inner.apply_osc_event(base_offset + offset as u64, event))
+            .collect()
+    }
+
     pub(crate) fn resize(&self, rows: u16, cols: u16) -> Result<()> {
+        {
+            let mut screen = self.screen.lock().unwrap();
+            screen.resize(rows, cols);
+        }
+        if let Some(recorder) = self.recorder.lock().unwrap().as_mut() {
+            recorder.resize(cols, rows)?;
+        }
         let mut inner = self.inner.lock().unwrap();
         inner.resize(rows, cols)
     }
 
+    /// Record the output from now on into the asciicast file at `path`.
+    pub(crate) fn start_recording(&self, path: &Path) -> Result<()> {
+        let (rows, cols) = self.screen.lock().unwrap().size();
+        let shell = self.inner.lock().unwrap().shell.clone();
+        let recorder = CastRecorder::create(path, cols, rows, &shell)?;
+        let mut current = self.recorder.lock().unwrap();
+        if let Some(previous) = current.replace(recorder) {
+            previous.finish()?;
+        }
+        Ok(())
+    }
+
+    /// Returns the path of the recording, if there is one.
+    pub(crate) fn stop_recording(&self) -> Result<Option<PathBuf>> {
+        let recorder = self.recorder.lock().unwrap().take();
+        match recorder {
+            Some(recorder) => Ok(Some(recorder.finish()?)),
+            None => Ok(None),
+        }
+    }
+
+    fn record_output(&self, data: &[u8]) {
+        let mut recorder = self.recorder.lock().unwrap();
+        if let Some(current) = recorder.as_mut() {
+            // the data always ends on a character boundary
+            if let Err(err) = current.output(&String::from_utf8_lossy(data)) {
+                error!("recording error: {}", err);
+                *recorder = None;
+            }
+        }
+    }
+
+    pub(crate) fn screen(&self, styled: bool) -> ScreenResponse {
+        let mut screen = self.screen.lock().unwrap();
+        screen.visible(styled)
+    }
+
+    pub(crate) fn scrollback(&self, start: usize, count: usize, styled: bool) -> ScreenResponse {
+        let mut screen = self.screen.lock().unwrap();
+        screen.lines(start, count, styled)
+    }
+
     pub(crate) fn set_options(&self, options: TermOptions) -> Result<()> {
         let event_handler = self._event_handler.clone();
         let mut inner = self.inner.lock().unwrap();
         inner.set_options(options, event_handler)
     }
 
+    /// The pid of the shell.
+    pub(crate) fn process_id(&self) -> Option<u32> {
+        self.inner.lock().unwrap().process_id
+    }
+
+    /// What runs in the foreground now, the shell itself at the prompt.
+    pub(crate) fn fetch_foreground(&self) -> Option<ForegroundProcess> {
+        let (pgid, shell_pid) = {
+            let inner = self.inner.lock().unwrap();
+            let pgid = inner.master.as_ref()?.process_group_leader()?;
+            (pgid, inner.process_id)
+        };
+        if pgid <= 0 {
+            return None;
+        }
+        fetch_foreground_process(pgid as u32, shell_pid)
+    }
+
     pub(crate) fn fetch_statistics(&self) -> StatResult {
         let process_id = {
             let inner = self.inner.lock().unwrap();
@@ -155,15 +524,30 @@ impl TerminalDelegate {
             .unwrap_or_default()
     }
 
-    #[allow(unused)]
     pub(crate) fn is_closed(&self) -> bool {
         let inner = self.inner.lock().unwrap();
         inner.is_closed
     }
 
+    /// Close the terminal and end its shell with all the processes it has started,
+    /// `handle_exit` gets the final status of the shell.
     pub(crate) fn close(&self) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.is_closed {
+            return;
+        }
+        inner.is_closed = true;
+        inner.writer = None;
+        inner.kill_process_tree();
+        drop(inner);
+        self.output.close();
+    }
+
+    /// Close the terminal and leave its shell running in the daemon.
+    pub(crate) fn detach(&self) {
         let mut inner = self.inner.lock().unwrap();
         inner.close();
+        self.output.close();
     }
 }
 
@@ -192,14 +576,30 @@ impl std::io::Write for TerminalDelegate {
 
 struct TerminalDelegateInner {
     id: String,
+    init_path: Option<String>,
+    profile: Option<String>,
     #[allow(dead_code)]
     shell_path: PathBuf,
+    /// The program of the shell, from the settings or the profile.
+    shell: String,
     process_id: Option<u32>,
     is_closed: bool,
     master: Option<Box<dyn MasterPty + Send>>,
+    /// Set for a daemon session, closing the connection doesn't end the shell.
+    session_id: Option<String>,
+    killer: Option<Box<dyn ChildKiller + Send + Sync>>,
     writer: Option<Box<dyn std::io::Write + Send>>,
     options: Option<TermOptions>,
     fs_watcher: Option<Debouncer<FsEventWatcher>>,
+    output_encoding: PtyEncoding,
+    info: TerminalInfo,
+    /// The total bytes of the output stream, the preamble included.
+    output_offset: u64,
+    /// How long the processes have to exit before they are signaled harder.
+    kill_grace_period: Duration,
+    started_at: Instant,
+    exit_action: ExitAction,
+    command_started_at: Option<Instant>,
 }
 
 #[allow(dead_code)]
@@ -222,15 +622,14 @@ impl TerminalDelegateInner {
         id: String,
         path: Option<String>,
         shell_path: PathBuf,
+        shell: ShellCommand,
         envs: BTreeMap<String, Option<String>>,
-    ) -> Result<(TerminalDelegateInner, Box<dyn Child + Send + Sync>)> {
-        // Use the native pty implementation for the system
-        let pty_system = native_pty_system();
-
-        // Create a new pty
-        let pair = pty_system.openpty(PtySize {
-            rows: 24,
-            cols: 80,
+        backend: PtyBackend,
+        kill_grace_period: Duration,
+    ) -> Result<(TerminalDelegateInner, Box<dyn Child + Send + Sync>, bool)> {
+        let size = PtySize {
+            rows: INIT_ROWS,
+            cols: INIT_COLS,
             // Not all systems support pixel_width, pixel_height,
             // but it is good practice to set it to something
             // that matches the size of the selected font.  That
@@ -238,16 +637,15 @@ impl TerminalDelegateInner {
             // brief example though!
             pixel_width: 0,
             pixel_height: 0,
-        })?;
+        };
 
         // let dot_dir_str = shell_path.to_str().unwrap();
         // let user_dot_dir_str = get_user_zdot_dir();
 
         // Spawn a shell into the pty
         // add params to cmd
-        let mut cmd = CommandBuilder::new("/bin/zsh");
-        cmd.arg("-i");
-        cmd.arg("--login");
+        let mut cmd = CommandBuilder::new(&shell.program);
+        cmd.args(&shell.args);
         let version = env!("CARGO_PKG_VERSION");
         cmd.env("TERM_PROGRAM", "Terminal_One.app");
         cmd.env("TERM_PROGRAM_VERSION", version);
@@ -264,6 +662,10 @@ impl TerminalDelegateInner {
             }
         }
 
+        for (key, value) in &shell.envs {
+            cmd.env(key, value);
+        }
+
         if let Some(path) = path.as_ref() {
             cmd.cwd(path);
         } else {
@@ -271,25 +673,74 @@ impl TerminalDelegateInner {
             cmd.cwd(&home_dir);
         }
 
-        let child = pair.slave.spawn_command(cmd)?;
-        let process_id = child.process_id();
+        let (master, child, session_id, killer, attached) = match backend {
+            PtyBackend::Local => {
+                // Use the native pty implementation for the system
+                let pty_system = native_pty_system();
+
+                // Create a new pty
+                let pair = pty_system.openpty(size)?;
 
-        drop(pair.slave);
+                let child = pair.slave.spawn_command(cmd)?;
 
-        let writer = pair.master.take_writer()?;
+                drop(pair.slave);
+
+                (pair.master, child, None, None, false)
+            }
+            PtyBackend::Daemon { socket, attach } => {
+                let pty = open_daemon_pty(
+                    &socket,
+                    &id,
+                    attach.as_deref(),
+                    cmd,
+                    size,
+                    kill_grace_period,
+                )?;
+                info!(
+                    "daemon session {} for terminal {}, attached: {}",
+                    pty.session_id, id, pty.attached
+                );
+                let killer = pty.child.clone_killer();
+                (
+                    pty.master,
+                    pty.child,
+                    Some(pty.session_id),
+                    Some(killer),
+                    pty.attached,
+                )
+            }
+        };
+        let process_id = child.process_id();
+
+        let writer = master.take_writer()?;
 
         let inner = TerminalDelegateInner {
             id: id.clone(),
+            init_path: path,
+            profile: None,
             shell_path,
+            shell: shell.program.clone(),
             process_id,
             is_closed: false,
-            master: Some(pair.master),
+            master: Some(master),
+            session_id,
+            killer,
             writer: Some(writer),
             options: None,
             fs_watcher: None::<Debouncer<FsEventWatcher>>,
+            output_encoding: PtyEncoding::default(),
+            info: TerminalInfo {
+                id: id.clone(),
+                ..TerminalInfo::default()
+            },
+            output_offset: 0,
+            kill_grace_period,
+            started_at: Instant::now(),
+            exit_action: ExitAction::default(),
+            command_started_at: None,
         };
 
-        Ok((inner, child))
+        Ok((inner, child, attached))
     }
 
     fn set_options(
@@ -349,6 +800,54 @@ impl TerminalDelegateInner {
         Ok(())
     }
 
+    fn apply_osc_event(&mut self, offset: u64, event: OscEvent) -> Option<InfoChanged> {
+        match event {
+            OscEvent::CwdChanged(cwd) => {
+                if self.info.cwd.as_ref() == Some(&cwd) {
+                    return None;
+                }
+                self.info.cwd = Some(cwd.clone());
+                Some(InfoChanged::Cwd(cwd))
+            }
+            OscEvent::TitleChanged(title) => {
+                if self.info.title.as_ref() == Some(&title) {
+                    return None;
+                }
+                self.info.title = Some(title.clone());
+                Some(InfoChanged::Title(title))
+            }
+            OscEvent::PromptStart => {
+                self.info.phase = ShellPhase::Prompt;
+                self.info.prompt_start = Some(offset);
+                None
+            }
+            OscEvent::CommandStart => {
+                self.info.phase = ShellPhase::Input;
+                self.info.command_start = Some(offset);
+                None
+            }
+            OscEvent::CommandExecuted => {
+                self.info.phase = ShellPhase::Running;
+                self.info.output_start = Some(offset);
+                self.info.output_end = None;
+                self.command_started_at = Some(Instant::now());
+                None
+            }
+            OscEvent::CommandFinished(exit_code) => {
+                // The shell reports D before the first prompt as well,
+                // there is no command to finish then.
+                let started_at = self.command_started_at.take()?;
+                self.info.phase = ShellPhase::Unknown;
+                self.info.output_end = Some(offset);
+                self.info.last_exit_code = exit_code;
+                Some(InfoChanged::CommandFinished(
+                    exit_code,
+                    Some(started_at.elapsed()),
+                ))
+            }
+        }
+    }
+
     fn resize(&mut self, rows: u16, cols: u16) -> Result<()> {
         if let Some(master) = self.master.as_mut() {
             master.resize(PtySize {
@@ -361,8 +860,212 @@ impl TerminalDelegateInner {
         Ok(())
     }
 
+    fn kill_process_tree(&mut self) {
+        // The daemon owns the processes of its sessions.
+        if let Some(killer) = self.killer.as_mut() {
+            if let Err(err) = killer.kill() {
+                warn!("kill session error: {}, id: {}", err, self.id);
+            }
+            return;
+        }
+
+        let shell_pid = match self.process_id {
+            Some(pid) => pid,
+            None => return,
+        };
+        let foreground_pgid = self
+            .master
+            .as_ref()
+            .and_then(|master| master.process_group_leader());
+        let killer = ProcessKiller::new(shell_pid, foreground_pgid, self.kill_grace_period);
+        let id = self.id.clone();
+        std::thread::spawn(move || {
+            killer.kill();
+            debug!("process tree killed, id: {}", id);
+        });
+    }
+
     fn close(&mut self) {
+        self.is_closed = true;
         self.writer = None;
         self.master = None;
+        self.killer = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    const BENCH_BYTES: u64 = 256 * 1024 * 1024;
+
+    /// Acks every batch right away, like the frontend when it keeps up.
+    #[derive(Default)]
+    struct CountingEventHandler {
+        received: Arc<AtomicU64>,
+        /// Whether the terminal was closed, and the signal of the shell.
+        exits: Arc<Mutex<Vec<(bool, Option<String>)>>>,
+    }
+
+    impl TerminalDelegateEventHandler for CountingEventHandler {
+        fn handle_data(&self, terminal: &TerminalDelegate, data: &[u8], offset: u64) -> Result<()> {
+            self.received.fetch_add(data.len() as u64, Ordering::SeqCst);
+            terminal.ack_output(offset);
+            Ok(())
+        }
+
+        fn handle_exit(&self, terminal: &TerminalDelegate, exit_status: ExitStatus) -> Result<()> {
+            let exit = (terminal.is_closed(), exit_signal(&exit_status));
+            self.exits.lock().unwrap().push(exit);
+            Ok(())
+        }
+
+        fn handle_fs_changed(&self, _id: String, _path: Vec<String>) -> Result<()> {
+            Ok(())
+        }
+
+        fn handle_cwd_changed(&self, _id: String, _cwd: String) -> Result<()> {
+            Ok(())
+        }
+
+        fn handle_title_changed(&self, _id: String, _title: String) -> Result<()> {
+            Ok(())
+        }
+
+        fn handle_foreground_changed(
+            &self,
+            _id: String,
+            _process: ForegroundProcess,
+        ) -> Result<()> {
+            Ok(())
+        }
+
+        fn handle_command_finished(
+            &self,
+            _id: String,
+            _exit_code: Option<i32>,
+            _duration: Option<Duration>,
+        ) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn sh_command(script: &str) -> ShellCommand {
+        ShellCommand {
+            program: "/bin/sh".to_string(),
+            args: vec!["-c".to_string(), script.to_string()],
+            envs: BTreeMap::new(),
+        }
+    }
+
+    fn wait_exit(exits: &Mutex<Vec<(bool, Option<String>)>>) -> (bool, Option<String>) {
+        let started_at = Instant::now();
+        loop {
+            if let Some(exit) = exits.lock().unwrap().first() {
+                return exit.clone();
+            }
+            assert!(started_at.elapsed() < Duration::from_secs(5), "no exit");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn close_reports_final_exit() {
+        let event_handler = CountingEventHandler::default();
+        let exits = event_handler.exits.clone();
+        let delegate = TerminalDelegate::new(
+            "close".to_string(),
+            None,
+            PathBuf::new(),
+            sh_command("sleep 30"),
+            BTreeMap::new(),
+            1000,
+            None,
+            PtyBackend::Local,
+            Duration::from_millis(100),
+            Box::new(event_handler),
+        )
+        .unwrap();
+
+        delegate.close();
+        let (closed, signal) = wait_exit(&exits);
+        assert!(closed);
+        assert!(signal.is_some());
+    }
+
+    #[test]
+    fn exit_is_reported_as_not_closed() {
+        let event_handler = CountingEventHandler::default();
+        let exits = event_handler.exits.clone();
+        let _delegate = TerminalDelegate::new(
+            "exit".to_string(),
+            None,
+            PathBuf::new(),
+            sh_command("exit 3"),
+            BTreeMap::new(),
+            1000,
+            None,
+            PtyBackend::Local,
+            Duration::from_millis(100),
+            Box::new(event_handler),
+        )
+        .unwrap();
+
+        let (closed, signal) = wait_exit(&exits);
+        assert!(!closed);
+        assert!(signal.is_none());
+    }
+
+    /// cargo test --release output_throughput -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn output_throughput() {
+        let received = Arc::new(AtomicU64::new(0));
+        let shell = ShellCommand {
+            program: "/bin/sh".to_string(),
+            args: vec![
+                "-c".to_string(),
+                // no newline, the PTY would turn it into CRLF
+                format!("head -c {} /dev/zero | tr '\\0' x", BENCH_BYTES),
+            ],
+            envs: BTreeMap::new(),
+        };
+
+        let started_at = Instant::now();
+        let delegate = TerminalDelegate::new(
+            "bench".to_string(),
+            None,
+            PathBuf::new(),
+            shell,
+            BTreeMap::new(),
+            1000,
+            None,
+            PtyBackend::Local,
+            Duration::from_millis(500),
+            Box::new(CountingEventHandler {
+                received: received.clone(),
+                ..CountingEventHandler::default()
+            }),
+        )
+        .unwrap();
+
+        while received.load(Ordering::SeqCst) < BENCH_BYTES {
+            assert!(started_at.elapsed() < Duration::from_secs(300), "timed out");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        let elapsed = started_at.elapsed();
+
+        let metrics = delegate.output_metrics();
+        println!(
+            "{} MB in {:?}, {:.1} MB/s, {:?}",
+            BENCH_BYTES / 1024 / 1024,
+            elapsed,
+            BENCH_BYTES as f64 / 1024.0 / 1024.0 / elapsed.as_secs_f64(),
+            metrics
+        );
+        assert_eq!(received.load(Ordering::SeqCst), BENCH_BYTES);
+        assert_eq!(metrics.dropped_bytes, 0);
+        assert_eq!(metrics.ack_timeouts, 0);
     }
 }
@@ -0,0 +1,232 @@
+use crate::messages::{CellColor, ScreenCell, ScreenLine, ScreenResponse};
+
+/// A headless model of the screen and the scrollback of a terminal,
+/// fed with the same output as the frontend.
+pub(crate) struct TerminalScreen {
+    parser: vt100::Parser,
+}
+
+impl TerminalScreen {
+    pub(crate) fn new(rows: u16, cols: u16, scrollback: usize) -> TerminalScreen {
+        TerminalScreen {
+            parser: vt100::Parser::new(rows, cols, scrollback),
+        }
+    }
+
+    pub(crate) fn process(&mut self, data: &[u8]) {
+        self.parser.process(data);
+    }
+
+    pub(crate) fn resize(&mut self, rows: u16, cols: u16) {
+        self.parser.screen_mut().set_size(rows, cols);
+    }
+
+    /// The rows and the columns of the visible screen.
+    pub(crate) fn size(&self) -> (u16, u16) {
+        self.parser.screen().size()
+    }
+
+    /// The number of lines in the scrollback, not including the visible screen.
+    pub(crate) fn scrollback_len(&mut self) -> usize {
+        // The offset is clamped to the length of the scrollback.
+        self.parser.screen_mut().set_scrollback(usize::MAX);
+        let len = self.parser.screen().scrollback();
+        self.parser.screen_mut().set_scrollback(0);
+        len
+    }
+
+    /// The lines of the scrollback and the visible screen.
+    pub(crate) fn total_lines(&mut self) -> usize {
+        let (rows, _) = self.parser.screen().size();
+        self.scrollback_len() + rows as usize
+    }
+
+    pub(crate) fn visible(&mut self, styled: bool) -> ScreenResponse {
+        let scrollback_len = self.scrollback_len();
+        let (rows, _) = self.parser.screen().size();
+        self.lines(scrollback_len, rows as usize, styled)
+    }
+
+    /// Fetch `count` lines from `start`, the line 0 is the oldest line
+    /// of the scrollback and the visible screen comes last.
+    pub(crate) fn lines(&mut self, start: usize, count: usize, styled: bool) -> ScreenResponse {
+        let scrollback_len = self.scrollback_len();
+        let (rows, cols) = self.parser.screen().size();
+        let (cursor_row, cursor_col) = self.parser.screen().cursor_position();
+        let total_lines = scrollback_len + rows as usize;
+
+        let start = start.min(total_lines);
+        let end = start.saturating_add(count).min(total_lines);
+        let mut lines = Vec::with_capacity(end - start);
+
+        let mut line_index = start;
+        while line_index < end {
+            // Scroll the view so the line is as close to the top as possible,
+            // then read the rows of the view. The offset may be larger than
+            // the screen, the view is the `rows` lines from its top then.
+            let offset = scrollback_len.saturating_sub(line_index).min(scrollback_len);
+            self.parser.screen_mut().set_scrollback(offset);
+            let view_top = scrollback_len - offset;
+
+            let screen = self.parser.screen();
+            while line_index < end && line_index - view_top < rows as usize {
+                let row = (line_index - view_top) as u16;
+                lines.push(read_line(screen, row, cols, styled));
+                line_index += 1;
+            }
+        }
+
+        self.parser.screen_mut().set_scrollback(0);
+
+        ScreenResponse {
+            rows,
+            cols,
+            cursor_row,
+            cursor_col,
+            total_lines,
+            start,
+            lines,
+        }
+    }
+}
+
+fn read_line(screen: &vt100::Screen, row: u16, cols: u16, styled: bool) -> ScreenLine {
+    let text = screen
+        .rows(0, cols)
+        .nth(row as usize)
+        .unwrap_or_default()
+        .trim_end()
+        .to_string();
+
+    let cells = if styled {
+        let mut cells = Vec::with_capacity(cols as usize);
+        for col in 0..cols {
+            let cell = match screen.cell(row, col) {
+                Some(cell) => cell,
+                None => break,
+            };
+            if cell.is_wide_continuation() {
+                continue;
+            }
+            cells.push(ScreenCell {
+                contents: cell.contents().to_string(),
+                fg: to_cell_color(cell.fgcolor()),
+                bg: to_cell_color(cell.bgcolor()),
+                bold: cell.bold(),
+                italic: cell.italic(),
+                underline: cell.underline(),
+                inverse: cell.inverse(),
+            });
+        }
+        Some(cells)
+    } else {
+        None
+    };
+
+    ScreenLine { text, cells }
+}
+
+fn to_cell_color(color: vt100::Color) -> Option<CellColor> {
+    match color {
+        vt100::Color::Default => None,
+        vt100::Color::Idx(index) => Some(CellColor::Indexed { index }),
+        vt100::Color::Rgb(r, g, b) => Some(CellColor::Rgb { r, g, b }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROWS: u16 = 5;
+
+    /// 20 lines of text then the line of the cursor,
+    /// 16 of them are in the scrollback.
+    fn screen_with_scrollback() -> TerminalScreen {
+        let mut screen = TerminalScreen::new(ROWS, 20, 100);
+        for index in 0..20 {
+            screen.process(format!("line {}\r\n", index).as_bytes());
+        }
+        screen
+    }
+
+    fn texts(response: &ScreenResponse) -> Vec<String> {
+        response.lines.iter().map(|line| line.text.clone()).collect()
+    }
+
+    fn expected(range: std::ops::Range<usize>) -> Vec<String> {
+        range
+            .map(|index| {
+                if index < 20 {
+                    format!("line {}", index)
+                } else {
+                    String::new()
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn total_lines() {
+        let mut screen = screen_with_scrollback();
+        assert_eq!(screen.scrollback_len(), 16);
+        assert_eq!(screen.total_lines(), 21);
+    }
+
+    #[test]
+    fn visible_is_the_bottom() {
+        let mut screen = screen_with_scrollback();
+        let response = screen.visible(false);
+        assert_eq!(response.start, 16);
+        assert_eq!(response.total_lines, 21);
+        assert_eq!(texts(&response), expected(16..21));
+        assert_eq!((response.cursor_row, response.cursor_col), (4, 0));
+    }
+
+    #[test]
+    fn page_through_scrollback() {
+        let mut screen = screen_with_scrollback();
+
+        let first = screen.lines(0, ROWS as usize, false);
+        assert_eq!(first.start, 0);
+        assert_eq!(texts(&first), expected(0..5));
+
+        let mut all = Vec::new();
+        for start in (0..21).step_by(ROWS as usize) {
+            let page = screen.lines(start, ROWS as usize, false);
+            assert_eq!(page.start, start);
+            all.extend(texts(&page));
+        }
+        assert_eq!(all, expected(0..21));
+
+        let last = screen.lines(20, ROWS as usize, false);
+        assert_eq!(last.start, 20);
+        assert_eq!(texts(&last), expected(20..21));
+
+        // the view is scrolled back to the bottom
+        assert_eq!(texts(&screen.visible(false)), expected(16..21));
+    }
+
+    #[test]
+    fn page_larger_than_screen() {
+        let mut screen = screen_with_scrollback();
+        assert_eq!(texts(&screen.lines(0, 100, false)), expected(0..21));
+        assert_eq!(texts(&screen.lines(3, 12, false)), expected(3..15));
+    }
+
+    #[test]
+    fn start_past_the_end() {
+        let mut screen = screen_with_scrollback();
+        let response = screen.lines(100, ROWS as usize, false);
+        assert_eq!(response.start, 21);
+        assert!(response.lines.is_empty());
+    }
+
+    #[test]
+    fn without_scrollback() {
+        let mut screen = TerminalScreen::new(ROWS, 20, 100);
+        screen.process(b"hello");
+        assert_eq!(screen.total_lines(), ROWS as usize);
+        assert_eq!(texts(&screen.lines(0, 2, false)), vec!["hello", ""]);
+    }
+}
@@ -1,7 +1,38 @@
+use log::{debug, error, info, warn};
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+use schemars::JsonSchema;
 use serde::Serialize;
 
 use crate::Result;
-use std::{path::{Path, PathBuf}, ops::Index};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Used when `app.theme` is not set or not found.
+pub(crate) const DEFAULT_THEME: &str = "Atom";
+
+/// The themes of the user, they override the bundled ones with the same name.
+pub(crate) fn user_themes_dir(app_dir: &Path) -> PathBuf {
+    PathBuf::from(app_dir).join("User").join("themes")
+}
+
+/// A theme which can't be loaded, it's skipped.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ThemeError {
+    pub path: String,
+    pub message: String,
+}
+
+impl ThemeError {
+    fn new(path: &Path, message: impl Into<String>) -> ThemeError {
+        ThemeError {
+            path: path.to_string_lossy().to_string(),
+            message: message.into(),
+        }
+    }
+}
 
 #[derive(Clone, Serialize)]
 pub(crate) struct ThemeItem {
@@ -11,7 +42,21 @@ pub(crate) struct ThemeItem {
 }
 
 impl ThemeItem {
+    /// A theme is a directory with a `.toml` file, named after the directory,
+    /// or a single `.toml` file, named after the file.
     pub(crate) fn load_from_file(path: &Path) -> Result<Option<ThemeItem>> {
+        if path.is_file() {
+            if path.extension().map_or(false, |ext| ext == "toml") {
+                let name = path.file_stem().unwrap().to_string_lossy().to_string();
+                return Ok(Some(ThemeItem {
+                    name,
+                    path: path.to_path_buf(),
+                    toml_file_path: Some(path.to_path_buf()),
+                }));
+            }
+            return Ok(None);
+        }
+
         let dir_name = path.file_name().unwrap().to_str().unwrap();
         for entry in path.read_dir()? {
             let entry = entry?;
@@ -31,21 +76,86 @@ impl ThemeItem {
 
         return Ok(None);
     }
+
+    /// The frontend needs at least the `[colors]` table.
+    fn check(&self) -> std::result::Result<(), ThemeError> {
+        let toml_path = match &self.toml_file_path {
+            Some(toml_path) => toml_path,
+            None => return Ok(()),
+        };
+        let content = std::fs::read_to_string(toml_path)
+            .map_err(|err| ThemeError::new(toml_path, err.to_string()))?;
+        let table: toml::Table = toml::from_str(&content)
+            .map_err(|err| ThemeError::new(toml_path, err.to_string()))?;
+        if !table.get("colors").map_or(false, |colors| colors.is_table()) {
+            return Err(ThemeError::new(toml_path, "missing [colors] table"));
+        }
+        Ok(())
+    }
 }
 
 pub(crate) struct ThemeContext {
-    candidates: Vec<ThemeItem>,
+    candidates: BTreeMap<String, ThemeItem>,
 }
 
 impl ThemeContext {
     pub(crate) fn new() -> ThemeContext {
         ThemeContext {
-            candidates: Vec::new(),
+            candidates: BTreeMap::new(),
+        }
+    }
+
+    /// Load the themes of `dirs` in order, a theme overrides
+    /// the one with the same name in a previous directory.
+    pub(crate) fn load(dirs: &[PathBuf]) -> (ThemeContext, Vec<ThemeError>) {
+        let mut theme_context = ThemeContext::new();
+        let mut errors = Vec::new();
+
+        for dir in dirs {
+            debug!("Load themes from {:?}", dir);
+            // the user directory may not exist
+            if !dir.is_dir() {
+                continue;
+            }
+            let entries = match dir.read_dir() {
+                Ok(entries) => entries,
+                Err(err) => {
+                    errors.push(ThemeError::new(dir, err.to_string()));
+                    continue;
+                }
+            };
+
+            for entry in entries.flatten() {
+                let path = entry.path();
+                let item = match ThemeItem::load_from_file(&path) {
+                    Ok(Some(item)) => item,
+                    Ok(None) => continue,
+                    Err(err) => {
+                        errors.push(ThemeError::new(&path, err.to_string()));
+                        continue;
+                    }
+                };
+                if let Err(err) = item.check() {
+                    errors.push(err);
+                    continue;
+                }
+                debug!("Found theme {} in {:?}", item.name, item.path);
+                theme_context.add(item);
+            }
+        }
+
+        for err in &errors {
+            warn!("invalid theme: {}, {}", err.path, err.message);
+        }
+        if theme_context.is_empty() {
+            error!("no themes found in {:?}", dirs);
         }
+
+        (theme_context, errors)
     }
 
     pub(crate) fn add(&mut self, item: ThemeItem) {
-        self.candidates.push(item);
+        self.candidates.insert(item.name.clone(), item);
     }
 
     #[inline]
@@ -53,12 +163,59 @@ impl ThemeContext {
         self.candidates.is_empty()
     }
 
+    /// The names of the themes, sorted.
+    pub(crate) fn list_themes(&self) -> Vec<String> {
+        self.candidates.keys().cloned().collect()
+    }
+
+    pub(crate) fn get_theme(&self, name: &str) -> Option<&ThemeItem> {
+        self.candidates.get(name)
+    }
+
+    /// The theme named `name`, the default one if it's not found.
+    pub(crate) fn get_theme_or_default(&self, name: Option<&str>) -> Option<&ThemeItem> {
+        name.and_then(|name| self.get_theme(name))
+            .or_else(|| self.get_theme(DEFAULT_THEME))
+            .or_else(|| self.candidates.values().next())
+    }
+}
+
+pub(crate) trait ThemeWatcherEventHandler {
+    fn handle_themes_changed(
+        &self,
+        theme_context: ThemeContext,
+        errors: Vec<ThemeError>,
+    ) -> crate::Result<()>;
 }
 
-impl Index<usize> for ThemeContext {
-    type Output = ThemeItem;
+/// Reload the themes when a file in `dirs` changes.
+pub(crate) fn watch_themes(
+    dirs: Vec<PathBuf>,
+    event_handler: Box<dyn ThemeWatcherEventHandler + Send>,
+) -> crate::Result<Debouncer<RecommendedWatcher>> {
+    let watched_dirs = dirs.clone();
+    let mut debouncer = new_debouncer(
+        Duration::from_millis(300),
+        None,
+        move |res: DebounceEventResult| {
+            if let Err(errors) = res {
+                error!("watch themes error: {:?}", errors);
+                return;
+            }
+
+            let (theme_context, errors) = ThemeContext::load(&watched_dirs);
+            info!("themes changed: {:?}", theme_context.list_themes());
+            if let Err(err) = event_handler.handle_themes_changed(theme_context, errors) {
+                error!("handle themes changed error: {}", err);
+            }
+        },
+    )?;
 
-    fn index(&self, index: usize) -> &ThemeItem {
-        &self.candidates[index]
+    for dir in &dirs {
+        if dir.is_dir() {
+            debouncer.watcher().watch(dir, RecursiveMode::Recursive)?;
+        }
     }
+
+    Ok(debouncer)
 }
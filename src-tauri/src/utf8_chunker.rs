@@ -0,0 +1,140 @@
+/// Splits the PTY output on UTF-8 character boundaries.
+///
+/// A multibyte character may straddle two reads,
+/// the incomplete tail is held back and prepended to the next chunk.
+pub(crate) struct Utf8Chunker {
+    pending: Vec<u8>,
+}
+
+impl Utf8Chunker {
+    pub(crate) fn new() -> Utf8Chunker {
+        Utf8Chunker {
+            pending: Vec::new(),
+        }
+    }
+
+    /// Returns the bytes ending on a character boundary,
+    /// which may be empty if the whole chunk is an incomplete character.
+    pub(crate) fn push(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut chunk = std::mem::take(&mut self.pending);
+        chunk.extend_from_slice(data);
+
+        let cut = incomplete_tail_start(&chunk);
+        self.pending = chunk.split_off(cut);
+
+        chunk
+    }
+
+    /// Returns the bytes held back followed by the whole chunk,
+    /// for an output which isn't decoded as UTF-8, e.g. sent as base64.
+    pub(crate) fn push_raw(&mut self, data: &[u8]) -> Vec<u8> {
+        let mut chunk = std::mem::take(&mut self.pending);
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    /// Take the bytes held back, e.g. when the PTY reaches EOF.
+    pub(crate) fn flush(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// Find where a trailing incomplete character starts,
+/// `data.len()` if the data ends on a boundary.
+///
+/// Invalid sequences are not held back, they are replaced
+/// when the data is decoded.
+fn incomplete_tail_start(data: &[u8]) -> usize {
+    let len = data.len();
+
+    // A character is 4 bytes at most, so only the last 3 bytes
+    // may be the start of an incomplete one.
+    for back in 1..=len.min(3) {
+        let index = len - back;
+        let byte = data[index];
+
+        // continuation byte, keep looking for the lead byte
+        if byte & 0b1100_0000 == 0b1000_0000 {
+            continue;
+        }
+
+        let expected = match byte {
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF7 => 4,
+            _ => 1,
+        };
+
+        return if expected > back { index } else { len };
+    }
+
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii() {
+        let mut chunker = Utf8Chunker::new();
+        assert_eq!(chunker.push(b"hello"), b"hello");
+        assert!(chunker.flush().is_empty());
+    }
+
+    #[test]
+    fn multibyte_split_across_chunks() {
+        // 2, 3 and 4 bytes characters
+        let text = "é中🦀!".as_bytes();
+        for split in 0..=text.len() {
+            let mut chunker = Utf8Chunker::new();
+            let first = chunker.push(&text[..split]);
+            let second = chunker.push(&text[split..]);
+            assert!(std::str::from_utf8(&first).is_ok(), "split at {}", split);
+            assert!(std::str::from_utf8(&second).is_ok(), "split at {}", split);
+            assert_eq!([first, second].concat(), text, "split at {}", split);
+            assert!(chunker.flush().is_empty());
+        }
+    }
+
+    #[test]
+    fn byte_by_byte() {
+        let text = "🦀🦀".as_bytes();
+        let mut chunker = Utf8Chunker::new();
+        let mut chunks = Vec::new();
+        for byte in text {
+            let chunk = chunker.push(&[*byte]);
+            if !chunk.is_empty() {
+                chunks.push(String::from_utf8(chunk).unwrap());
+            }
+        }
+        assert_eq!(chunks, vec!["🦀", "🦀"]);
+    }
+
+    #[test]
+    fn incomplete_tail_is_flushed() {
+        let mut chunker = Utf8Chunker::new();
+        assert_eq!(chunker.push(b"a\xe4\xb8"), b"a");
+        assert_eq!(chunker.flush(), b"\xe4\xb8");
+    }
+
+    #[test]
+    fn raw_chunks_are_not_held_back() {
+        let mut chunker = Utf8Chunker::new();
+        // a Latin-1 prompt ending with é
+        assert_eq!(chunker.push_raw(b"caf\xe9"), b"caf\xe9");
+        assert!(chunker.flush().is_empty());
+
+        // held back before the encoding changed
+        assert_eq!(chunker.push(b"a\xe4"), b"a");
+        assert_eq!(chunker.push_raw(b"\xe9"), b"\xe4\xe9");
+        assert!(chunker.flush().is_empty());
+    }
+
+    #[test]
+    fn invalid_bytes_are_not_held_back() {
+        let mut chunker = Utf8Chunker::new();
+        assert_eq!(chunker.push(b"a\xff"), b"a\xff");
+        assert_eq!(chunker.push(b"\x80\x80\x80"), b"\x80\x80\x80");
+    }
+}
@@ -6,10 +6,14 @@ import { useTabSwitcher } from "@pkg/hooks/tab_switcher";
 import { usePtyExit } from "@pkg/hooks/pty_exit";
 import { MainContentLayout } from "@pkg/components/main_content_layout";
 import { exit } from "@tauri-apps/api/process";
+import { open } from "@tauri-apps/api/dialog";
 import { AppContext } from "@pkg/contexts/app_context";
 import { type UnlistenFn, listen } from "@tauri-apps/api/event";
 import { useBehaviorSubject } from "./hooks/observable";
 import { SettingsModal } from "@pkg/components/settings_modal";
+import { NEW_TAB_PROFILE_PREFIX } from "@pkg/constants";
+import type { PtyExitMessage } from "@pkg/bindings/messages";
+import { invoke } from "@tauri-apps/api";
 import "./App.css";
 
 const appState = new AppState();
@@ -23,8 +27,17 @@ function App() {
   const showOnboarding = useBehaviorSubject(appState.showOnboarding$);
 
   const handlePtyExit = useCallback(
-    async (id: string) => {
+    async (payload: PtyExitMessage) => {
       const { sessionManager } = appState;
+      const { id, action } = payload;
+      const session = sessionManager.sessionsMap.get(id);
+      if (session && action !== "close") {
+        session.exited$.next(payload);
+        if (action === "restart") {
+          await invoke("restart_terminal", { id });
+        }
+        return;
+      }
       sessionManager.removeTabById(id);
       if (sessionManager.sessions$.value.length === 0) {
         await exit(0);
@@ -34,7 +47,8 @@ function App() {
   );
 
   useEffect(() => {
-    appState.sessionManager.newTab();
+    // the saved sessions come with the init data
+    appState.init().then(() => appState.sessionManager.openInitialTabs());
   }, [appState]);
 
   useEffect(() => {
@@ -54,7 +68,22 @@ function App() {
           break;
         }
         case "close-tab": {
-          appState.sessionManager.closeTab();
+          appState.sessionManager.confirmCloseTab();
+          break;
+        }
+        case "toggle-recording": {
+          appState.sessionManager.toggleRecording();
+          break;
+        }
+        case "play-recording": {
+          open({
+            multiple: false,
+            filters: [{ name: "Asciicast", extensions: ["cast"] }],
+          }).then((path) => {
+            if (isString(path)) {
+              appState.sessionManager.newPlaybackTab(path);
+            }
+          });
           break;
         }
         case "explorer": {
@@ -66,6 +95,15 @@ function App() {
           break;
         }
         default: {
+          const menuId = event.payload as string;
+          if (menuId.startsWith(NEW_TAB_PROFILE_PREFIX)) {
+            // the items are the profiles by index, renamed on reload
+            const index = Number(menuId.slice(NEW_TAB_PROFILE_PREFIX.length));
+            const profile = appState.settings$.value?.profiles[index];
+            if (profile) {
+              appState.sessionManager.newTab(undefined, profile.name);
+            }
+          }
         }
       }
     }).then((fn) => unlisten.push(fn));
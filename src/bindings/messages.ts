@@ -0,0 +1,427 @@
+/* eslint-disable */
+// Generated by scripts/bindings.ts from src-tauri, don't edit.
+// Run `pnpm bindings` after changing the Rust types.
+
+import type { Settings, ExitAction } from "./settings";
+
+export interface BatchTestFilesReq {
+  currentDir: string;
+  files: string[];
+}
+
+export interface BatchTestFilesResp {
+  files: number[];
+}
+
+export interface CanCloseResponse {
+  canClose: boolean;
+  /**
+   * e.g. "vim and cargo are still running", to confirm with.
+   */
+  message?: string | null;
+  /**
+   * The processes running in the foreground of the terminals, by name.
+   */
+  processes: RunningProcess[];
+}
+
+export type CellColor =
+  | {
+      index: number;
+      type: "indexed";
+    }
+  | {
+      b: number;
+      g: number;
+      r: number;
+      type: "rgb";
+    };
+
+export interface CommandFinishedMessage {
+  durationMs?: number | null;
+  exitCode?: number | null;
+  id: string;
+}
+
+export interface CwdChangedMessage {
+  cwd: string;
+  id: string;
+}
+
+export interface FileItem {
+  filename: string;
+  isDir: boolean;
+  path: string;
+}
+
+export interface ForegroundChangedMessage {
+  id: string;
+  process: ForegroundProcess;
+}
+
+/**
+ * The leader of the foreground process group of a terminal.
+ */
+export interface ForegroundProcess {
+  argv: string[];
+  cwd?: string | null;
+  /**
+   * The shell itself is in the foreground, i.e. the terminal is idle.
+   */
+  isShell: boolean;
+  name: string;
+  pgid: number;
+}
+
+export interface FsChangedMessage {
+  id: string;
+  paths: string[];
+}
+
+export interface FsLsResponse {
+  content: FileItem[];
+}
+
+export interface FsStatResponse {
+  accessedTime: number;
+  createdTime: number;
+  modifiedTime: number;
+}
+
+export interface InitMessage {
+  forceOnboarding: boolean;
+  homeDir: string;
+  /**
+   * The tabs left open when the app quit last time.
+   */
+  restoredSessions: SavedSession[];
+  settings: Settings;
+  /**
+   * Set if `settings` are the defaults because `settings.toml` is invalid.
+   */
+  settingsError?: SettingsError | null;
+  /**
+   * The theme files which are skipped.
+   */
+  themeErrors: ThemeError[];
+  /**
+   * The values saved with `ui_store`, by key.
+   */
+  uiStores: { [key: string]: unknown };
+}
+
+/**
+ * A TCP port listened on by a process of a terminal.
+ */
+export interface ListeningPort {
+  /**
+   * e.g. "127.0.0.1", "::" or "*"
+   */
+  address: string;
+  pid: number;
+  port: number;
+  processName: string;
+}
+
+export interface MenuItem {
+  key: string;
+  title: string;
+}
+
+export interface NewTerminalResponse {
+  theme?: string | null;
+  title?: string | null;
+}
+
+export interface OpenContextMenuClickedMessage {
+  id: string;
+  key: string;
+}
+
+export interface OpenContextMenuReq {
+  id: string;
+  items: MenuItem[];
+  position: number[];
+}
+
+export interface OutputMetrics {
+  /**
+   * How many times the reader resumed without an ack.
+   */
+  ackTimeouts: number;
+  batches: number;
+  /**
+   * The output which couldn't be sent to the frontend.
+   */
+  droppedBytes: number;
+  receivedBytes: number;
+  sentBytes: number;
+  /**
+   * How many times the reader paused because the frontend was behind.
+   */
+  throttledCount: number;
+  throttledMs: number;
+}
+
+export interface PlaybackFinishedMessage {
+  id: string;
+}
+
+export interface PlaybackInfo {
+  cols: number;
+  /**
+   * The length of the recording in seconds.
+   */
+  duration: number;
+  rows: number;
+}
+
+export interface PlaybackResizedMessage {
+  cols: number;
+  id: string;
+  rows: number;
+}
+
+export interface PortsChangedMessage {
+  id: string;
+  ports: ListeningPort[];
+}
+
+/**
+ * The settings of a terminal after it has entered or left a project.
+ */
+export interface ProjectSettingsChangedMessage {
+  error?: string | null;
+  id: string;
+  /**
+   * The keys a project file is not allowed to set.
+   */
+  ignoredKeys: string[];
+  /**
+   * The project file applied, none outside of a trusted project.
+   */
+  path?: string | null;
+  settings: Settings;
+}
+
+/**
+ * A project file is seen for the first time, or has changed since it's trusted.
+ */
+export interface ProjectTrustRequestMessage {
+  content: string;
+  id: string;
+  path: string;
+}
+
+/**
+ * How the PTY output is transported in `PtyResponse.data`.
+ */
+export type PtyEncoding = "utf8" | "base64";
+
+export interface PtyExitMessage {
+  action: ExitAction;
+  /**
+   * How long the shell has been running.
+   */
+  durationMs: number;
+  exitCode: number;
+  id: string;
+  /**
+   * e.g. "Segmentation fault", when the shell is killed by a signal.
+   */
+  signal?: string | null;
+}
+
+export interface PtyResponse {
+  data: string;
+  encoding: PtyEncoding;
+  id: string;
+  /**
+   * Acked with `ack_pty_output` when the data is written.
+   */
+  offset: number;
+}
+
+export interface ResourceAlert {
+  id: string;
+  kind: ResourceAlertKind;
+  /**
+   * e.g. "above 4.0 GB of memory (4.3 GB)"
+   */
+  message: string;
+  threshold: number;
+  value: number;
+}
+
+export type ResourceAlertKind = "memory" | "cpu";
+
+/**
+ * The resources used by a shell and all its descendants at a time.
+ */
+export interface ResourceSample {
+  childrenCount: number;
+  cpuUsage: number;
+  /**
+   * Resident memory.
+   */
+  memBytes: number;
+  memUsage: number;
+  /**
+   * Read and written since the previous sample.
+   */
+  readBytes: number;
+  /**
+   * Milliseconds since the Unix epoch.
+   */
+  timestamp: number;
+  writtenBytes: number;
+}
+
+export interface RunningProcess {
+  count: number;
+  name: string;
+}
+
+export interface SavedSession {
+  cwd?: string | null;
+  id: string;
+  profile?: string | null;
+  scrollback?: string | null;
+}
+
+export interface ScreenCell {
+  bg?: CellColor | null;
+  bold: boolean;
+  contents: string;
+  /**
+   * `None` for the default color.
+   */
+  fg?: CellColor | null;
+  inverse: boolean;
+  italic: boolean;
+  underline: boolean;
+}
+
+export interface ScreenLine {
+  /**
+   * Only present if styled cells are requested.
+   */
+  cells?: ScreenCell[] | null;
+  text: string;
+}
+
+export interface ScreenResponse {
+  cols: number;
+  cursorCol: number;
+  cursorRow: number;
+  lines: ScreenLine[];
+  rows: number;
+  /**
+   * The index of the first line in `lines`.
+   */
+  start: number;
+  /**
+   * The lines of the scrollback and the visible screen.
+   */
+  totalLines: number;
+}
+
+/**
+ * Why `settings.toml` can't be used, the line and column are 1-based.
+ */
+export interface SettingsError {
+  column?: number | null;
+  line?: number | null;
+  message: string;
+  path: string;
+}
+
+export interface ShellIntegrationStatus {
+  installed: boolean;
+  rcFile: string;
+  scriptPath: string;
+  shell: string;
+  /**
+   * Scripts sourced by the rc file which are not the current one, e.g. left by an older app bundle.
+   */
+  stalePaths: string[];
+}
+
+export interface ShellOptions {
+  args?: string[] | null;
+  env?: { [key: string]: string } | null;
+  shell?: string | null;
+}
+
+export type ShellPhase = "unknown" | "prompt" | "input" | "running";
+
+export interface SpawnResult {
+  code?: number | null;
+  output: string;
+  success: boolean;
+}
+
+export interface StatResult {
+  cpuUsage: number;
+  firstLevelChildrenNames: string[];
+  memUsage: number;
+  totalChildrenCount: number;
+}
+
+export interface StatsUpdatedMessage {
+  samples: TerminalSample[];
+}
+
+export interface TermOptions {
+  path: string;
+  watchDirs: boolean;
+}
+
+export interface TerminalInfo {
+  commandStart?: number | null;
+  cwd?: string | null;
+  /**
+   * Unknown for a session in the daemon.
+   */
+  foreground?: ForegroundProcess | null;
+  id: string;
+  lastExitCode?: number | null;
+  outputEnd?: number | null;
+  outputStart?: number | null;
+  phase: ShellPhase;
+  /**
+   * Offsets of the latest OSC 133 marks in the output stream, on the same basis as `PtyResponse::offset`.
+   */
+  promptStart?: number | null;
+  title?: string | null;
+}
+
+export interface TerminalSample {
+  id: string;
+  sample: ResourceSample;
+}
+
+/**
+ * A theme which can't be loaded, it's skipped.
+ */
+export interface ThemeError {
+  message: string;
+  path: string;
+}
+
+/**
+ * Sent when the themes are reloaded, empty if every theme is valid.
+ */
+export interface ThemeErrorsMessage {
+  errors: ThemeError[];
+}
+
+export interface ThemeResponse {
+  jsonContent?: string | null;
+  name: string;
+}
+
+export interface TitleChangedMessage {
+  id: string;
+  title: string;
+}
@@ -0,0 +1,116 @@
+/* eslint-disable */
+// Generated by scripts/bindings.ts from src-tauri, don't edit.
+// Run `pnpm bindings` after changing the Rust types.
+
+export interface Settings {
+  app: AppSettings;
+  extensions: { [key: string]: unknown } | null;
+  keys: KeysSettings;
+  profiles: ProfileSettings[];
+  statistics: StatisticsSettings;
+  terminal: TerminalSettings;
+  version: SettingsVersion;
+}
+
+export interface AppSettings {
+  "auto-update": AutoUpdate;
+  /**
+   * Run the shells in a background daemon, so they survive the app.
+   */
+  "session-daemon": boolean;
+  /**
+   * The name of the theme, the default one if not set.
+   */
+  theme: string | null;
+}
+
+export type AutoUpdate = boolean;
+
+/**
+ * In seconds, how long the CPU usage has to stay above `cpu-alert`.
+ */
+export type CpuAlertDuration = number;
+
+/**
+ * What happens to the tab when its shell exits.
+ */
+export type ExitAction = "close" | "keep" | "restart";
+
+export type FontSize = number;
+
+export interface KeysSettings {
+  /**
+   * A hotkey to its action, e.g. `"mod+l" = "send-hex-codes:0x0c"`.
+   */
+  bindings: { [key: string]: string } | null;
+}
+
+/**
+ * In milliseconds, how long the processes of a closed terminal have to exit before they get SIGTERM, then SIGKILL.
+ */
+export type KillGracePeriod = number;
+
+/**
+ * A named preset of `[[profiles]]` to open a terminal with.
+ */
+export interface ProfileSettings {
+  args: string[] | null;
+  cwd: string | null;
+  env: { [key: string]: string } | null;
+  name: string;
+  /**
+   * Overrides `terminal.on-exit`.
+   */
+  "on-exit": ExitAction | null;
+  shell: string | null;
+  theme: string | null;
+  title: string | null;
+}
+
+/**
+ * How many samples are kept per terminal.
+ */
+export type SampleHistory = number;
+
+/**
+ * In milliseconds, how often the resources of the terminals are sampled.
+ */
+export type SampleInterval = number;
+
+export type Scrollback = number;
+
+/**
+ * A file without `version` is older than the migrations, i.e. version 0. The current version is only written when the file is saved.
+ */
+export type SettingsVersion = number;
+
+export interface StatisticsSettings {
+  /**
+   * In percent of one core, alert when a terminal uses more CPU.
+   */
+  "cpu-alert": number | null;
+  "cpu-alert-duration": CpuAlertDuration;
+  history: SampleHistory;
+  interval: SampleInterval;
+  /**
+   * In megabytes, alert when a terminal uses more memory.
+   */
+  "memory-alert": number | null;
+}
+
+export interface TerminalSettings {
+  args: string[] | null;
+  /**
+   * The processes which may be running when a terminal is closed without asking.
+   */
+  "confirm-close-allowlist": string[];
+  env: { [key: string]: string } | null;
+  "font-size": FontSize;
+  "kill-grace-period": KillGracePeriod;
+  "on-exit": ExitAction;
+  scrollback: Scrollback;
+  /**
+   * The shell to spawn, `$SHELL` or the passwd entry if not set.
+   */
+  shell: string | null;
+}
@@ -1,7 +1,7 @@
 import { useEffect, useMemo, useState, memo, useContext, useRef } from "react";
 import { escapeShellPath } from "@pkg/utils/shell";
 import { isString } from "lodash-es";
-import { FileItem as FileItemModel, LsFileResponse } from "@pkg/messages";
+import { FileItem as FileItemModel, FsLsResponse } from "@pkg/bindings/messages";
 import AutoSizer, { type Size } from "react-virtualized-auto-sizer";
 import {
   FixedSizeList as List,
@@ -44,7 +44,7 @@ export const FileExplorer = memo(() => {
         return;
       }
 
-      const data: LsFileResponse = await fs.ls(currentDir);
+      const data: FsLsResponse = await fs.ls(currentDir);
 
       const newFiles = data.content.sort((a, b) => {
         const aFtOrder = a.isDir ? 0 : 1;
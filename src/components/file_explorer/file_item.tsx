@@ -1,5 +1,5 @@
 import React, { useCallback, useContext } from "react";
-import { FileItem as FileItemModule } from "@pkg/messages";
+import { FileItem as FileItemModule } from "@pkg/bindings/messages";
 import { MdInsertDriveFile, MdFolder, MdStarRate } from "react-icons/md";
 import { invoke } from "@tauri-apps/api";
 import className from "classnames";
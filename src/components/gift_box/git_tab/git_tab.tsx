@@ -7,7 +7,7 @@ import * as fs from "@pkg/utils/fs";
 import { PrimaryButton } from "@pkg/components/button";
 import { type GitStatusItemIntf, GitStatusItem } from "./git_status_item";
 import classes from "./git_tab.module.css";
-import type { SpawnResult } from "@pkg/messages";
+import type { SpawnResult } from "@pkg/bindings/messages";
 
 interface GitShowProps {
   gitPath: string;
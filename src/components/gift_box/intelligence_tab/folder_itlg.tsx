@@ -1,7 +1,7 @@
 import AppleFolderIcon from "./images/mac-folder-6654.svg";
 import { useEffect, useMemo, useState, memo } from "react";
 import * as fs from "@pkg/utils/fs";
-import type { LsStatResponse } from "@pkg/messages";
+import type { FsStatResponse } from "@pkg/bindings/messages";
 import dayjs from "dayjs";
 import relativeTime from "dayjs/plugin/relativeTime";
 import classes from "./folder_itlg.module.css";
@@ -31,7 +31,7 @@ export interface FolderItlgProps {
 
 export function FolderItlg(props: FolderItlgProps) {
   const { currentDir } = props;
-  const [stat, setStat] = useState<LsStatResponse | undefined>(undefined);
+  const [stat, setStat] = useState<FsStatResponse | undefined>(undefined);
 
   const folderName = useMemo(() => {
     return currentDir.split("/").pop();
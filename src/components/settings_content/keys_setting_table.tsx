@@ -1,4 +1,4 @@
-import type { KeysSettings } from "@pkg/settings";
+import type { KeysSettings } from "@pkg/bindings/settings";
 import classes from "./keys_setting_table.module.css";
 
 export interface KeysSettingsTableProps {
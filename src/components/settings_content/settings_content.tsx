@@ -7,19 +7,48 @@ import { AppContext } from "@pkg/contexts/app_context";
 import classes from "./settings_content.module.css";
 import { useBehaviorSubject } from "@pkg/hooks/observable";
 import KeysSettingsTable from "./keys_setting_table";
-import type { Settings } from "@pkg/settings";
+import type { Settings } from "@pkg/bindings/settings";
+import type { AppState } from "@pkg/models/app_state";
+import { invoke } from "@tauri-apps/api";
+import type { SettingsError } from "@pkg/bindings/messages";
+import { mkMenuId } from "@pkg/utils/id_helper";
+import { openContextMenu } from "@pkg/utils/context_menu";
+
+function formatSettingsError(error: SettingsError): string {
+  const position = error.line ? `:${error.line}:${error.column ?? 1}` : "";
+  return `${error.path}${position}: ${error.message}`;
+}
+
+function ThemeButton(props: { appState: AppState }) {
+  const { appState } = props;
+  const themeName = useBehaviorSubject(appState.themeName$);
+
+  const handleClick = async (e: React.MouseEvent) => {
+    const themes = await appState.listThemes();
+    openContextMenu(
+      {
+        id: mkMenuId(),
+        position: [e.clientX, e.clientY],
+        items: themes.map((name) => ({ key: name, title: name })),
+      },
+      (name) => appState.setTheme(name)
+    );
+  };
+
+  return <RoundButton onClick={handleClick}>{themeName}</RoundButton>;
+}
 
 interface SettingTabs {
   key: string;
   name: string;
-  content: (settings: Settings) => React.ReactNode;
+  content: (settings: Settings, appState: AppState) => React.ReactNode;
 }
 
 const settingTabs: SettingTabs[] = [
   {
     key: "general",
     name: "General",
-    content: (settings: Settings) => (
+    content: (settings: Settings, appState: AppState) => (
       <div className="inner">
         <SettingsGroup
           title="Language"
@@ -29,21 +58,37 @@ const settingTabs: SettingTabs[] = [
         <SettingsGroup
           title="Auto update"
           description="Auto update Terminal One when a new version is available."
-          right={<Toggle checked={settings.app["auto-update"]} />}
+          right={
+            <Toggle
+              checked={settings.app["auto-update"]}
+              onChange={(e) =>
+                appState.updateSetting("app.auto-update", e.target.checked)
+              }
+            />
+          }
           paddingRight={12}
         />
+        <SettingsGroup
+          title="Settings file"
+          description="Edit all the settings in settings.toml."
+          right={
+            <RoundButton onClick={() => invoke("open_settings_file")}>
+              Open
+            </RoundButton>
+          }
+        />
       </div>
     ),
   },
   {
     key: "appearance",
     name: "Appearance",
-    content: (settings: Settings) => (
+    content: (settings: Settings, appState: AppState) => (
       <div className="inner">
         <SettingsGroup
           title="Theme"
           description="Customize how Terminal One looks on your device."
-          right={<RoundButton>Dark</RoundButton>}
+          right={<ThemeButton appState={appState} />}
         />
         <SettingsGroup
           title="Font size"
@@ -81,6 +126,8 @@ export default function SettingsContent() {
   const [selectedKey, setSelectedKey] = useState(settingTabs[0].key);
   const appState = useContext(AppContext)!;
   const settings = useBehaviorSubject(appState.settings$)!;
+  const settingsError = useBehaviorSubject(appState.settingsError$);
+  const themeErrors = useBehaviorSubject(appState.themeErrors$);
 
   const tabContentMap = useMemo(() => {
     const result = new Map<string, SettingTabs>();
@@ -93,6 +140,16 @@ export default function SettingsContent() {
   return (
     <div className={classes.settingsContent}>
       <h1 className="t1-noselect">Settings</h1>
+      {settingsError && (
+        <div className={classes.settingsError}>
+          {formatSettingsError(settingsError)}
+        </div>
+      )}
+      {themeErrors?.map((error) => (
+        <div key={error.path} className={classes.settingsError}>
+          {error.path}: {error.message}
+        </div>
+      ))}
       <div className={classes.mainContent}>
         <div className={classes.settingsNavbar}>
           {settingTabs.map((tab) => (
@@ -108,7 +165,7 @@ export default function SettingsContent() {
           ))}
         </div>
         <div className={classes.navTabContent}>
-          {tabContentMap.get(selectedKey)?.content(settings)}
+          {tabContentMap.get(selectedKey)?.content(settings, appState)}
         </div>
       </div>
     </div>
@@ -1,4 +1,4 @@
-import type { KeysSettings } from "@pkg/settings";
+import type { KeysSettings } from "@pkg/bindings/settings";
 import { noop } from "lodash-es";
 import isHotkey from "is-hotkey";
 
@@ -8,15 +8,25 @@ import { invoke } from "@tauri-apps/api/tauri";
 import { Session } from "@pkg/models/session";
 import { AppTheme } from "@pkg/models/app_theme";
 import { debounce } from "lodash-es";
-import { interval, type Subscription } from "rxjs";
+import { combineLatest, interval, type Subscription } from "rxjs";
 import classNames from "classnames";
 import { UnlistenFn, listen } from "@tauri-apps/api/event";
 import type { AppState } from "@pkg/models/app_state";
-import type { TerminalStatistic } from "@pkg/messages";
+import type { StatResult, PtyExitMessage } from "@pkg/bindings/messages";
 import HotKeysHandler, { TerminalProxy } from "./hotkeys_handler";
 import "./terminal_wrapper.css";
 import "xterm.es/css/xterm.css";
 
+function exitBanner(payload: PtyExitMessage): string {
+  const { exitCode, signal, durationMs, action } = payload;
+  const reason = signal
+    ? `killed by ${signal}`
+    : `exited with code ${exitCode}`;
+  const duration = (durationMs / 1000).toFixed(1);
+  const next = action === "restart" ? ", restarting" : "";
+  return `\r\n\x1b[7m Process ${reason} after ${duration}s${next} \x1b[0m\r\n`;
+}
+
 export interface TerminalWrapperProps {
   appState: AppState;
   session: Session;
@@ -66,12 +76,16 @@ export class TerminalWrapper
   }
 
   override componentDidUpdate(prevProps: Readonly<TerminalWrapperProps>): void {
+    // a recording keeps the size it's recorded in
+    const fit = !this.props.session.isPlayback;
     if (!prevProps.active && this.props.active) {
       this.terminal?.focus();
-      this.fitAddon?.fit();
+      if (fit) {
+        this.fitAddon?.fit();
+      }
     }
 
-    if (prevProps.width !== this.props.width || prevProps.height !== this.props.height) {
+    if (fit && (prevProps.width !== this.props.width || prevProps.height !== this.props.height)) {
       this.fitAddon?.fit();
     }
   }
@@ -104,12 +118,25 @@ export class TerminalWrapper
   async initTerminal() {
     const { session } = this.props;
     const { id } = session;
-    await invoke("new_terminal", {
-      id,
-      path: session.initPath,
-    });
+    let playbackSize: { cols: number; rows: number } | undefined;
+    if (session.isPlayback) {
+      playbackSize = await invoke("start_playback", {
+        id,
+        path: session.playback,
+      });
+    } else {
+      await invoke("new_terminal", {
+        id,
+        path: session.initPath,
+        profile: session.profile,
+        restore: session.restore,
+      });
+    }
     const initOptions = this.generateTermOptions();
-    const terminal = new Terminal(initOptions);
+    const terminal = new Terminal({
+      ...initOptions,
+      disableStdin: session.isPlayback,
+    });
     terminal.attachCustomKeyEventHandler(this.#customKeyEventHandler);
     this.terminal = terminal;
     const fitAddon = new FitAddon();
@@ -127,6 +154,14 @@ export class TerminalWrapper
     );
     terminal.open(this.containerRef.current!);
 
+    if (playbackSize) {
+      // replay in the size it's recorded in
+      terminal.resize(playbackSize.cols, playbackSize.rows);
+      this.#initPlayback(terminal);
+      session.uiReady$.next(true);
+      return;
+    }
+
     window.requestAnimationFrame(() => {
       this.fitAddon?.fit();
     });
@@ -171,6 +206,31 @@ export class TerminalWrapper
       session.termFocus$.subscribe(() => this.delayFocus())
     );
 
+    // settings.toml is reloaded, or the shell enters or leaves a project
+    this.#subscriptions.push(
+      combineLatest([
+        this.props.appState.settings$,
+        session.projectSettings$,
+      ]).subscribe(([settings, projectSettings]) => {
+        const effective = projectSettings ?? settings;
+        if (!effective) {
+          return;
+        }
+        const { terminal: terminalSettings } = effective;
+        if (terminal.options.fontSize !== terminalSettings["font-size"]) {
+          terminal.options.fontSize = terminalSettings["font-size"];
+          this.fitAddon?.fit();
+        }
+        terminal.options.scrollback = terminalSettings.scrollback;
+      })
+    );
+
+    this.#subscriptions.push(
+      session.exited$.subscribe((payload) => {
+        terminal.write(exitBanner(payload));
+      })
+    );
+
     this.#subscriptions.push(
       session.searchNext$.subscribe((content) => {
         searchAddon.findNext(content);
@@ -193,11 +253,51 @@ export class TerminalWrapper
     session.uiReady$.next(true);
   }
 
+  #initPlayback(terminal: Terminal) {
+    const { session } = this.props;
+
+    // space pauses, +/- change the speed
+    terminal.onKey(({ key }) => {
+      switch (key) {
+        case " ":
+          session.togglePlaybackPaused();
+          break;
+        case "+":
+        case "=":
+          session.changePlaybackSpeed(true);
+          break;
+        case "-":
+          session.changePlaybackSpeed(false);
+          break;
+      }
+    });
+
+    terminal.onTitleChange((title) => session.setTitle(title));
+
+    this.#subscriptions.push(
+      session.ptyOutput$.subscribe((data: string) => {
+        terminal.write(data);
+      })
+    );
+
+    this.#subscriptions.push(
+      session.playbackResize$.subscribe(({ cols, rows }) => {
+        terminal.resize(cols, rows);
+      })
+    );
+
+    this.#subscriptions.push(
+      session.termFocus$.subscribe(() => this.delayFocus())
+    );
+
+    this.delayFocus();
+  }
+
   #initMonitor() {
     const event$ = interval(2000);
     const { session } = this.props;
     const s = event$.subscribe(async () => {
-      const statistic: TerminalStatistic = await invoke(
+      const statistic: StatResult = await invoke(
         "get_terminal_statistics",
         {
           id: session.id,
@@ -243,8 +343,13 @@ export class TerminalWrapper
   }
 
   async removeTerminal() {
-    const termId = this.props.session.id;
-    await invoke("remove_terminal", { id: termId });
+    const { session } = this.props;
+    const termId = session.id;
+    if (session.isPlayback) {
+      await invoke("stop_playback", { id: termId });
+    } else {
+      await invoke("remove_terminal", { id: termId });
+    }
     console.log("terminal removed:", termId);
     this.terminal = undefined;
   }
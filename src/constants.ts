@@ -2,13 +2,32 @@ export enum PushMessages {
   PTY_OUTPUT = "pty-output",
   PTY_EXIT = "pty-exit",
   FS_CHANGED = "fs-changed",
+  CWD_CHANGED = "cwd-changed",
+  TITLE_CHANGED = "title-changed",
+  FOREGROUND_CHANGED = "foreground-changed",
+  SETTINGS_CHANGED = "settings-changed",
+  SETTINGS_ERROR = "settings-error",
+  THEME_CHANGED = "theme-changed",
+  THEME_ERRORS = "theme-errors",
+  PROJECT_SETTINGS_CHANGED = "project-settings-changed",
+  PROJECT_TRUST_REQUESTED = "project-trust-requested",
+  COMMAND_FINISHED = "command-finished",
+  PLAYBACK_RESIZED = "playback-resized",
+  PLAYBACK_FINISHED = "playback-finished",
+  STATS_UPDATED = "stats-updated",
+  RESOURCE_ALERT = "resource-alert",
+  PORTS_CHANGED = "ports-changed",
   UPDATE_AVAILABLE = "update-available",
   CONTEXT_MENU_CLICKED = "context-menu-clicked",
 }
 
+export const NEW_TAB_PROFILE_PREFIX = "new-tab-profile:";
+
 export interface PtyResponse {
   id: string;
   data: string;
+  encoding: "utf8" | "base64";
+  offset: number;
 }
 
 export enum StoreKeys {
@@ -19,6 +38,63 @@ export enum StoreKeys {
   collectDiagnosticData = "collectDiagnosticData",
 }
 
+export interface PlaybackResizedEvent {
+  id: string;
+  cols: number;
+  rows: number;
+}
+
+export interface PlaybackFinishedEvent {
+  id: string;
+}
+
+export interface ResourceSample {
+  timestamp: number;
+  cpuUsage: number;
+  memBytes: number;
+  memUsage: number;
+  childrenCount: number;
+  readBytes: number;
+  writtenBytes: number;
+}
+
+export interface StatsUpdatedEvent {
+  samples: { id: string; sample: ResourceSample }[];
+}
+
+export interface ResourceAlertEvent {
+  id: string;
+  kind: "memory" | "cpu";
+  value: number;
+  threshold: number;
+  message: string;
+}
+
+export interface ForegroundProcess {
+  pgid: number;
+  name: string;
+  argv: string[];
+  cwd?: string;
+  isShell: boolean;
+}
+
+export interface ForegroundChangedEvent {
+  id: string;
+  process: ForegroundProcess;
+}
+
+export interface ListeningPort {
+  port: number;
+  address: string;
+  pid: number;
+  processName: string;
+}
+
+export interface PortsChangedEvent {
+  id: string;
+  ports: ListeningPort[];
+}
+
 export interface FsChangedEvent {
   id: string;
   paths: string[];
@@ -1,6 +1,6 @@
 import { ExtensionConfig } from "@pkg/models/extension";
 import { invoke } from "@tauri-apps/api";
-import type { SpawnResult } from "@pkg/messages";
+import type { SpawnResult } from "@pkg/bindings/messages";
 import { isString } from "lodash-es";
 
 const gitExt: ExtensionConfig = {
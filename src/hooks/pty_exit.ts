@@ -1,18 +1,15 @@
 import { useEffect } from "react";
 import { listen, UnlistenFn } from "@tauri-apps/api/event";
 import { PushMessages } from "@pkg/constants";
+import type { PtyExitMessage } from "@pkg/bindings/messages";
 
-interface PtyExitPayload {
-  id: string;
-}
-
-export function usePtyExit(handler: (id: string) => void) {
+export function usePtyExit(handler: (payload: PtyExitMessage) => void) {
 
   useEffect(() => {
     let unlisten: UnlistenFn | undefined;
     listen(PushMessages.PTY_EXIT, (event) => {
-      const payload = event.payload as PtyExitPayload;
-      handler(payload.id);
+      const payload = event.payload as PtyExitMessage;
+      handler(payload);
     }).then((fn: UnlistenFn) => {
       unlisten = fn;
     });
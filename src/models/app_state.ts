@@ -1,15 +1,26 @@
 import { SessionManager } from "./session_manager";
 import ExtensionManager from "./extension_manager";
 import { BehaviorSubject, Subject, Subscription, map, take } from "rxjs";
-import { FileItem as FileItemModel, InitMessage } from "@pkg/messages";
-import type { Settings } from "@pkg/settings";
+import {
+  FileItem as FileItemModel,
+  InitMessage,
+  SavedSession,
+  SettingsError,
+  ThemeError,
+  ThemeErrorsMessage,
+} from "@pkg/bindings/messages";
+import type { Settings } from "@pkg/bindings/settings";
 import { List as ImmutableList } from "immutable";
 import { invoke } from "@tauri-apps/api";
 import { isBoolean, isString, once } from "lodash-es";
 import { objectToCamlCaseDeep } from "@pkg/utils/objects";
 import * as uiStore from "@pkg/utils/ui_store";
-import type { ThemeResponse } from "@pkg/messages";
-import { StoreKeys, UpdateAvailableEvent } from "@pkg/constants";
+import type { ThemeResponse } from "@pkg/bindings/messages";
+import {
+  PushMessages,
+  StoreKeys,
+  UpdateAvailableEvent,
+} from "@pkg/constants";
 import { type AppTheme } from "./app_theme";
 import extensions from "@pkg/extensions";
 import { listen } from "@tauri-apps/api/event";
@@ -56,13 +67,21 @@ export class AppState {
   modalClosed$ = new Subject<void>();
 
   theme$ = new BehaviorSubject<AppTheme | undefined>(undefined);
+  themeName$ = new BehaviorSubject<string | undefined>(undefined);
+  // the theme files which are skipped
+  themeErrors$ = new BehaviorSubject<ThemeError[]>([]);
 
   updateInfo$ = new BehaviorSubject<UpdateAvailableEvent | undefined>(
     undefined
   );
   updateStatus$ = new BehaviorSubject<UpdateStatus | undefined>(undefined);
 
+  // the tabs open when the app quit, offered once on launch
+  restoredSessions: SavedSession[] = [];
+
   settings$ = new BehaviorSubject<Settings | undefined>(undefined);
+  // why settings.toml isn't applied, the previous settings are kept
+  settingsError$ = new BehaviorSubject<SettingsError | undefined>(undefined);
 
   constructor() {
     let lastSubscription: Subscription | undefined;
@@ -86,6 +105,8 @@ export class AppState {
       this.#fetchInitData(),
       this.#listenUpdateInfo(),
       this.#listenFileDrop(),
+      this.#listenSettings(),
+      this.#listenTheme(),
     ]);
   });
 
@@ -124,6 +145,18 @@ export class AppState {
     });
   }
 
+  async #listenSettings() {
+    await listen(PushMessages.SETTINGS_CHANGED, (event) => {
+      this.settings$.next(event.payload as Settings);
+      this.settingsError$.next(undefined);
+    });
+    await listen(PushMessages.SETTINGS_ERROR, (event) => {
+      const error = event.payload as SettingsError;
+      console.error("settings error:", error);
+      this.settingsError$.next(error);
+    });
+  }
+
   async #listenFileDrop() {
     await listen("tauri://file-drop", (event) => {
       const payload = event.payload as string[];
@@ -142,28 +175,58 @@ export class AppState {
   }
 
   async #fetchTheme() {
-    const themeResp: ThemeResponse = await invoke("get_a_theme");
+    const themeResp: ThemeResponse = await invoke("current_theme");
+    this.#applyTheme(themeResp);
+  }
+
+  async #listenTheme() {
+    await listen(PushMessages.THEME_CHANGED, (event) => {
+      this.#applyTheme(event.payload as ThemeResponse);
+    });
+    await listen(PushMessages.THEME_ERRORS, (event) => {
+      const { errors } = event.payload as ThemeErrorsMessage;
+      errors.forEach((error) => console.error("theme error:", error));
+      this.themeErrors$.next(errors);
+    });
+  }
+
+  #applyTheme(themeResp: ThemeResponse) {
     if (isString(themeResp.jsonContent)) {
       let themeContent = JSON.parse(themeResp.jsonContent) as AppTheme;
       themeContent = objectToCamlCaseDeep(themeContent);
-      console.log("update theme:", themeContent);
+      console.log("update theme:", themeResp.name, themeContent);
       this.theme$.next(themeContent);
+      this.themeName$.next(themeResp.name);
     }
   }
 
+  listThemes(): Promise<string[]> {
+    return invoke("list_themes");
+  }
+
+  // saved as `app.theme`, every window gets `theme-changed`
+  async setTheme(name: string) {
+    await invoke("set_theme", { name });
+  }
+
   async #fetchInitData() {
     const initData: InitMessage = await invoke("fetch_init_data");
     console.log("initData:", initData);
     const { homeDir, uiStores, settings } = initData;
     this.homeDir$.next(homeDir);
     this.settings$.next(settings);
+    this.settingsError$.next(initData.settingsError ?? undefined);
+    this.themeErrors$.next(initData.themeErrors);
+    this.restoredSessions = initData.restoredSessions;
 
-    if (isBoolean(uiStores[StoreKeys.showFileExplorer])) {
-      this.showFileExplorer$.next(uiStores[StoreKeys.showFileExplorer]);
+    const showFileExplorer = uiStores[StoreKeys.showFileExplorer];
+    if (isBoolean(showFileExplorer)) {
+      this.showFileExplorer$.next(showFileExplorer);
     }
 
-    if (isBoolean(uiStores[StoreKeys.showGiftBox])) {
-      this.showGiftBox$.next(uiStores[StoreKeys.showGiftBox]);
+    const showGiftBox = uiStores[StoreKeys.showGiftBox];
+    if (isBoolean(showGiftBox)) {
+      this.showGiftBox$.next(showGiftBox);
     }
 
     if (uiStores[StoreKeys.onboarding] === 2 && !initData.forceOnboarding) {
@@ -190,6 +253,17 @@ export class AppState {
     this.favoriteDirsPath$.next(ImmutableList(paths));
   };
 
+  // `path` is dotted, e.g. "terminal.font-size"
+  async updateSetting(path: string, value: unknown) {
+    const settings: Settings = await invoke("update_setting", { path, value });
+    this.settings$.next(settings);
+  }
+
+  async resetSetting(path: string) {
+    const settings: Settings = await invoke("reset_setting", { path });
+    this.settings$.next(settings);
+  }
+
   toggleShowSettings() {
     const next = !this.showSettings$.value;
     this.showSettings$.next(next);
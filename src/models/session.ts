@@ -4,12 +4,15 @@ import type { AppState } from "./app_state";
 import { invoke } from "@tauri-apps/api";
 import { isUndefined, isString } from "lodash-es";
 import { ToolbarButtonExtPayload } from "./extension";
-import { TerminalStatistic } from "@pkg/messages";
+import { StatResult, PtyExitMessage } from "@pkg/bindings/messages";
+import type { ForegroundProcess, ListeningPort } from "@pkg/constants";
+import type { Settings } from "@pkg/bindings/settings";
 import { List as ImmutableList } from "immutable";
 import { dynamicDebounce } from "@pkg/utils/dynamic_debounce";
 
 const FILE_PATTERN = /file:\/\/([^\/]+)(.+)/;
 const MAX_STATISTICS = 100;
+const PLAYBACK_SPEEDS = [0.25, 0.5, 1, 2, 4, 8];
 
 export class Session {
   id: string;
@@ -18,9 +21,16 @@ export class Session {
   toolbarButtons$ = new BehaviorSubject<ToolbarButtonExtPayload[]>([]);
   showSearchBox$ = new BehaviorSubject<boolean>(false);
   uiReady$ = new BehaviorSubject<boolean>(false);
-  statistics$: BehaviorSubject<ImmutableList<TerminalStatistic>> =
+  statistics$: BehaviorSubject<ImmutableList<StatResult>> =
     new BehaviorSubject(ImmutableList());
   activeToolbarButtonIndex$ = new BehaviorSubject<number>(-1);
+  recordingPath$ = new BehaviorSubject<string | undefined>(undefined);
+  playbackPaused$ = new BehaviorSubject<boolean>(false);
+  playbackSpeed$ = new BehaviorSubject<number>(1);
+  ports$ = new BehaviorSubject<ListeningPort[]>([]);
+  foreground$ = new BehaviorSubject<ForegroundProcess | undefined>(undefined);
+  // the settings with the project file of the cwd, if any
+  projectSettings$ = new BehaviorSubject<Settings | undefined>(undefined);
 
   shellInput$ = new Subject<string>();
   ptyOutput$ = new Subject<string>();
@@ -28,15 +38,25 @@ export class Session {
   termFocus$ = new Subject<void>();
   searchBoxFocus$ = new Subject<void>();
   searchNext$ = new Subject<string>();
+  playbackResize$ = new Subject<{ cols: number; rows: number }>();
+  exited$ = new Subject<PtyExitMessage>();
 
   generateActionsDuration = 0;
 
-  constructor(public appState: AppState, public initPath?: string) {
+  constructor(
+    public appState: AppState,
+    public initPath?: string,
+    public profile?: string,
+    public playback?: string,
+    // the saved session reopened in this tab, see `new_terminal`
+    public restore?: string
+  ) {
     this.id = mkTabId();
 
     this.cwd$.pipe(skip(1)).subscribe((path) => {
       this.generateActions();
-      if (isUndefined(path)) {
+      // a recording has no terminal to watch
+      if (isUndefined(path) || this.isPlayback) {
         return;
       }
       invoke("terminal_set_options", {
@@ -67,6 +87,40 @@ export class Session {
     this.toolbarButtons$.next(next);
   });
 
+  get isPlayback(): boolean {
+    return !isUndefined(this.playback);
+  }
+
+  async toggleRecording() {
+    if (this.isPlayback) {
+      return;
+    }
+    if (isUndefined(this.recordingPath$.value)) {
+      const path: string = await invoke("start_recording", { id: this.id });
+      this.recordingPath$.next(path);
+    } else {
+      await invoke("stop_recording", { id: this.id });
+      this.recordingPath$.next(undefined);
+    }
+  }
+
+  async togglePlaybackPaused() {
+    const paused = !this.playbackPaused$.value;
+    await invoke("set_playback_paused", { id: this.id, paused });
+    this.playbackPaused$.next(paused);
+  }
+
+  async changePlaybackSpeed(faster: boolean) {
+    const index = PLAYBACK_SPEEDS.indexOf(this.playbackSpeed$.value);
+    const nextIndex = Math.min(
+      Math.max(index + (faster ? 1 : -1), 0),
+      PLAYBACK_SPEEDS.length - 1
+    );
+    const speed = PLAYBACK_SPEEDS[nextIndex];
+    await invoke("set_playback_speed", { id: this.id, speed });
+    this.playbackSpeed$.next(speed);
+  }
+
   resetActiveToolbarButtonIndex() {
     if (this.activeToolbarButtonIndex$.value < 0) {
       return;
@@ -74,7 +128,7 @@ export class Session {
     this.activeToolbarButtonIndex$.next(-1);
   }
 
-  pushStatistic(statistic: TerminalStatistic) {
+  pushStatistic(statistic: StatResult) {
     if (!statistic && this.statistics$.value.count() === 0) {
       return;
     }
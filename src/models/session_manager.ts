@@ -7,25 +7,49 @@ import {
 } from "rxjs";
 import { Session } from "./session";
 import { listen } from "@tauri-apps/api/event";
-import { PushMessages, type PtyResponse, FsChangedEvent } from "@pkg/constants";
+import { invoke } from "@tauri-apps/api";
+import { confirm } from "@tauri-apps/api/dialog";
+import {
+  PushMessages,
+  type PtyResponse,
+  FsChangedEvent,
+  PlaybackResizedEvent,
+  PlaybackFinishedEvent,
+  PortsChangedEvent,
+  ForegroundChangedEvent,
+} from "@pkg/constants";
+import type {
+  CanCloseResponse,
+  ProjectSettingsChangedMessage,
+  ProjectTrustRequestMessage,
+  SavedSession,
+} from "@pkg/bindings/messages";
 import type { AppState } from "./app_state";
 
 export class SessionManager {
   sessionsMap = new Map<string, Session>();
   sessions$ = new BehaviorSubject<Session[]>([]);
   activeSessionIndex$ = new BehaviorSubject<number>(-1);
+  // the project files being asked about, asked once for all their tabs
+  #pendingTrust = new Set<string>();
 
   constructor(public appState: AppState) {
     this.#listenPtyOutput();
     this.#listenFsChanged();
+    this.#listenPlayback();
+    this.#listenPortsChanged();
+    this.#listenForegroundChanged();
+    this.#listenProjectSettings();
   }
 
   async #listenPtyOutput() {
     await listen(PushMessages.PTY_OUTPUT, (event) => {
       const resp = event.payload as PtyResponse;
       const session = this.sessionsMap.get(resp.id);
-      const { data } = resp;
+      const { data, offset } = resp;
       session?.ptyOutput$.next(data);
+      // let the backend keep reading
+      invoke("ack_pty_output", { id: resp.id, offset });
     });
   }
 
@@ -37,8 +61,125 @@ export class SessionManager {
     });
   }
 
-  newTab(initPath?: string): Session {
-    const session = new Session(this.appState, initPath);
+  async #listenPortsChanged() {
+    await listen(PushMessages.PORTS_CHANGED, (event) => {
+      const resp = event.payload as PortsChangedEvent;
+      const session = this.sessionsMap.get(resp.id);
+      session?.ports$.next(resp.ports);
+    });
+  }
+
+  async #listenForegroundChanged() {
+    await listen(PushMessages.FOREGROUND_CHANGED, (event) => {
+      const resp = event.payload as ForegroundChangedEvent;
+      const session = this.sessionsMap.get(resp.id);
+      session?.foreground$.next(resp.process);
+    });
+  }
+
+  async #listenProjectSettings() {
+    await listen(PushMessages.PROJECT_SETTINGS_CHANGED, (event) => {
+      const resp = event.payload as ProjectSettingsChangedMessage;
+      const session = this.sessionsMap.get(resp.id);
+      if (resp.error) {
+        console.error("project settings error:", resp.error);
+      }
+      session?.projectSettings$.next(resp.path ? resp.settings : undefined);
+    });
+    await listen(PushMessages.PROJECT_TRUST_REQUESTED, async (event) => {
+      const resp = event.payload as ProjectTrustRequestMessage;
+      if (this.#pendingTrust.has(resp.path)) {
+        return;
+      }
+      this.#pendingTrust.add(resp.path);
+      try {
+        const trusted = await confirm(
+          `${resp.path} wants to change the settings of this terminal:\n\n${resp.content}`,
+          { title: "Trust project settings?", type: "warning" }
+        );
+        await invoke("trust_project_settings", {
+          path: resp.path,
+          content: resp.content,
+          trusted,
+        });
+      } finally {
+        this.#pendingTrust.delete(resp.path);
+      }
+    });
+  }
+
+  async #listenPlayback() {
+    await listen(PushMessages.PLAYBACK_RESIZED, (event) => {
+      const resp = event.payload as PlaybackResizedEvent;
+      const session = this.sessionsMap.get(resp.id);
+      session?.playbackResize$.next({ cols: resp.cols, rows: resp.rows });
+    });
+    await listen(PushMessages.PLAYBACK_FINISHED, (event) => {
+      const resp = event.payload as PlaybackFinishedEvent;
+      const session = this.sessionsMap.get(resp.id);
+      session?.playbackPaused$.next(true);
+    });
+  }
+
+  // A read-only tab replaying the recording at `path`.
+  newPlaybackTab(path: string): Session {
+    const session = new Session(this.appState, undefined, undefined, path);
+    this.sessionsMap.set(session.id, session);
+
+    const len = this.sessions$.value.length;
+    this.sessions$.next([...this.sessions$.value, session]);
+
+    this.activeSessionIndex$.next(len);
+
+    return session;
+  }
+
+  toggleRecording() {
+    this.activeSession$.pipe(take(1)).subscribe((session) => {
+      session?.toggleRecording();
+    });
+  }
+
+  // The tabs of the last session if the user wants them back, a new tab otherwise.
+  async openInitialTabs() {
+    const saved = this.appState.restoredSessions;
+    if (saved.length > 0) {
+      if (await this.#confirmRestore(saved)) {
+        saved.forEach((session) =>
+          this.newTab(
+            session.cwd ?? undefined,
+            session.profile ?? undefined,
+            session.id
+          )
+        );
+        return;
+      }
+      // the daemon shells of the declined sessions are killed
+      await invoke("discard_sessions");
+    }
+    this.newTab();
+  }
+
+  #confirmRestore(saved: SavedSession[]): Promise<boolean> {
+    const dirs = saved
+      .map((session) =>
+        session.cwd ? this.appState.prettyPath(session.cwd) : "~"
+      )
+      .join("\n");
+    return confirm(`Reopen the tabs of the last session?\n\n${dirs}`, {
+      title: "Restore Session",
+      type: "info",
+    });
+  }
+
+  newTab(initPath?: string, profile?: string, restore?: string): Session {
+    const session = new Session(
+      this.appState,
+      initPath,
+      profile,
+      undefined,
+      restore
+    );
     this.sessionsMap.set(session.id, session);
 
     const len = this.sessions$.value.length;
@@ -49,6 +190,26 @@ export class SessionManager {
     return session;
   }
 
+  // Ask before closing a tab which is running something.
+  async confirmCloseTab() {
+    const activeSession = this.sessions$.value[this.activeSessionIndex$.value];
+    if (activeSession && !activeSession.isPlayback) {
+      const resp: CanCloseResponse = await invoke("can_close", {
+        id: activeSession.id,
+      });
+      if (!resp.canClose) {
+        const confirmed = await confirm(
+          `${resp.message}. Do you want to close the tab anyway?`,
+          { title: "Close Tab", type: "warning" }
+        );
+        if (!confirmed) {
+          return;
+        }
+      }
+    }
+    this.closeTab();
+  }
+
   closeTab() {
     const activeSessionIndex = this.activeSessionIndex$.value;
     if (activeSessionIndex >= 0) {
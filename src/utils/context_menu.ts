@@ -1,5 +1,5 @@
 import { PushMessages } from "@pkg/constants";
-import { OpenContextMenuClickedMessage } from "@pkg/messages";
+import { OpenContextMenuClickedMessage } from "@pkg/bindings/messages";
 import { invoke } from "@tauri-apps/api";
 import { UnlistenFn, listen } from "@tauri-apps/api/event";
 
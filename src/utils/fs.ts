@@ -1,10 +1,10 @@
-import type { LsFileResponse, LsStatResponse } from "@pkg/messages";
+import type { FsLsResponse, FsStatResponse } from "@pkg/bindings/messages";
 import { invoke } from "@tauri-apps/api";
 
-export async function ls(path: string): Promise<LsFileResponse> {
+export async function ls(path: string): Promise<FsLsResponse> {
   const resp = await invoke("fs_ls", {
     path,
-  }) as LsFileResponse;
+  }) as FsLsResponse;
   return resp;
 }
 
@@ -15,10 +15,10 @@ export async function readAll(path: string): Promise<string> {
   return resp;
 }
 
-export async function stat(path: string): Promise<LsStatResponse> {
+export async function stat(path: string): Promise<FsStatResponse> {
   const resp = await invoke("fs_stat", {
     path,
-  }) as LsStatResponse;
+  }) as FsStatResponse;
   return resp;
 }
 